@@ -0,0 +1,73 @@
+use log::warn;
+
+use crate::Error;
+
+/// Command line arguments e.g. `photo-farm IMG_0771.JPG --originals ../full-res --export-to /mnt/backup/picks`
+#[derive(Debug)]
+pub struct Args {
+    /// the image to open first (the rest of its folder is loaded too) or a folder to open,
+    /// none to choose a folder when the program starts
+    pub file_name: Option<String>,
+    /// folder of full resolution originals when the folder being viewed contains small proofs
+    pub originals: Option<String>,
+    /// jpeg quality of exported photos from 1 to 100
+    pub export_quality: Option<u8>,
+    /// longest edge in pixels of exported photos
+    pub export_max_dimension: Option<u32>,
+    /// folder to export to instead of an `export` folder next to the photos
+    pub export_to: Option<String>,
+    /// resize every image in the cache again before the viewer starts
+    pub rebuild_cache: bool,
+    /// compact the database file before the viewer starts
+    pub vacuum_cache: bool,
+}
+
+impl Args {
+    /// parse the arguments passed to the program, the first one being the program itself
+    pub fn parse(args: &[String]) -> Result<Self, Error> {
+        let mut file_name = None;
+        let mut originals = None;
+        let mut export_quality = None;
+        let mut export_max_dimension = None;
+        let mut export_to = None;
+        let mut rebuild_cache = false;
+        let mut vacuum_cache = false;
+
+        let mut args = args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--originals" => originals = Some(args.next().ok_or(Error::InvalidArgs)?.clone()),
+                "--export-quality" => {
+                    let quality = parse_number(args.next())?;
+                    if quality > 100 {
+                        return Err(Error::InvalidArgs);
+                    }
+                    export_quality = Some(quality as u8);
+                }
+                "--export-max-size" => export_max_dimension = Some(parse_number(args.next())?),
+                "--export-to" => export_to = Some(args.next().ok_or(Error::InvalidArgs)?.clone()),
+                "--rebuild-cache" => rebuild_cache = true,
+                "--vacuum-cache" => vacuum_cache = true,
+                _ if file_name.is_none() => file_name = Some(arg.clone()),
+                _ => warn!("Ignoring extra argument: {arg}"),
+            }
+        }
+
+        Ok(Self {
+            file_name,
+            originals,
+            export_quality,
+            export_max_dimension,
+            export_to,
+            rebuild_cache,
+            vacuum_cache,
+        })
+    }
+}
+
+/// a whole number greater than zero
+fn parse_number(arg: Option<&String>) -> Result<u32, Error> {
+    arg.and_then(|x| x.parse().ok())
+        .filter(|x| *x > 0)
+        .ok_or(Error::InvalidArgs)
+}
@@ -0,0 +1,374 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use image::imageops::FilterType;
+use log::{error, info};
+use speedy2d::dimen::UVec2;
+use speedy2d::window::UserEventSender;
+use sqlite::Connection;
+
+use crate::progress::Progress;
+use crate::{db, load_and_insert_image, metadata, Error, THUMBNAIL_SIZE};
+
+// images either side of the current image that are resized before the rest of the folder
+const NEIGHBOURS: usize = 2;
+
+/// The screen resolution can change many times a second while a window is dragged or animated by the
+/// window manager, so a new resolution is only used once it has not changed for this long
+pub const RESOLUTION_SETTLE: Duration = Duration::from_millis(500);
+
+/// Messages sent from the window to the background cache thread
+#[derive(Debug)]
+pub enum CacheMessage {
+    /// the screen resolution has changed, all queued work is for the wrong size and starts again
+    Resolution(UVec2),
+    /// the user is now looking at this image so work near it should be done first
+    Current(String),
+    /// the window is waiting for this image, it is resized next even if it was done before, for example
+    /// after it was rotated. The window is woken once it has been resized or has failed
+    Load(String),
+    /// the folder has been scanned again, these are all the images in it
+    Files(Vec<String>),
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Priority {
+    Current,
+    Neighbour,
+    Rest,
+}
+
+/// The images still to be resized, ordered by how close they are to the current image
+struct CacheQueue {
+    names: Vec<String>,
+    pending: BTreeSet<usize>,
+    current: usize,
+}
+
+impl CacheQueue {
+    fn new(names: Vec<String>, current: usize) -> Self {
+        Self {
+            names,
+            pending: BTreeSet::new(),
+            current,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pending = (0..self.names.len()).collect();
+    }
+
+    fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn set_names(&mut self, names: Vec<String>) {
+        let current = self.names.get(self.current).cloned();
+        self.names = names;
+        self.current = 0;
+        if let Some(current) = current {
+            self.set_current(&current);
+        }
+        self.reset();
+    }
+
+    fn set_current(&mut self, name: &str) {
+        if let Some(index) = self.names.iter().position(|x| x == name) {
+            self.current = index;
+        }
+    }
+
+    fn load(&mut self, name: &str) {
+        if let Some(index) = self.names.iter().position(|x| x == name) {
+            self.current = index;
+            self.pending.insert(index);
+        }
+    }
+
+    fn is_current(&self, name: &str) -> bool {
+        self.names.get(self.current).is_some_and(|x| x == name)
+    }
+
+    fn priority(&self, index: usize) -> (Priority, usize) {
+        let len = self.names.len();
+        let forward = (index + len - self.current) % len;
+        let backward = (self.current + len - index) % len;
+        let distance = forward.min(backward);
+
+        let priority = if distance == 0 {
+            Priority::Current
+        } else if distance <= NEIGHBOURS {
+            Priority::Neighbour
+        } else {
+            Priority::Rest
+        };
+
+        // within a priority, images after the current one come first then wrap around
+        (priority, forward)
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        let index = self
+            .pending
+            .iter()
+            .copied()
+            .min_by_key(|index| self.priority(*index))?;
+        self.pending.remove(&index);
+        Some(index)
+    }
+}
+
+/// State shared between the thread receiving messages and the workers
+struct Shared {
+    queue: CacheQueue,
+    size: Option<UVec2>,
+    /// incremented whenever queued work is thrown away so that work already in progress is not counted
+    generation: u64,
+    stopped: bool,
+}
+
+/// Everything a worker thread needs to resize an image
+#[derive(Clone)]
+struct Worker {
+    path: String,
+    connection: Arc<Mutex<Connection>>,
+    shared_cache: Option<Arc<Mutex<Connection>>>,
+    filter: FilterType,
+    exif_orientation: Arc<AtomicBool>,
+    progress: Arc<Progress>,
+    /// why images could not be resized, by name, taken by the window when it is waiting for one of them
+    failures: Arc<Mutex<HashMap<String, String>>>,
+    user_event_sender: Arc<Mutex<UserEventSender<()>>>,
+    shared: Arc<(Mutex<Shared>, Condvar)>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_cache(
+    path: String,
+    image_file_names: Vec<String>,
+    image_index: usize,
+    connection: Arc<Mutex<Connection>>,
+    shared_cache: Option<Arc<Mutex<Connection>>>,
+    filter: FilterType,
+    exif_orientation: Arc<AtomicBool>,
+    progress: Arc<Progress>,
+    failures: Arc<Mutex<HashMap<String, String>>>,
+    user_event_sender: Arc<Mutex<UserEventSender<()>>>,
+    rx: Receiver<CacheMessage>,
+    num_workers: usize,
+) -> Result<(), Error> {
+    let shared = Arc::new((
+        Mutex::new(Shared {
+            queue: CacheQueue::new(image_file_names, image_index),
+            size: None,
+            generation: 0,
+            stopped: false,
+        }),
+        Condvar::new(),
+    ));
+
+    // decoding and resizing run in parallel, database access is serialized by its mutex
+    let worker = Worker {
+        path,
+        connection: connection.clone(),
+        shared_cache,
+        filter,
+        exif_orientation,
+        progress: progress.clone(),
+        failures,
+        user_event_sender,
+        shared: shared.clone(),
+    };
+    let workers: Vec<_> = (0..num_workers.max(1))
+        .map(|_| {
+            let worker = worker.clone();
+            thread::spawn(move || worker.run())
+        })
+        .collect();
+
+    let (lock, condvar) = &*shared;
+    let mut resolution = None;
+    // the latest resolution and when it was received, while waiting for it to settle
+    let mut pending_resolution: Option<(UVec2, Instant)> = None;
+    loop {
+        let message = match pending_resolution {
+            Some((_, received)) => {
+                match rx.recv_timeout(RESOLUTION_SETTLE.saturating_sub(received.elapsed())) {
+                    Ok(message) => Some(message),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            None => match rx.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            },
+        };
+
+        match message {
+            None => {
+                let (new_size, _) = pending_resolution.take().expect("waiting for a resolution");
+
+                // settling back on the resolution already being worked on leaves the queue as it was
+                if resolution != Some(new_size) {
+                    resolution = Some(new_size);
+
+                    // the workers are paused so nothing is resized while the images are counted
+                    let names = lock.lock().unwrap().queue.names.clone();
+                    let uncached = count_uncached(&names, new_size, connection.clone());
+
+                    let mut shared = lock.lock().unwrap();
+                    shared.queue.reset();
+                    shared.generation += 1;
+                    progress.start(uncached);
+                }
+                lock.lock().unwrap().size = Some(new_size);
+            }
+            Some(CacheMessage::Resolution(new_size)) => {
+                // pause the workers until the resolution stops changing
+                lock.lock().unwrap().size = None;
+                pending_resolution = Some((new_size, Instant::now()));
+            }
+            Some(CacheMessage::Current(name)) => lock.lock().unwrap().queue.set_current(&name),
+            Some(CacheMessage::Load(name)) => lock.lock().unwrap().queue.load(&name),
+            Some(CacheMessage::Files(names)) => {
+                // images are counted once the resolution is known
+                let uncached = match resolution {
+                    Some(size) => count_uncached(&names, size, connection.clone()),
+                    None => 0,
+                };
+
+                let mut shared = lock.lock().unwrap();
+                shared.queue.set_names(names);
+                shared.generation += 1;
+                progress.start(uncached);
+            }
+        }
+
+        condvar.notify_all();
+    }
+
+    lock.lock().unwrap().stopped = true;
+    condvar.notify_all();
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    info!("UpdateCache ended");
+    Ok(())
+}
+
+/// an image is cached once it has been resized to the screen and to a thumbnail
+fn is_cached(name: &str, size: UVec2, connection: Arc<Mutex<Connection>>) -> Result<bool, Error> {
+    Ok(db::photo_exists(name, size, connection.clone())?
+        && db::photo_exists(name, THUMBNAIL_SIZE, connection)?)
+}
+
+/// Images that still need to be resized for the screen, progress only counts these so that it reflects
+/// the work left to do rather than racing through images that were cached before
+fn count_uncached(names: &[String], size: UVec2, connection: Arc<Mutex<Connection>>) -> usize {
+    names
+        .iter()
+        .filter(|name| !is_cached(name, size, connection.clone()).unwrap_or(false))
+        .count()
+}
+
+impl Worker {
+    fn run(self) {
+        while let Some((image_file, size, generation)) = self.next_job() {
+            let resized = match self.resize(&image_file, size) {
+                Ok(resized) => resized,
+                Err(e) => {
+                    // it was counted as work to do so it still counts towards the progress
+                    error!("Unable to cache {image_file}: {e:?}");
+                    let mut failures = self.failures.lock().unwrap();
+                    failures.insert(image_file.clone(), e.to_string());
+                    true
+                }
+            };
+
+            // skipped images and work for an old resolution or file list are not part of the current progress
+            let shared = self.shared.0.lock().unwrap();
+            let counts = resized && shared.generation == generation;
+            let is_current = shared.queue.is_current(&image_file);
+            drop(shared);
+
+            if counts {
+                self.progress.complete_one();
+            }
+
+            // display progress on the screen, or the image the window is waiting for
+            if counts || is_current {
+                let locked = self.user_event_sender.lock().unwrap();
+                locked.send_event(()).unwrap();
+            }
+        }
+    }
+
+    /// blocks until there is an image to resize, none when the cache is shutting down
+    fn next_job(&self) -> Option<(String, UVec2, u64)> {
+        let (lock, condvar) = &*self.shared;
+        let mut shared = lock.lock().unwrap();
+        loop {
+            if shared.stopped {
+                return None;
+            }
+
+            if let Some(size) = shared.size {
+                if let Some(index) = shared.queue.pop() {
+                    return Some((shared.queue.names[index].clone(), size, shared.generation));
+                }
+            }
+
+            shared = condvar.wait(shared).unwrap();
+        }
+    }
+
+    /// returns false when the image had already been resized
+    fn resize(&self, image_file: &str, size: UVec2) -> Result<bool, Error> {
+        // the thumbnail for the grid is made along with the full screen image
+        let size = if !db::photo_exists(image_file, size, self.connection.clone())? {
+            size
+        } else if !db::photo_exists(image_file, THUMBNAIL_SIZE, self.connection.clone())? {
+            THUMBNAIL_SIZE
+        } else {
+            info!("Photo already exists, skipping...");
+            if let Err(e) = self.score_exposure(image_file) {
+                error!("Unable to score the exposure of {image_file}: {e:?}");
+            }
+            return Ok(false);
+        };
+
+        load_and_insert_image(
+            &self.path,
+            image_file,
+            size,
+            self.filter,
+            self.exif_orientation.load(Ordering::Relaxed),
+            self.connection.clone(),
+            self.shared_cache.clone(),
+        )?;
+        Ok(true)
+    }
+
+    /// images are scored when they are resized, images cached before that are scored from their thumbnail
+    /// so that jumping to the most under or over exposed photo covers the whole folder
+    fn score_exposure(&self, image_file: &str) -> Result<(), Error> {
+        if db::has_exposure(image_file, self.connection.clone())? {
+            return Ok(());
+        }
+
+        let thumbnail =
+            db::try_get_image_from_db(image_file, THUMBNAIL_SIZE, self.connection.clone())?;
+        if let Some(thumbnail) = thumbnail {
+            let img = image::load_from_memory(&thumbnail)?;
+            let exposure = metadata::exposure_score(&img);
+            db::update_image_exposure(image_file, exposure, self.connection.clone())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use image::{DynamicImage, Rgb};
+use imageproc::drawing::{draw_filled_rect_mut, draw_text_mut, text_size};
+use imageproc::rect::Rect;
+use rusttype::{Font, Scale};
+
+use crate::config::{CaptionConfig, CaptionPosition};
+use crate::metadata::ImageMetadata;
+
+// space between the text and the edge of its background, and between the background and the image edge
+const PADDING: i32 = 10;
+
+/// Capture settings e.g. "1/250 s  f/5.6  ISO 400  50 mm", none if the camera recorded none of them
+pub fn text(metadata: &ImageMetadata) -> Option<String> {
+    let settings: Vec<String> = [
+        metadata.exposure_time.clone(),
+        metadata.f_number.clone(),
+        metadata.iso.as_ref().map(|x| format!("ISO {x}")),
+        metadata.focal_length.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if settings.is_empty() {
+        None
+    } else {
+        Some(settings.join("  "))
+    }
+}
+
+/// Draw the capture settings onto the image in a corner, on a black background so that they can be read
+pub fn burn(img: DynamicImage, metadata: &ImageMetadata, config: &CaptionConfig) -> DynamicImage {
+    let Some(text) = text(metadata) else {
+        return img;
+    };
+
+    let font = Font::try_from_bytes(include_bytes!("../fonts/NotoSans-Regular.ttf"))
+        .expect("embedded font is valid");
+    let scale = Scale::uniform(config.font_size);
+    let (text_width, text_height) = text_size(scale, &font, &text);
+
+    let mut img = img.to_rgb8();
+    let width = text_width + PADDING * 2;
+    let height = text_height + PADDING * 2;
+    let (image_width, image_height) = (img.width() as i32, img.height() as i32);
+    let (x, y) = match config.position {
+        CaptionPosition::TopLeft => (PADDING, PADDING),
+        CaptionPosition::TopRight => (image_width - width - PADDING, PADDING),
+        CaptionPosition::BottomLeft => (PADDING, image_height - height - PADDING),
+        CaptionPosition::BottomRight => (
+            image_width - width - PADDING,
+            image_height - height - PADDING,
+        ),
+    };
+
+    let background = Rect::at(x, y).of_size(width as u32, height as u32);
+    draw_filled_rect_mut(&mut img, background, Rgb([0, 0, 0]));
+    draw_text_mut(
+        &mut img,
+        Rgb([230, 230, 204]),
+        x + PADDING,
+        y + PADDING,
+        scale,
+        &font,
+        &text,
+    );
+
+    DynamicImage::ImageRgb8(img)
+}
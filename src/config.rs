@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use image::imageops::FilterType;
+use log::info;
+use serde::Deserialize;
+use speedy2d::window::VirtualKeyCode;
+
+use crate::{disk, keys, Error};
+
+const CONFIG_FILE_NAME: &str = "photo-farm.toml";
+
+/// Settings that change how Photo Farm looks and behaves
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub calibration: CalibrationConfig,
+    pub duplicate_stems: DuplicateStems,
+    pub progress: ProgressConfig,
+    /// folder of full resolution originals to export instead of the proofs being viewed
+    pub originals: Option<String>,
+    pub start_position: StartPosition,
+    /// folder of a thumbnail cache shared by every folder that is viewed so that
+    /// identical files (e.g. imported more than once) are only resized once
+    pub shared_cache: Option<String>,
+    pub navigation: NavigationConfig,
+    pub space_key: SpaceKey,
+    pub export: ExportConfig,
+    /// number of recently viewed images kept on the GPU, 0 to upload every image each time it is shown
+    pub texture_cache_size: usize,
+    /// memory in megabytes for full size images decoded for zooming, the least recently used are dropped
+    pub decode_cache_mb: usize,
+    pub resize_filter: ResizeFilter,
+    /// turn photos the way their exif orientation says, unless it has been toggled in the folder being viewed
+    pub exif_orientation: bool,
+    /// a ttf or otf file to draw text with instead of the built in font, e.g. for names in other scripts
+    pub font: Option<PathBuf>,
+    /// fonts for text with characters the font above has no glyphs for, e.g. a CJK font, tried in order
+    pub fallback_fonts: Vec<PathBuf>,
+    pub playlist_format: PlaylistFormat,
+    /// also write selected.csv with the rating, color label and date taken of each starred photo
+    pub manifest_csv: bool,
+    /// how long to keep showing the previous photo while the next one is resized
+    /// before the hourglass is shown
+    pub loading_indicator_delay: Duration,
+    /// on a high dpi screen, cache photos at the logical resolution of the window rather than its physical
+    /// pixels and scale them up when they are shown. Caching is faster and uses less space but photos are softer
+    pub cache_logical_resolution: bool,
+    /// number of threads resizing images in the background, defaults to one per cpu core
+    pub cache_workers: Option<usize>,
+    /// how long each photo is shown in a slideshow, this can be changed while it is running
+    pub slideshow_interval: Duration,
+    pub sort_order: SortOrder,
+    /// nothing is drawn on a screen narrower than this, for example while the window is being created
+    pub min_resolution_width: u32,
+    /// pressing the first key does the same as pressing the second
+    pub key_bindings: HashMap<VirtualKeyCode, VirtualKeyCode>,
+}
+
+/// The settings that can be changed in photo-farm.toml, for example
+/// ```toml
+/// export_quality = 85
+/// export_layout = "by_date"
+/// slideshow_interval_seconds = 3
+/// min_resolution_width = 800
+/// decode_cache_mb = 1024
+/// resize_filter = "triangle"
+/// exif_orientation = false
+/// flagged_is_rated = true
+/// font = "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf"
+/// cache_logical_resolution = true
+/// fallback_fonts = ["/usr/share/fonts/truetype/noto/NotoSansJP-Regular.ttf"]
+///
+/// [keys]
+/// U = "Space"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    export_quality: Option<u8>,
+    export_layout: Option<ExportLayout>,
+    slideshow_interval_seconds: Option<f64>,
+    min_resolution_width: Option<u32>,
+    decode_cache_mb: Option<usize>,
+    resize_filter: Option<ResizeFilter>,
+    exif_orientation: Option<bool>,
+    starred_is_rated: Option<bool>,
+    flagged_is_rated: Option<bool>,
+    font: Option<PathBuf>,
+    fallback_fonts: Option<Vec<PathBuf>>,
+    cache_logical_resolution: Option<bool>,
+    /// the key pressed and the key it acts as
+    keys: HashMap<String, String>,
+}
+
+/// Read photo-farm.toml from the folder being viewed, or from the user's config folder when there is none there.
+/// Anything the file does not set, or everything when there is no file, keeps its default
+pub fn load(path: &str) -> Result<Config, Error> {
+    let mut config = Config::default();
+    let candidates = [
+        Some(PathBuf::from(path).join(CONFIG_FILE_NAME)),
+        disk::user_config_dir().map(|x| x.join(CONFIG_FILE_NAME)),
+    ];
+    let Some(file_name) = candidates.into_iter().flatten().find(|x| x.exists()) else {
+        return Ok(config);
+    };
+
+    info!("Reading settings from {file_name:?}");
+    let file: ConfigFile = toml::from_str(&fs::read_to_string(&file_name)?)?;
+    let invalid = |message: String| Error::InvalidConfig(format!("{file_name:?}: {message}"));
+
+    if let Some(quality) = file.export_quality {
+        if !(1..=100).contains(&quality) {
+            return Err(invalid(format!(
+                "export_quality must be from 1 to 100, not {quality}"
+            )));
+        }
+        config.export.quality = quality;
+    }
+    if let Some(layout) = file.export_layout {
+        config.export.layout = layout;
+    }
+    if let Some(seconds) = file.slideshow_interval_seconds {
+        // the same minimum as changing the interval during a slideshow
+        if !seconds.is_finite() || seconds < 1.0 {
+            return Err(invalid(format!(
+                "slideshow_interval_seconds must be at least 1, not {seconds}"
+            )));
+        }
+        config.slideshow_interval = Duration::from_secs_f64(seconds);
+    }
+    if let Some(width) = file.min_resolution_width {
+        config.min_resolution_width = width;
+    }
+    if let Some(megabytes) = file.decode_cache_mb {
+        config.decode_cache_mb = megabytes;
+    }
+    if let Some(filter) = file.resize_filter {
+        config.resize_filter = filter;
+    }
+    if let Some(exif_orientation) = file.exif_orientation {
+        config.exif_orientation = exif_orientation;
+    }
+    if let Some(starred_is_rated) = file.starred_is_rated {
+        config.navigation.starred_is_rated = starred_is_rated;
+    }
+    if let Some(flagged_is_rated) = file.flagged_is_rated {
+        config.navigation.flagged_is_rated = flagged_is_rated;
+    }
+    if let Some(font) = file.font {
+        config.font = Some(font);
+    }
+    if let Some(fallback_fonts) = file.fallback_fonts {
+        config.fallback_fonts = fallback_fonts;
+    }
+    if let Some(cache_logical_resolution) = file.cache_logical_resolution {
+        config.cache_logical_resolution = cache_logical_resolution;
+    }
+    for (pressed, acts_as) in file.keys.iter() {
+        let parse =
+            |name: &str| keys::parse(name).ok_or_else(|| invalid(format!("unknown key {name}")));
+        config.key_bindings.insert(parse(pressed)?, parse(acts_as)?);
+    }
+
+    Ok(config)
+}
+
+/// The order photos are shown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    FileName,
+    /// by the exif capture date, useful when several cameras were used.
+    /// Photos without a date come last in file name order
+    DateTaken,
+}
+
+/// File written with the starred photos so that other programs can use them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlaylistFormat {
+    /// picks.txt with one full path per line
+    #[default]
+    Paths,
+    /// picks.m3u, the same as Paths with a header understood by media players
+    M3u,
+    /// picks.csv with the path, star, rating and protected flag of each photo
+    Csv,
+    /// picks.json with the same fields as the csv
+    Json,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            calibration: CalibrationConfig::default(),
+            duplicate_stems: DuplicateStems::default(),
+            progress: ProgressConfig::default(),
+            originals: None,
+            start_position: StartPosition::default(),
+            shared_cache: None,
+            navigation: NavigationConfig::default(),
+            space_key: SpaceKey::default(),
+            export: ExportConfig::default(),
+            texture_cache_size: 16,
+            decode_cache_mb: 512,
+            resize_filter: ResizeFilter::default(),
+            exif_orientation: true,
+            font: None,
+            fallback_fonts: Vec::new(),
+            playlist_format: PlaylistFormat::default(),
+            manifest_csv: false,
+            loading_indicator_delay: Duration::from_millis(150),
+            cache_logical_resolution: false,
+            cache_workers: None,
+            slideshow_interval: Duration::from_secs(5),
+            sort_order: SortOrder::default(),
+            min_resolution_width: 1024,
+            key_bindings: HashMap::new(),
+        }
+    }
+}
+
+/// Changes made to photos as they are exported
+#[derive(Debug, Clone)]
+pub struct ExportConfig {
+    /// burn the capture settings into exported photos, they are re-encoded rather than copied
+    pub caption: Option<CaptionConfig>,
+    /// re-encode photos that were rotated in the viewer so that other programs show them the same way,
+    /// otherwise they are copied as they are
+    pub bake_rotation: bool,
+    /// jpeg quality from 1 to 100 of photos that are re-encoded
+    pub quality: u8,
+    /// make the longer edge of exported photos no more than this many pixels,
+    /// none to copy them at full size
+    pub max_dimension: Option<u32>,
+    /// folder the photos are exported to, none for an `export` folder inside the folder being viewed
+    pub destination: Option<PathBuf>,
+    pub layout: ExportLayout,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            caption: None,
+            bake_rotation: true,
+            quality: 90,
+            max_dimension: None,
+            destination: None,
+            layout: ExportLayout::default(),
+        }
+    }
+}
+
+/// How photos are resized for the cache, from the fastest to the sharpest. Exports are always resized with catmull_rom
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeFilter {
+    /// blocky but very quick, for slow machines and huge folders
+    Nearest,
+    /// bilinear, soft but several times quicker than catmull_rom
+    Triangle,
+    /// bicubic
+    #[default]
+    CatmullRom,
+    /// the sharpest and the slowest
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub fn filter_type(self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// How exported photos are arranged in the export folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportLayout {
+    /// every photo directly in the export folder
+    #[default]
+    Flat,
+    /// a YYYY-MM-DD folder for each day photos were taken, photos without an exif date go in `undated`
+    ByDate,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaptionConfig {
+    pub position: CaptionPosition,
+    /// height of the text in pixels of the exported image
+    pub font_size: f32,
+}
+
+impl Default for CaptionConfig {
+    fn default() -> Self {
+        Self {
+            position: CaptionPosition::BottomRight,
+            font_size: 36.0,
+        }
+    }
+}
+
+/// Corner of the photo that the caption is drawn in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaptionPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// What pressing SPACE does to the current photo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpaceKey {
+    /// star or unstar the photo and stay on it
+    #[default]
+    Toggle,
+    /// star the photo (never unstar it) and move on to the next one.
+    /// This is the only place Space advances, so any other setting that advances
+    /// after rating must not move again when Space has already done so
+    StarAndAdvance,
+}
+
+/// Where to start when a folder is opened rather than a particular image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StartPosition {
+    First,
+    Last,
+    /// the first photo that has not been starred or rated, useful for resuming a cull
+    FirstUnrated,
+    /// the photo that was showing when the folder was last closed, otherwise the first
+    #[default]
+    LastViewed,
+}
+
+/// What to do when a folder contains both IMG_0001.JPG and IMG_0001.JPEG
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateStems {
+    /// show every jpeg as its own photo. Other files with the same stem (e.g. raw files)
+    /// belong to the first jpeg in sorted file name order
+    #[default]
+    Separate,
+    /// show a single photo using the .jpg file, the .jpeg file is exported along with it
+    PreferJpg,
+    /// show a single photo using the .jpeg file, the .jpg file is exported along with it
+    PreferJpeg,
+}
+
+/// Strips of known gray levels drawn along the screen edges so that the user
+/// can judge whether their display is clipping shadows or highlights
+#[derive(Debug, Clone)]
+pub struct CalibrationConfig {
+    /// width of each strip in pixels
+    pub strip_width: f32,
+    /// length of each block of a single gray level in pixels
+    pub segment_length: f32,
+    /// gray levels repeated along the strip where 0.0 is pure black and 1.0 is pure white
+    pub values: Vec<f32>,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            strip_width: 8.0,
+            segment_length: 64.0,
+            // black, white and 18% gray (which is 0.46 in sRGB)
+            values: vec![0.0, 1.0, 0.46],
+        }
+    }
+}
+
+/// How far the navigation keys move through the folder
+#[derive(Debug, Clone)]
+pub struct NavigationConfig {
+    /// number of photos skipped with SHIFT+UP and SHIFT+DOWN
+    pub big_step: usize,
+    /// photos taken no more than this far apart are a burst that LEFT and RIGHT skip over
+    pub group_threshold: Duration,
+    /// N and SHIFT+N skip starred photos that have no rating, as well as rated ones
+    pub starred_is_rated: bool,
+    /// N and SHIFT+N skip flagged photos that have no rating, as well as rated ones
+    pub flagged_is_rated: bool,
+}
+
+impl Default for NavigationConfig {
+    fn default() -> Self {
+        Self {
+            big_step: 10,
+            group_threshold: Duration::from_secs(1),
+            starred_is_rated: true,
+            flagged_is_rated: false,
+        }
+    }
+}
+
+/// How the progress of background work is reported
+#[derive(Debug, Clone)]
+pub struct ProgressConfig {
+    /// weight given to the most recent image when estimating the time remaining,
+    /// lower values give a steadier estimate that is slower to react to changes
+    pub smoothing: f64,
+}
+
+impl Default for ProgressConfig {
+    fn default() -> Self {
+        Self { smoothing: 0.1 }
+    }
+}
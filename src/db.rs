@@ -1,15 +1,18 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    fs,
     path::Path,
     sync::{Arc, Mutex},
 };
 
-use log::info;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone};
+use log::{info, warn};
 use speedy2d::dimen::UVec2;
 use sqlite::{Connection, State, Value};
 
+use crate::metadata::{ImageMetadata, ShotSettings};
 use crate::Error;
-use crate::{disk, metadata::ImageMetadata};
+use crate::{disk, ImageNamePair};
 
 const DB_TABLE_PHOTOS: &str = "photos";
 const DB_COL_NAME: &str = "name";
@@ -18,43 +21,846 @@ const DB_COL_Y_RES: &str = "y_res";
 const DB_COL_RESIZED: &str = "resized";
 const DB_COL_IS_STARRED: &str = "is_starred";
 const DB_COL_DATE_TIME: &str = "date_time";
+const DB_COL_PROTECTED: &str = "protected";
+const DB_COL_EXPOSURE: &str = "exposure";
+const DB_COL_RATING: &str = "rating";
+const DB_COL_ROTATION: &str = "rotation";
+const DB_COL_COLOR_LABEL: &str = "color_label";
+// the rating or label was chosen in Photo Farm, even if it was cleared, so xmp sidecars are no longer read
+const DB_COL_RATING_SET: &str = "rating_set";
+const DB_COL_COLOR_LABEL_SET: &str = "color_label_set";
+const DB_COL_GROUP_ID: &str = "group_id";
+const DB_COL_FLAGGED: &str = "flagged";
+const DB_COL_ISO: &str = "iso";
+const DB_COL_F_NUMBER: &str = "f_number";
+const DB_COL_FOCAL_LENGTH: &str = "focal_length";
+// the camera settings were read, even if the photo has none, so its exif is not read again
+const DB_COL_SHOT_READ: &str = "shot_read";
+const DB_COL_UTC_OFFSET: &str = "utc_offset";
+const DB_COL_NANOSECOND: &str = "nanosecond";
+// the exif was read and has no capture date, so it is not read again each time the folder is scanned
+const DB_COL_NO_DATE: &str = "no_date";
+
+const SHARED_DB_FILE_NAME: &str = "shared_thumbnails.db";
+const DB_TABLE_SHARED: &str = "thumbnails";
+// values remembered for the folder rather than for a photo
+const DB_TABLE_SETTINGS: &str = "settings";
+const DB_COL_KEY: &str = "key";
+const DB_COL_VALUE: &str = "value";
+const SETTING_LAST_VIEWED: &str = "last_viewed";
+const SETTING_EXIF_ORIENTATION: &str = "exif_orientation";
+const DB_COL_HASH: &str = "hash";
+const DB_INDEX_LOOKUP: &str = "idx_photos_lookup";
+
+// columns added after the original schema, these are added to existing databases
+// so that stars and other user data survive an upgrade
+const DB_ADDED_COLUMNS: &[(&str, &str)] = &[
+    (DB_COL_PROTECTED, "INTEGER NOT NULL DEFAULT 0"),
+    (DB_COL_EXPOSURE, "REAL"),
+    (DB_COL_RATING, "INTEGER NOT NULL DEFAULT 0"),
+    (DB_COL_ROTATION, "INTEGER NOT NULL DEFAULT 0"),
+    (DB_COL_COLOR_LABEL, "INTEGER NOT NULL DEFAULT 0"),
+    (DB_COL_GROUP_ID, "INTEGER"),
+    (DB_COL_UTC_OFFSET, "INTEGER"),
+    (DB_COL_NANOSECOND, "INTEGER"),
+    (DB_COL_FLAGGED, "INTEGER NOT NULL DEFAULT 0"),
+    (DB_COL_ISO, "INTEGER"),
+    (DB_COL_F_NUMBER, "REAL"),
+    (DB_COL_FOCAL_LENGTH, "REAL"),
+    (DB_COL_RATING_SET, "INTEGER NOT NULL DEFAULT 0"),
+    (DB_COL_COLOR_LABEL_SET, "INTEGER NOT NULL DEFAULT 0"),
+    (DB_COL_NO_DATE, "INTEGER NOT NULL DEFAULT 0"),
+    (DB_COL_SHOT_READ, "INTEGER NOT NULL DEFAULT 0"),
+];
 
 pub fn photo_exists(
     name: &str,
-    size: UVec2,
+    size: UVec2,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<bool, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("SELECT 1 FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME} AND {DB_COL_X_RES} = :{DB_COL_X_RES} AND {DB_COL_Y_RES} = :{DB_COL_Y_RES};");
+    let mut statement = connection.prepare(query)?;
+    let x = size.x as i64;
+    let y = size.y as i64;
+
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+            (format!(":{DB_COL_X_RES}").as_str(), x.into()),
+            (format!(":{DB_COL_Y_RES}").as_str(), y.into()),
+        ][..],
+    )?;
+
+    match statement.next()? {
+        State::Row => Ok(true),
+        State::Done => Ok(false),
+    }
+}
+
+pub fn try_get_image_from_db(
+    name: &str,
+    size: UVec2,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<Option<Vec<u8>>, Error> {
+    let connection = connection.lock().unwrap();
+
+    // even if there is an entry in the db there may not yet be a resized image
+    let query = format!(
+        "SELECT {DB_COL_RESIZED} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME} AND {DB_COL_X_RES} = :{DB_COL_X_RES} AND {DB_COL_Y_RES} = :{DB_COL_Y_RES} AND NOT {DB_COL_RESIZED} IS NULL;"
+    );
+
+    let mut statement = connection.prepare(query)?;
+    let x = size.x as i64;
+    let y = size.y as i64;
+
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+            (format!(":{DB_COL_X_RES}").as_str(), x.into()),
+            (format!(":{DB_COL_Y_RES}").as_str(), y.into()),
+        ][..],
+    )?;
+
+    match statement.next()? {
+        State::Row => {
+            let resized = statement.read::<Vec<u8>, _>(DB_COL_RESIZED)?;
+            Ok(Some(resized))
+        }
+        State::Done => Ok(None),
+    }
+}
+
+pub fn insert_image(
+    name: &str,
+    size: UVec2,
+    resized: &[u8],
+    metadata: Option<ImageMetadata>,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!(
+        "INSERT INTO {DB_TABLE_PHOTOS} ({DB_COL_NAME}, {DB_COL_X_RES}, {DB_COL_Y_RES}, {DB_COL_RESIZED}, {DB_COL_IS_STARRED}, {DB_COL_DATE_TIME}, {DB_COL_UTC_OFFSET}, {DB_COL_NANOSECOND}) VALUES (:{DB_COL_NAME}, :{DB_COL_X_RES}, :{DB_COL_Y_RES}, :{DB_COL_RESIZED}, :{DB_COL_IS_STARRED}, :{DB_COL_DATE_TIME}, :{DB_COL_UTC_OFFSET}, :{DB_COL_NANOSECOND});"
+    );
+    let mut statement = connection.prepare(query)?;
+    let x = size.x as i64;
+    let y = size.y as i64;
+    let is_starred: i64 = 0;
+
+    let (date_time, utc_offset, nanosecond) =
+        capture_time_values(metadata.and_then(|x| x.capture_time));
+
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+            (format!(":{DB_COL_X_RES}").as_str(), x.into()),
+            (format!(":{DB_COL_Y_RES}").as_str(), y.into()),
+            (format!(":{DB_COL_RESIZED}").as_str(), resized.into()),
+            (format!(":{DB_COL_IS_STARRED}").as_str(), is_starred.into()),
+            (format!(":{DB_COL_DATE_TIME}").as_str(), date_time.clone()),
+            (format!(":{DB_COL_UTC_OFFSET}").as_str(), utc_offset.clone()),
+            (format!(":{DB_COL_NANOSECOND}").as_str(), nanosecond.clone()),
+        ][..],
+    )?;
+
+    statement.next()?;
+
+    // rows cached before the offset from UTC was recorded hold the camera's local time as if it were UTC
+    if matches!(utc_offset, Value::Integer(_)) {
+        let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_DATE_TIME} = :{DB_COL_DATE_TIME}, {DB_COL_UTC_OFFSET} = :{DB_COL_UTC_OFFSET}, {DB_COL_NANOSECOND} = :{DB_COL_NANOSECOND} WHERE {DB_COL_NAME} = :{DB_COL_NAME} AND {DB_COL_UTC_OFFSET} IS NULL;");
+        let mut statement = connection.prepare(query)?;
+        statement.bind::<&[(_, Value)]>(
+            &[
+                (format!(":{DB_COL_DATE_TIME}").as_str(), date_time),
+                (format!(":{DB_COL_UTC_OFFSET}").as_str(), utc_offset),
+                (format!(":{DB_COL_NANOSECOND}").as_str(), nanosecond),
+                (format!(":{DB_COL_NAME}").as_str(), name.into()),
+            ][..],
+        )?;
+        statement.next()?;
+    }
+
+    Ok(())
+}
+
+pub fn update_image_is_starred(
+    name: &str,
+    is_starred: bool,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_IS_STARRED} = :{DB_COL_IS_STARRED} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let is_starred = is_starred as i64;
+
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_IS_STARRED}").as_str(), is_starred.into()),
+            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+        ][..],
+    )?;
+
+    statement.next()?;
+    Ok(())
+}
+
+/// star or unstar every image in the folder
+pub fn set_all_starred(is_starred: bool, connection: Arc<Mutex<Connection>>) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_IS_STARRED} = :{DB_COL_IS_STARRED};");
+    let mut statement = connection.prepare(query)?;
+    let is_starred = is_starred as i64;
+
+    statement.bind::<&[(_, Value)]>(
+        &[(format!(":{DB_COL_IS_STARRED}").as_str(), is_starred.into())][..],
+    )?;
+
+    statement.next()?;
+    Ok(())
+}
+
+pub fn get_starred_image_names(
+    connection: Arc<Mutex<Connection>>,
+) -> Result<HashSet<String>, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query =
+        format!("SELECT {DB_COL_NAME} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_IS_STARRED} = TRUE;");
+    let mut statement = connection.prepare(query)?;
+    let mut names = HashSet::new();
+
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        names.insert(name);
+    }
+
+    Ok(names)
+}
+
+/// names of the images that have been resized to `size`
+pub fn get_cached_image_names(
+    size: UVec2,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<HashSet<String>, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("SELECT {DB_COL_NAME} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_X_RES} = :{DB_COL_X_RES} AND {DB_COL_Y_RES} = :{DB_COL_Y_RES} AND NOT {DB_COL_RESIZED} IS NULL;");
+    let mut statement = connection.prepare(query)?;
+    let x = size.x as i64;
+    let y = size.y as i64;
+
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_X_RES}").as_str(), x.into()),
+            (format!(":{DB_COL_Y_RES}").as_str(), y.into()),
+        ][..],
+    )?;
+
+    let mut names = HashSet::new();
+    while let State::Row = statement.next()? {
+        names.insert(statement.read::<String, _>(DB_COL_NAME)?);
+    }
+
+    Ok(names)
+}
+
+pub fn update_image_rating(
+    name: &str,
+    rating: u8,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_RATING} = :{DB_COL_RATING}, {DB_COL_RATING_SET} = TRUE WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let rating = rating as i64;
+
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_RATING}").as_str(), rating.into()),
+            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+        ][..],
+    )?;
+
+    statement.next()?;
+    Ok(())
+}
+
+/// ratings of every image that has been rated, including 0 for one whose rating was cleared
+pub fn get_ratings(connection: Arc<Mutex<Connection>>) -> Result<HashMap<String, u8>, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("SELECT {DB_COL_NAME}, MAX({DB_COL_RATING}) AS {DB_COL_RATING} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_RATING} > 0 OR {DB_COL_RATING_SET} = TRUE GROUP BY {DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let mut ratings = HashMap::new();
+
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        let rating = statement.read::<i64, _>(DB_COL_RATING)?;
+        ratings.insert(name, rating.clamp(0, 5) as u8);
+    }
+
+    Ok(ratings)
+}
+
+/// `color_label` is 0 for none, 1 red, 2 yellow or 3 green
+pub fn update_image_color_label(
+    name: &str,
+    color_label: u8,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_COLOR_LABEL} = :{DB_COL_COLOR_LABEL}, {DB_COL_COLOR_LABEL_SET} = TRUE WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let color_label = color_label as i64;
+
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (
+                format!(":{DB_COL_COLOR_LABEL}").as_str(),
+                color_label.into(),
+            ),
+            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+        ][..],
+    )?;
+
+    statement.next()?;
+    Ok(())
+}
+
+/// color labels of every image that has one, including 0 for one whose label was cleared
+pub fn get_color_labels(connection: Arc<Mutex<Connection>>) -> Result<HashMap<String, u8>, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("SELECT {DB_COL_NAME}, MAX({DB_COL_COLOR_LABEL}) AS {DB_COL_COLOR_LABEL} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_COLOR_LABEL} > 0 OR {DB_COL_COLOR_LABEL_SET} = TRUE GROUP BY {DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let mut color_labels = HashMap::new();
+
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        let color_label = statement.read::<i64, _>(DB_COL_COLOR_LABEL)?;
+        color_labels.insert(name, color_label.clamp(0, 3) as u8);
+    }
+
+    Ok(color_labels)
+}
+
+/// `rotation` is the number of quarter turns clockwise applied on top of the exif orientation
+pub fn update_image_rotation(
+    name: &str,
+    rotation: u8,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_ROTATION} = :{DB_COL_ROTATION} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let rotation = rotation as i64;
+
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_ROTATION}").as_str(), rotation.into()),
+            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+        ][..],
+    )?;
+
+    statement.next()?;
+    Ok(())
+}
+
+pub fn get_rotation(name: &str, connection: Arc<Mutex<Connection>>) -> Result<u8, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("SELECT MAX({DB_COL_ROTATION}) AS {DB_COL_ROTATION} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    statement.bind::<&[(_, Value)]>(&[(format!(":{DB_COL_NAME}").as_str(), name.into())][..])?;
+
+    // an aggregate always returns a row, the value is null if the image has never been cached
+    match statement.next()? {
+        State::Row => {
+            let rotation = statement.read::<Option<i64>, _>(DB_COL_ROTATION)?;
+            Ok(rotation.unwrap_or(0).rem_euclid(4) as u8)
+        }
+        State::Done => Ok(0),
+    }
+}
+
+/// rotations of every image that has been rotated
+pub fn get_rotations(connection: Arc<Mutex<Connection>>) -> Result<HashMap<String, u8>, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("SELECT {DB_COL_NAME}, MAX({DB_COL_ROTATION}) AS {DB_COL_ROTATION} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_ROTATION} > 0 GROUP BY {DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let mut rotations = HashMap::new();
+
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        let rotation = statement.read::<i64, _>(DB_COL_ROTATION)?;
+        rotations.insert(name, rotation.rem_euclid(4) as u8);
+    }
+
+    Ok(rotations)
+}
+
+/// Throw away every resized copy of an image, the thumbnail included, so that it is resized again, e.g. after
+/// it has been rotated. One row is kept without a resized image so that the stars and other user data are not lost
+pub fn remove_resized_images(name: &str, connection: Arc<Mutex<Connection>>) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("DELETE FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME} AND rowid NOT IN (SELECT MIN(rowid) FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME});");
+    let mut statement = connection.prepare(query)?;
+    statement.bind::<&[(_, Value)]>(&[(format!(":{DB_COL_NAME}").as_str(), name.into())][..])?;
+    statement.next()?;
+
+    // a resolution of zero never matches a screen so the cache thread does not skip the image
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_RESIZED} = NULL, {DB_COL_X_RES} = 0, {DB_COL_Y_RES} = 0 WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    statement.bind::<&[(_, Value)]>(&[(format!(":{DB_COL_NAME}").as_str(), name.into())][..])?;
+    statement.next()?;
+
+    Ok(())
+}
+
+/// `remove_resized_images` for every image in the folder
+pub fn remove_all_resized_images(connection: Arc<Mutex<Connection>>) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("DELETE FROM {DB_TABLE_PHOTOS} WHERE rowid NOT IN (SELECT MIN(rowid) FROM {DB_TABLE_PHOTOS} GROUP BY {DB_COL_NAME});");
+    connection.execute(query)?;
+
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_RESIZED} = NULL, {DB_COL_X_RES} = 0, {DB_COL_Y_RES} = 0;");
+    connection.execute(query)?;
+
+    Ok(())
+}
+
+/// the resolutions images have been resized to, the one with the most images first
+pub fn get_cached_resolutions(connection: Arc<Mutex<Connection>>) -> Result<Vec<UVec2>, Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("SELECT {DB_COL_X_RES}, {DB_COL_Y_RES} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_X_RES} > 0 AND NOT {DB_COL_RESIZED} IS NULL GROUP BY {DB_COL_X_RES}, {DB_COL_Y_RES} ORDER BY COUNT(*) DESC;");
+    let mut statement = connection.prepare(query)?;
+
+    let mut resolutions = Vec::new();
+    while let State::Row = statement.next()? {
+        let x = statement.read::<i64, _>(DB_COL_X_RES)?;
+        let y = statement.read::<i64, _>(DB_COL_Y_RES)?;
+        resolutions.push(UVec2::new(x as u32, y as u32));
+    }
+
+    Ok(resolutions)
+}
+
+/// remember the image being viewed so that the folder opens at it next time
+pub fn set_last_viewed(name: &str, connection: Arc<Mutex<Connection>>) -> Result<(), Error> {
+    set_setting(SETTING_LAST_VIEWED, name, connection)
+}
+
+/// the image that was being viewed when the folder was last open, if any
+pub fn get_last_viewed(connection: Arc<Mutex<Connection>>) -> Result<Option<String>, Error> {
+    get_setting(SETTING_LAST_VIEWED, connection)
+}
+
+/// whether photos in this folder are turned the way their exif orientation says
+pub fn set_exif_orientation(
+    exif_orientation: bool,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let value = if exif_orientation { "1" } else { "0" };
+    set_setting(SETTING_EXIF_ORIENTATION, value, connection)
+}
+
+/// none when the exif orientation has never been turned off or on again in this folder
+pub fn get_exif_orientation(connection: Arc<Mutex<Connection>>) -> Result<Option<bool>, Error> {
+    let value = get_setting(SETTING_EXIF_ORIENTATION, connection)?;
+    Ok(value.map(|x| x != "0"))
+}
+
+fn set_setting(key: &str, value: &str, connection: Arc<Mutex<Connection>>) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("INSERT OR REPLACE INTO {DB_TABLE_SETTINGS} ({DB_COL_KEY}, {DB_COL_VALUE}) VALUES (:{DB_COL_KEY}, :{DB_COL_VALUE});");
+    let mut statement = connection.prepare(query)?;
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_KEY}").as_str(), key.into()),
+            (format!(":{DB_COL_VALUE}").as_str(), value.into()),
+        ][..],
+    )?;
+    statement.next()?;
+    Ok(())
+}
+
+fn get_setting(key: &str, connection: Arc<Mutex<Connection>>) -> Result<Option<String>, Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!(
+        "SELECT {DB_COL_VALUE} FROM {DB_TABLE_SETTINGS} WHERE {DB_COL_KEY} = :{DB_COL_KEY};"
+    );
+    let mut statement = connection.prepare(query)?;
+    statement.bind::<&[(_, Value)]>(&[(format!(":{DB_COL_KEY}").as_str(), key.into())][..])?;
+
+    match statement.next()? {
+        State::Row => Ok(Some(statement.read::<String, _>(DB_COL_VALUE)?)),
+        State::Done => Ok(None),
+    }
+}
+
+/// forget everything about an image that is no longer in the folder
+pub fn delete_photo(name: &str, connection: Arc<Mutex<Connection>>) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("DELETE FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    statement.bind::<&[(_, Value)]>(&[(format!(":{DB_COL_NAME}").as_str(), name.into())][..])?;
+    statement.next()?;
+    Ok(())
+}
+
+/// Forget the images that are no longer in the folder, for example after they were moved or deleted by another
+/// program, along with any resized images left by interrupted runs. Returns how many rows were deleted
+pub fn prune_cache(
+    existing_names: &HashSet<String>,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<usize, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!(
+        "SELECT {DB_COL_NAME}, COUNT(*) AS count FROM {DB_TABLE_PHOTOS} GROUP BY {DB_COL_NAME};"
+    );
+    let mut statement = connection.prepare(query)?;
+    let mut gone = Vec::new();
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        let count = statement.read::<i64, _>("count")?;
+        // rows for kinds of photos that are not listed, e.g. HEIC without the `heif` feature, are kept
+        if disk::is_listed(&name) && !existing_names.contains(&name) {
+            gone.push((name, count as usize));
+        }
+    }
+
+    let query = format!("DELETE FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    for (name, _) in &gone {
+        let mut statement = connection.prepare(&query)?;
+        statement.bind::<&[(_, Value)]>(
+            &[(format!(":{DB_COL_NAME}").as_str(), name.as_str().into())][..],
+        )?;
+        statement.next()?;
+    }
+
+    Ok(gone.iter().map(|(_, count)| count).sum())
+}
+
+/// Give the space left by deleted rows back to the file system. This rewrites the whole file, which takes a
+/// while for a large cache, so it is only done when asked for
+pub fn vacuum_cache(connection: Arc<Mutex<Connection>>) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    connection.execute("VACUUM;")?;
+    Ok(())
+}
+
+/// Put back the user data of an image removed by `delete_photo`, for example when a delete is undone.
+/// The resized images were thrown away so the image is cached again like a new one
+pub fn restore_photo(
+    image: &ImageNamePair,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("INSERT INTO {DB_TABLE_PHOTOS} ({DB_COL_NAME}, {DB_COL_X_RES}, {DB_COL_Y_RES}, {DB_COL_IS_STARRED}, {DB_COL_DATE_TIME}, {DB_COL_UTC_OFFSET}, {DB_COL_NANOSECOND}, {DB_COL_RATING}, {DB_COL_ROTATION}, {DB_COL_COLOR_LABEL}, {DB_COL_FLAGGED}, {DB_COL_GROUP_ID}) VALUES (:{DB_COL_NAME}, 0, 0, :{DB_COL_IS_STARRED}, :{DB_COL_DATE_TIME}, :{DB_COL_UTC_OFFSET}, :{DB_COL_NANOSECOND}, :{DB_COL_RATING}, :{DB_COL_ROTATION}, :{DB_COL_COLOR_LABEL}, :{DB_COL_FLAGGED}, :{DB_COL_GROUP_ID});");
+    let mut statement = connection.prepare(query)?;
+    let is_starred = image.is_starred as i64;
+    let (date_time, utc_offset, nanosecond) = capture_time_values(image.date_time);
+    let rating = image.rating as i64;
+    let rotation = image.rotation as i64;
+    let color_label = image.color_label as i64;
+    let is_flagged = image.is_flagged as i64;
+    let group_id = image.group_id.map_or(Value::Null, Value::Integer);
+
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (
+                format!(":{DB_COL_NAME}").as_str(),
+                image.jpg_file_name.as_str().into(),
+            ),
+            (format!(":{DB_COL_IS_STARRED}").as_str(), is_starred.into()),
+            (format!(":{DB_COL_DATE_TIME}").as_str(), date_time),
+            (format!(":{DB_COL_UTC_OFFSET}").as_str(), utc_offset),
+            (format!(":{DB_COL_NANOSECOND}").as_str(), nanosecond),
+            (format!(":{DB_COL_RATING}").as_str(), rating.into()),
+            (format!(":{DB_COL_ROTATION}").as_str(), rotation.into()),
+            (
+                format!(":{DB_COL_COLOR_LABEL}").as_str(),
+                color_label.into(),
+            ),
+            (format!(":{DB_COL_FLAGGED}").as_str(), is_flagged.into()),
+            (format!(":{DB_COL_GROUP_ID}").as_str(), group_id),
+        ][..],
+    )?;
+    statement.next()?;
+
+    Ok(())
+}
+
+pub fn update_image_is_protected(
+    name: &str,
+    is_protected: bool,
     connection: Arc<Mutex<Connection>>,
-) -> Result<bool, Error> {
+) -> Result<(), Error> {
     let connection = connection.lock().unwrap();
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_PROTECTED} = :{DB_COL_PROTECTED} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let is_protected = is_protected as i64;
 
-    let query = format!("SELECT 1 FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME} AND {DB_COL_X_RES} = :{DB_COL_X_RES} AND {DB_COL_Y_RES} = :{DB_COL_Y_RES};");
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_PROTECTED}").as_str(), is_protected.into()),
+            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+        ][..],
+    )?;
+
+    statement.next()?;
+    Ok(())
+}
+
+pub fn get_protected_image_names(
+    connection: Arc<Mutex<Connection>>,
+) -> Result<HashSet<String>, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query =
+        format!("SELECT {DB_COL_NAME} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_PROTECTED} = TRUE;");
     let mut statement = connection.prepare(query)?;
-    let x = size.x as i64;
-    let y = size.y as i64;
+    let mut names = HashSet::new();
+
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        names.insert(name);
+    }
+
+    Ok(names)
+}
+
+/// flag an image as a maybe on a first pass, separately from the star given to final selects
+pub fn update_image_flagged(
+    name: &str,
+    is_flagged: bool,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_FLAGGED} = :{DB_COL_FLAGGED} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let is_flagged = is_flagged as i64;
 
     statement.bind::<&[(_, Value)]>(
         &[
+            (format!(":{DB_COL_FLAGGED}").as_str(), is_flagged.into()),
+            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+        ][..],
+    )?;
+
+    statement.next()?;
+    Ok(())
+}
+
+pub fn get_flagged_image_names(
+    connection: Arc<Mutex<Connection>>,
+) -> Result<HashSet<String>, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query =
+        format!("SELECT {DB_COL_NAME} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_FLAGGED} = TRUE;");
+    let mut statement = connection.prepare(query)?;
+    let mut names = HashSet::new();
+
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        names.insert(name);
+    }
+
+    Ok(names)
+}
+
+pub fn update_image_exposure(
+    name: &str,
+    exposure: f64,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_EXPOSURE} = :{DB_COL_EXPOSURE} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_EXPOSURE}").as_str(), exposure.into()),
             (format!(":{DB_COL_NAME}").as_str(), name.into()),
-            (format!(":{DB_COL_X_RES}").as_str(), x.into()),
-            (format!(":{DB_COL_Y_RES}").as_str(), y.into()),
         ][..],
     )?;
 
+    statement.next()?;
+    Ok(())
+}
+
+/// true once an image has an exposure score, images cached before scores were kept have none
+pub fn has_exposure(name: &str, connection: Arc<Mutex<Connection>>) -> Result<bool, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("SELECT 1 FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME} AND NOT {DB_COL_EXPOSURE} IS NULL;");
+    let mut statement = connection.prepare(query)?;
+    statement.bind::<&[(_, Value)]>(&[(format!(":{DB_COL_NAME}").as_str(), name.into())][..])?;
+
     match statement.next()? {
         State::Row => Ok(true),
         State::Done => Ok(false),
     }
 }
 
-pub fn try_get_image_from_db(
+/// Remember the camera settings of an image so that the exif is only read once when counting them, a photo
+/// without any is remembered too. An image without a row gets one without a resized image
+pub fn update_shot_settings(
     name: &str,
+    settings: ShotSettings,
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let iso = settings
+        .iso
+        .map_or(Value::Null, |x| Value::Integer(x as i64));
+    let f_number = settings.f_number.map_or(Value::Null, Value::Float);
+    let focal_length = settings.focal_length.map_or(Value::Null, Value::Float);
+
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_ISO} = :{DB_COL_ISO}, {DB_COL_F_NUMBER} = :{DB_COL_F_NUMBER}, {DB_COL_FOCAL_LENGTH} = :{DB_COL_FOCAL_LENGTH}, {DB_COL_SHOT_READ} = TRUE WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    statement.bind::<&[(_, Value)]>(
+        &[
+            (format!(":{DB_COL_ISO}").as_str(), iso.clone()),
+            (format!(":{DB_COL_F_NUMBER}").as_str(), f_number.clone()),
+            (
+                format!(":{DB_COL_FOCAL_LENGTH}").as_str(),
+                focal_length.clone(),
+            ),
+            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+        ][..],
+    )?;
+    statement.next()?;
+
+    ensure_placeholder_row(
+        &connection,
+        name,
+        &[
+            (DB_COL_ISO, iso),
+            (DB_COL_F_NUMBER, f_number),
+            (DB_COL_FOCAL_LENGTH, focal_length),
+            (DB_COL_SHOT_READ, Value::Integer(1)),
+        ],
+    )
+}
+
+/// camera settings of every image they have been read for, all none for a photo without any
+pub fn get_shot_settings(
+    connection: Arc<Mutex<Connection>>,
+) -> Result<HashMap<String, ShotSettings>, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("SELECT {DB_COL_NAME}, {DB_COL_ISO}, {DB_COL_F_NUMBER}, {DB_COL_FOCAL_LENGTH} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_SHOT_READ} = TRUE;");
+    let mut statement = connection.prepare(query)?;
+    let mut settings = HashMap::new();
+
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        let iso = statement.read::<Option<i64>, _>(DB_COL_ISO)?;
+        let f_number = statement.read::<Option<f64>, _>(DB_COL_F_NUMBER)?;
+        let focal_length = statement.read::<Option<f64>, _>(DB_COL_FOCAL_LENGTH)?;
+        settings.insert(
+            name,
+            ShotSettings {
+                iso: iso.map(|x| x as u32),
+                f_number,
+                focal_length,
+            },
+        );
+    }
+
+    Ok(settings)
+}
+
+/// exposure scores of every image that has been analysed
+pub fn get_exposures(connection: Arc<Mutex<Connection>>) -> Result<HashMap<String, f64>, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("SELECT {DB_COL_NAME}, MAX({DB_COL_EXPOSURE}) AS {DB_COL_EXPOSURE} FROM {DB_TABLE_PHOTOS} WHERE NOT {DB_COL_EXPOSURE} IS NULL GROUP BY {DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let mut exposures = HashMap::new();
+
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        let exposure = statement.read::<f64, _>(DB_COL_EXPOSURE)?;
+        exposures.insert(name, exposure);
+    }
+
+    Ok(exposures)
+}
+
+/// `group_id` is shared by neighbouring photos that look the same, none when a photo looks different
+pub fn set_group_ids(
+    group_ids: &[(String, Option<i64>)],
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+
+    // written in one transaction rather than waiting for the disk after every photo
+    connection.execute("BEGIN;")?;
+    let result = write_group_ids(group_ids, &connection);
+    match result {
+        Ok(()) => connection.execute("COMMIT;")?,
+        Err(_) => connection.execute("ROLLBACK;")?,
+    }
+    result
+}
+
+fn write_group_ids(
+    group_ids: &[(String, Option<i64>)],
+    connection: &Connection,
+) -> Result<(), Error> {
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_GROUP_ID} = :{DB_COL_GROUP_ID} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    for (name, group_id) in group_ids {
+        let mut statement = connection.prepare(&query)?;
+        let group_id = group_id.map_or(Value::Null, Value::Integer);
+
+        statement.bind::<&[(_, Value)]>(
+            &[
+                (format!(":{DB_COL_GROUP_ID}").as_str(), group_id),
+                (format!(":{DB_COL_NAME}").as_str(), name.as_str().into()),
+            ][..],
+        )?;
+
+        statement.next()?;
+    }
+    Ok(())
+}
+
+/// groups of photos that look the same, from the last similarity scan
+pub fn get_group_ids(connection: Arc<Mutex<Connection>>) -> Result<HashMap<String, i64>, Error> {
+    let connection = connection.lock().unwrap();
+
+    let query = format!("SELECT {DB_COL_NAME}, MAX({DB_COL_GROUP_ID}) AS {DB_COL_GROUP_ID} FROM {DB_TABLE_PHOTOS} WHERE NOT {DB_COL_GROUP_ID} IS NULL GROUP BY {DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    let mut group_ids = HashMap::new();
+
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        let group_id = statement.read::<i64, _>(DB_COL_GROUP_ID)?;
+        group_ids.insert(name, group_id);
+    }
+
+    Ok(group_ids)
+}
+
+pub fn try_get_shared_image(
+    hash: &str,
     size: UVec2,
     connection: Arc<Mutex<Connection>>,
 ) -> Result<Option<Vec<u8>>, Error> {
     let connection = connection.lock().unwrap();
 
-    // even if there is an entry in the db there may not yet be a resized image
     let query = format!(
-        "SELECT {DB_COL_RESIZED} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME} AND {DB_COL_X_RES} = :{DB_COL_X_RES} AND {DB_COL_Y_RES} = :{DB_COL_Y_RES} AND NOT {DB_COL_RESIZED} IS NULL;"
+        "SELECT {DB_COL_RESIZED} FROM {DB_TABLE_SHARED} WHERE {DB_COL_HASH} = :{DB_COL_HASH} AND {DB_COL_X_RES} = :{DB_COL_X_RES} AND {DB_COL_Y_RES} = :{DB_COL_Y_RES};"
     );
 
     let mut statement = connection.prepare(query)?;
@@ -63,7 +869,7 @@ pub fn try_get_image_from_db(
 
     statement.bind::<&[(_, Value)]>(
         &[
-            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+            (format!(":{DB_COL_HASH}").as_str(), hash.into()),
             (format!(":{DB_COL_X_RES}").as_str(), x.into()),
             (format!(":{DB_COL_Y_RES}").as_str(), y.into()),
         ][..],
@@ -78,36 +884,28 @@ pub fn try_get_image_from_db(
     }
 }
 
-pub fn insert_image(
-    name: &str,
+pub fn insert_shared_image(
+    hash: &str,
     size: UVec2,
     resized: &[u8],
-    metadata: Option<ImageMetadata>,
     connection: Arc<Mutex<Connection>>,
 ) -> Result<(), Error> {
     let connection = connection.lock().unwrap();
 
+    // another instance of the viewer may have resized the same file in the meantime
     let query = format!(
-        "INSERT INTO {DB_TABLE_PHOTOS} VALUES (:{DB_COL_NAME}, :{DB_COL_X_RES}, :{DB_COL_Y_RES}, :{DB_COL_RESIZED}, :{DB_COL_IS_STARRED}, :{DB_COL_DATE_TIME});"
+        "INSERT OR IGNORE INTO {DB_TABLE_SHARED} ({DB_COL_HASH}, {DB_COL_X_RES}, {DB_COL_Y_RES}, {DB_COL_RESIZED}) VALUES (:{DB_COL_HASH}, :{DB_COL_X_RES}, :{DB_COL_Y_RES}, :{DB_COL_RESIZED});"
     );
     let mut statement = connection.prepare(query)?;
     let x = size.x as i64;
     let y = size.y as i64;
-    let is_starred: i64 = 0;
-
-    let date_time = match metadata {
-        Some(metadata) => metadata.try_get_timestamp_from_date_time(),
-        _ => 0,
-    };
 
     statement.bind::<&[(_, Value)]>(
         &[
-            (format!(":{DB_COL_NAME}").as_str(), name.into()),
+            (format!(":{DB_COL_HASH}").as_str(), hash.into()),
             (format!(":{DB_COL_X_RES}").as_str(), x.into()),
             (format!(":{DB_COL_Y_RES}").as_str(), y.into()),
             (format!(":{DB_COL_RESIZED}").as_str(), resized.into()),
-            (format!(":{DB_COL_IS_STARRED}").as_str(), is_starred.into()),
-            (format!(":{DB_COL_DATE_TIME}").as_str(), date_time.into()),
         ][..],
     )?;
 
@@ -115,34 +913,112 @@ pub fn insert_image(
     Ok(())
 }
 
-pub fn update_image_is_starred(
+/// Remember the capture date and time of an image read before it has been cached.
+/// An image without a row gets one without a resized image
+pub fn update_date_time(
     name: &str,
-    is_starred: bool,
+    date_time: DateTime<FixedOffset>,
     connection: Arc<Mutex<Connection>>,
 ) -> Result<(), Error> {
     let connection = connection.lock().unwrap();
-    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_IS_STARRED} = :{DB_COL_IS_STARRED} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
-    let mut statement = connection.prepare(query)?;
-    let is_starred = is_starred as i64;
+    let (date_time, utc_offset, nanosecond) = capture_time_values(Some(date_time));
 
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_DATE_TIME} = :{DB_COL_DATE_TIME}, {DB_COL_UTC_OFFSET} = :{DB_COL_UTC_OFFSET}, {DB_COL_NANOSECOND} = :{DB_COL_NANOSECOND} WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
     statement.bind::<&[(_, Value)]>(
         &[
-            (format!(":{DB_COL_IS_STARRED}").as_str(), is_starred.into()),
+            (format!(":{DB_COL_DATE_TIME}").as_str(), date_time.clone()),
+            (format!(":{DB_COL_UTC_OFFSET}").as_str(), utc_offset.clone()),
+            (format!(":{DB_COL_NANOSECOND}").as_str(), nanosecond.clone()),
             (format!(":{DB_COL_NAME}").as_str(), name.into()),
         ][..],
     )?;
+    statement.next()?;
+
+    ensure_placeholder_row(
+        &connection,
+        name,
+        &[
+            (DB_COL_DATE_TIME, date_time),
+            (DB_COL_UTC_OFFSET, utc_offset),
+            (DB_COL_NANOSECOND, nanosecond),
+        ],
+    )
+}
+
+/// remember that the image has no capture date in its exif
+pub fn set_no_date(name: &str, connection: Arc<Mutex<Connection>>) -> Result<(), Error> {
+    let connection = connection.lock().unwrap();
+    let query = format!("UPDATE {DB_TABLE_PHOTOS} SET {DB_COL_NO_DATE} = TRUE WHERE {DB_COL_NAME} = :{DB_COL_NAME};");
+    let mut statement = connection.prepare(query)?;
+    statement.bind::<&[(_, Value)]>(&[(format!(":{DB_COL_NAME}").as_str(), name.into())][..])?;
+    statement.next()?;
 
+    ensure_placeholder_row(&connection, name, &[(DB_COL_NO_DATE, Value::Integer(1))])
+}
+
+/// Give an image that has no row yet one without a resized image, holding the values in `columns`, so that
+/// what was read from its exif is kept before it has been cached. A resolution of zero never matches a screen
+/// so the image is still resized by the cache thread
+fn ensure_placeholder_row(
+    connection: &Connection,
+    name: &str,
+    columns: &[(&str, Value)],
+) -> Result<(), Error> {
+    // the columns that have no default, unless they are given
+    let defaults: Vec<&str> = [
+        DB_COL_X_RES,
+        DB_COL_Y_RES,
+        DB_COL_IS_STARRED,
+        DB_COL_DATE_TIME,
+    ]
+    .into_iter()
+    .filter(|x| !columns.iter().any(|(column, _)| column == x))
+    .collect();
+    let names: Vec<&str> = defaults
+        .iter()
+        .copied()
+        .chain(columns.iter().map(|(column, _)| *column))
+        .collect();
+    let values: Vec<String> = defaults
+        .iter()
+        .map(|_| "0".to_owned())
+        .chain(columns.iter().map(|(column, _)| format!(":{column}")))
+        .collect();
+
+    let query = format!(
+        "INSERT INTO {DB_TABLE_PHOTOS} ({DB_COL_NAME}, {}) SELECT :{DB_COL_NAME}, {} WHERE NOT EXISTS (SELECT 1 FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NAME} = :{DB_COL_NAME});",
+        names.join(", "),
+        values.join(", ")
+    );
+    let mut statement = connection.prepare(query)?;
+    let keys: Vec<String> = columns
+        .iter()
+        .map(|(column, _)| format!(":{column}"))
+        .collect();
+    let name_key = format!(":{DB_COL_NAME}");
+    let bindings: Vec<(&str, Value)> = [(name_key.as_str(), name.into())]
+        .into_iter()
+        .chain(
+            keys.iter()
+                .map(String::as_str)
+                .zip(columns.iter().map(|(_, value)| value.clone())),
+        )
+        .collect();
+    statement.bind::<&[(_, Value)]>(&bindings[..])?;
     statement.next()?;
+
     Ok(())
 }
 
-pub fn get_starred_image_names(
+/// names of the images whose exif has been read and has no capture date
+pub fn get_no_date_image_names(
     connection: Arc<Mutex<Connection>>,
 ) -> Result<HashSet<String>, Error> {
     let connection = connection.lock().unwrap();
 
     let query =
-        format!("SELECT {DB_COL_NAME} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_IS_STARRED} = TRUE;");
+        format!("SELECT {DB_COL_NAME} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_NO_DATE} = TRUE;");
     let mut statement = connection.prepare(query)?;
     let mut names = HashSet::new();
 
@@ -154,17 +1030,102 @@ pub fn get_starred_image_names(
     Ok(names)
 }
 
+/// capture date and time of every image that has been cached and has one
+pub fn get_date_times(
+    connection: Arc<Mutex<Connection>>,
+) -> Result<HashMap<String, DateTime<FixedOffset>>, Error> {
+    let connection = connection.lock().unwrap();
+
+    // when the rows of an image disagree the most recently written one with an offset from UTC wins, rows
+    // written before the offset was recorded are on the camera's local time and are only used as a last resort
+    let query = format!("SELECT {DB_COL_NAME}, {DB_COL_DATE_TIME}, {DB_COL_UTC_OFFSET}, {DB_COL_NANOSECOND} FROM {DB_TABLE_PHOTOS} WHERE {DB_COL_DATE_TIME} > 0 ORDER BY {DB_COL_UTC_OFFSET} IS NOT NULL, rowid;");
+    let mut statement = connection.prepare(query)?;
+    let mut date_times = HashMap::new();
+
+    while let State::Row = statement.next()? {
+        let name = statement.read::<String, _>(DB_COL_NAME)?;
+        let date_time = statement.read::<i64, _>(DB_COL_DATE_TIME)?;
+        let utc_offset = statement.read::<Option<i64>, _>(DB_COL_UTC_OFFSET)?;
+        let nanosecond = statement.read::<Option<i64>, _>(DB_COL_NANOSECOND)?;
+        if let Some(date_time) = capture_time_from_values(date_time, utc_offset, nanosecond) {
+            date_times.insert(name, date_time);
+        }
+    }
+
+    Ok(date_times)
+}
+
+/// A capture time is stored as a unix timestamp in the date_time column, with the offset from UTC the camera
+/// recorded in seconds and the fraction of a second in nanoseconds alongside it. Unknown times are 0
+fn capture_time_values(date_time: Option<DateTime<FixedOffset>>) -> (Value, Value, Value) {
+    match date_time {
+        Some(date_time) => (
+            Value::Integer(date_time.timestamp()),
+            Value::Integer(date_time.offset().local_minus_utc() as i64),
+            Value::Integer(date_time.timestamp_subsec_nanos() as i64),
+        ),
+        None => (Value::Integer(0), Value::Null, Value::Null),
+    }
+}
+
+/// the reverse of `capture_time_values`, times stored before the offset was recorded are on UTC
+fn capture_time_from_values(
+    timestamp: i64,
+    utc_offset: Option<i64>,
+    nanosecond: Option<i64>,
+) -> Option<DateTime<FixedOffset>> {
+    let utc = NaiveDateTime::from_timestamp_opt(timestamp, nanosecond.unwrap_or(0) as u32)?;
+    let offset = FixedOffset::east_opt(utc_offset.unwrap_or(0) as i32)?;
+    Some(offset.from_utc_datetime(&utc))
+}
+
 fn create_schema(connection: &Connection) -> Result<(), Error> {
     let query = format!("DROP TABLE IF EXISTS {DB_TABLE_PHOTOS};");
     connection.execute(query)?;
 
     let query = format!("CREATE TABLE {DB_TABLE_PHOTOS} ({DB_COL_NAME} TEXT, {DB_COL_X_RES} INTEGER, {DB_COL_Y_RES} INTEGER, {DB_COL_RESIZED} BLOB, {DB_COL_IS_STARRED} INTEGER, {DB_COL_DATE_TIME} INTEGER);");
     connection.execute(query)?;
+    add_missing_columns(connection)?;
+    add_lookup_index(connection)?;
+    add_settings_table(connection)?;
+    Ok(())
+}
+
+fn add_settings_table(connection: &Connection) -> Result<(), Error> {
+    let query = format!("CREATE TABLE IF NOT EXISTS {DB_TABLE_SETTINGS} ({DB_COL_KEY} TEXT PRIMARY KEY, {DB_COL_VALUE} TEXT);");
+    connection.execute(query)?;
+    Ok(())
+}
+
+/// images are looked up by name and resolution every time the photo changes
+fn add_lookup_index(connection: &Connection) -> Result<(), Error> {
+    let query = format!("CREATE INDEX IF NOT EXISTS {DB_INDEX_LOOKUP} ON {DB_TABLE_PHOTOS} ({DB_COL_NAME}, {DB_COL_X_RES}, {DB_COL_Y_RES});");
+    connection.execute(query)?;
+    Ok(())
+}
+
+fn add_missing_columns(connection: &Connection) -> Result<(), Error> {
+    let query = format!("PRAGMA table_info({DB_TABLE_PHOTOS});");
+    let mut statement = connection.prepare(query)?;
+    let mut existing = HashSet::new();
+
+    while let State::Row = statement.next()? {
+        existing.insert(statement.read::<String, _>("name")?);
+    }
+
+    for (column, definition) in DB_ADDED_COLUMNS {
+        if !existing.contains(*column) {
+            info!("Adding column {column} to {DB_TABLE_PHOTOS}");
+            let query = format!("ALTER TABLE {DB_TABLE_PHOTOS} ADD COLUMN {column} {definition};");
+            connection.execute(query)?;
+        }
+    }
+
     Ok(())
 }
 
 fn schema_is_ok(connection: &Connection) -> Result<bool, Error> {
-    let query = format!("SELECT {DB_COL_NAME}, {DB_COL_X_RES}, {DB_COL_Y_RES}, {DB_COL_RESIZED}, {DB_COL_IS_STARRED}, {DB_COL_DATE_TIME} FROM {DB_TABLE_PHOTOS} LIMIT 1;");
+    let query = format!("SELECT {DB_COL_NAME}, {DB_COL_X_RES}, {DB_COL_Y_RES}, {DB_COL_RESIZED}, {DB_COL_IS_STARRED}, {DB_COL_DATE_TIME}, {DB_COL_PROTECTED}, {DB_COL_RATING}, {DB_COL_ROTATION}, {DB_COL_COLOR_LABEL} FROM {DB_TABLE_PHOTOS} LIMIT 1;");
     let mut statement = connection.prepare(query)?;
 
     // read the first row of the photos table
@@ -175,6 +1136,10 @@ fn schema_is_ok(connection: &Connection) -> Result<bool, Error> {
         statement.read::<Vec<u8>, _>(DB_COL_RESIZED)?;
         statement.read::<i64, _>(DB_COL_IS_STARRED)?;
         statement.read::<i64, _>(DB_COL_DATE_TIME)?;
+        statement.read::<i64, _>(DB_COL_PROTECTED)?;
+        statement.read::<i64, _>(DB_COL_RATING)?;
+        statement.read::<i64, _>(DB_COL_ROTATION)?;
+        statement.read::<i64, _>(DB_COL_COLOR_LABEL)?;
         Ok(true)
     } else {
         // no rows, we might as well recreate the schema
@@ -183,6 +1148,16 @@ fn schema_is_ok(connection: &Connection) -> Result<bool, Error> {
 }
 
 fn check_schema(connection: &Connection) -> Result<(), Error> {
+    if let Err(e) = add_missing_columns(connection) {
+        info!("Unable to upgrade database schema: {e:?}");
+    }
+    if let Err(e) = add_lookup_index(connection) {
+        info!("Unable to add the lookup index: {e:?}");
+    }
+    if let Err(e) = add_settings_table(connection) {
+        info!("Unable to add the settings table: {e:?}");
+    }
+
     match schema_is_ok(connection) {
         Ok(true) => Ok(()),
         Ok(false) => {
@@ -198,17 +1173,60 @@ fn check_schema(connection: &Connection) -> Result<(), Error> {
     }
 }
 
+/// a sqlite3 database of thumbnails keyed by file contents rather than file name
+pub fn get_or_create_shared_db(path: &str) -> Result<Connection, Error> {
+    fs::create_dir_all(path)?;
+    let db_file_name = disk::get_full_path(path, SHARED_DB_FILE_NAME)?;
+    info!("Opening shared database: {db_file_name}");
+    let connection = sqlite::open(&db_file_name)?;
+    let query = format!("CREATE TABLE IF NOT EXISTS {DB_TABLE_SHARED} ({DB_COL_HASH} TEXT, {DB_COL_X_RES} INTEGER, {DB_COL_Y_RES} INTEGER, {DB_COL_RESIZED} BLOB, PRIMARY KEY ({DB_COL_HASH}, {DB_COL_X_RES}, {DB_COL_Y_RES}));");
+    connection.execute(query)?;
+    Ok(connection)
+}
+
 pub fn get_or_create_db(path: &str) -> Result<Connection, Error> {
     // a sqlite3 database
-    let db_file_name = disk::get_full_path(path, "thumbnails.db");
+    let db_file_name = disk::get_full_path(path, "thumbnails.db")?;
+    open_db(&db_file_name)
+}
+
+/// A database for a folder that cannot be written to, kept in the user's cache folder instead.
+/// If that is not possible either the database is in memory and is lost when the viewer exits
+pub fn get_or_create_read_only_db(path: &str) -> Result<Connection, Error> {
+    if let Some(dir) = disk::user_cache_dir() {
+        // one database per folder, named after the folder
+        let name = format!("{}.db", blake3::hash(path.as_bytes()).to_hex());
+        let db_file_name = dir.join(name);
+        match fs::create_dir_all(&dir) {
+            Ok(()) => match db_file_name.to_str() {
+                Some(db_file_name) => return open_db(db_file_name),
+                None => warn!("Unable to use cache folder {dir:?} as it is not valid unicode"),
+            },
+            Err(e) => warn!("Unable to create cache folder {dir:?}: {e:?}"),
+        }
+    }
+
+    info!("Using an in memory database");
+    let connection = sqlite::open(":memory:")?;
+    create_schema(&connection)?;
+    Ok(connection)
+}
+
+fn open_db(db_file_name: &str) -> Result<Connection, Error> {
     info!("Opening database: {db_file_name}");
-    if Path::new(&db_file_name).exists() {
-        let connection = sqlite::open(&db_file_name)?;
+    let exists = Path::new(db_file_name).exists();
+    let connection = sqlite::open(db_file_name)?;
+
+    // the default rollback journal is used because wal mode needs shared memory, which does not work for
+    // folders on a network share. A database left in wal mode by an earlier version is switched back
+    if let Err(e) = connection.execute("PRAGMA journal_mode=DELETE;") {
+        info!("Unable to set the journal mode: {e:?}");
+    }
+
+    if exists {
         check_schema(&connection)?;
-        Ok(connection)
     } else {
-        let connection = sqlite::open(&db_file_name)?;
         create_schema(&connection)?;
-        Ok(connection)
     }
+    Ok(connection)
 }
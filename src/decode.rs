@@ -0,0 +1,156 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use image::DynamicImage;
+use log::{error, info};
+use speedy2d::window::UserEventSender;
+
+use crate::metadata::ImageMetadata;
+use crate::{load_image, Error};
+
+/// A full size image and its exif, as returned by `load_image`
+pub struct Decoded {
+    pub img: DynamicImage,
+    pub metadata: Option<ImageMetadata>,
+}
+
+/// name of the image, the quarter turns chosen by the user and whether the exif orientation was used
+type Key = (String, u8, bool);
+
+struct State {
+    // most recently used at the back
+    entries: VecDeque<(Key, Arc<Decoded>)>,
+    /// the image to decode next. Only the latest request is kept so that quickly moving through
+    /// the folder does not leave the current image waiting behind ones that are no longer shown
+    queued: Option<Key>,
+    /// the image being decoded now
+    decoding: Option<Key>,
+    /// why an image could not be decoded, reported by the next `get`
+    failed: HashMap<Key, String>,
+    stopped: bool,
+}
+
+impl State {
+    fn evict(&mut self, budget: usize) {
+        // the newest image is kept even when it is bigger than the budget by itself
+        while self.entries.len() > 1 && self.size() > budget {
+            self.entries.pop_front();
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|(_, x)| x.img.as_bytes().len())
+            .sum()
+    }
+}
+
+/// Full size images decoded on a background thread so that the window keeps drawing while a large
+/// photo is decoded. The least recently used images are dropped when they take up more than the budget
+pub struct DecodeCache {
+    state: Arc<(Mutex<State>, Condvar)>,
+    exif_orientation: Arc<AtomicBool>,
+}
+
+impl DecodeCache {
+    /// `budget` is in bytes of decoded pixels, the window is woken by `user_event_sender` when an image is ready
+    pub fn new(
+        path: String,
+        budget: usize,
+        exif_orientation: Arc<AtomicBool>,
+        user_event_sender: Arc<Mutex<UserEventSender<()>>>,
+    ) -> Self {
+        let state = Arc::new((
+            Mutex::new(State {
+                entries: VecDeque::new(),
+                queued: None,
+                decoding: None,
+                failed: HashMap::new(),
+                stopped: false,
+            }),
+            Condvar::new(),
+        ));
+
+        let state_t = state.clone();
+        thread::spawn(move || {
+            let (lock, condvar) = &*state_t;
+            loop {
+                let mut state = lock.lock().unwrap();
+                let key = loop {
+                    if state.stopped {
+                        return;
+                    }
+                    if let Some(key) = state.queued.take() {
+                        break key;
+                    }
+                    state = condvar.wait(state).unwrap();
+                };
+                state.decoding = Some(key.clone());
+                drop(state);
+
+                let result = load_image(&path, &key.0, key.1, key.2);
+
+                let mut state = lock.lock().unwrap();
+                state.decoding = None;
+                match result {
+                    Ok((img, metadata)) => {
+                        state
+                            .entries
+                            .push_back((key, Arc::new(Decoded { img, metadata })));
+                        state.evict(budget);
+                    }
+                    Err(e) => {
+                        error!("Unable to decode {}: {e:?}", key.0);
+                        state.failed.insert(key, e.to_string());
+                    }
+                }
+                drop(state);
+
+                let locked = user_event_sender.lock().unwrap();
+                locked.send_event(()).unwrap();
+            }
+        });
+
+        Self {
+            state,
+            exif_orientation,
+        }
+    }
+
+    /// The decoded image, or none while it is decoded in the background. Asking for another image
+    /// before this one has started decoding means this one is not decoded
+    pub fn get(&self, name: &str, rotation: u8) -> Result<Option<Arc<Decoded>>, Error> {
+        let exif_orientation = self.exif_orientation.load(Ordering::Relaxed);
+        let key = (name.to_owned(), rotation, exif_orientation);
+        let (lock, condvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        if let Some(message) = state.failed.remove(&key) {
+            return Err(Error::Decode(message));
+        }
+
+        if let Some(index) = state.entries.iter().position(|(x, _)| *x == key) {
+            let entry = state.entries.remove(index).expect("index is in range");
+            let decoded = entry.1.clone();
+            state.entries.push_back(entry);
+            return Ok(Some(decoded));
+        }
+
+        if state.decoding.as_ref() != Some(&key) && state.queued.as_ref() != Some(&key) {
+            info!("Decoding {name} in the background");
+            state.queued = Some(key);
+            condvar.notify_one();
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for DecodeCache {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.state;
+        lock.lock().unwrap().stopped = true;
+        condvar.notify_one();
+    }
+}
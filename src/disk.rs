@@ -2,14 +2,46 @@ use std::{
     collections::HashMap,
     fs,
     path::{Path, PathBuf},
+    process::Command,
     str::FromStr,
 };
 
-use crate::{Error, ImageNamePair};
+use chrono::{DateTime, FixedOffset};
+use glob::{MatchOptions, Pattern};
+use log::warn;
 
-pub fn get_file_names(path: &str) -> Result<Vec<ImageNamePair>, Error> {
-    let jpegs = get_image_file_names(path)?;
-    let others = get_other_file_names(path)?;
+use crate::config::{DuplicateStems, ExportLayout};
+use crate::{metadata, Error, ImageNamePair, Original};
+
+const IGNORE_FILE_NAME: &str = ".photofarmignore";
+const MANIFEST_FILE_NAME: &str = "selected";
+// deleted images are moved here rather than being deleted
+const TRASH_FOLDER_NAME: &str = "trash";
+// photos exported by date go here when they have no capture date
+const UNDATED_FOLDER_NAME: &str = "undated";
+const XMP_NAMESPACE: &str = "http://ns.adobe.com/xap/1.0/";
+// xmp has no standard property for a starred photo
+const PHOTO_FARM_NAMESPACE: &str = "https://github.com/ninjasource/photo-farm/ns/1.0/";
+
+/// extensions of the photos that are shown, everything else is matched to them by name
+#[cfg(not(feature = "heif"))]
+pub const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "tif", "tiff"];
+#[cfg(feature = "heif")]
+pub const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "tif", "tiff", "heic", "heif"];
+
+/// raw files are shown using their embedded preview when there is no jpeg with the same name
+pub const RAW_EXTENSIONS: &[&str] = &[
+    "cr2", "nef", "nrw", "arw", "dng", "orf", "pef", "raf", "rw2", "srw",
+];
+
+pub fn get_file_names(
+    path: &str,
+    duplicate_stems: DuplicateStems,
+) -> Result<Vec<ImageNamePair>, Error> {
+    let ignore_patterns = get_ignore_patterns(path);
+    let (jpegs, others) = list_folder(path)?;
+    let jpegs = remove_ignored(jpegs, &ignore_patterns);
+    let others = remove_ignored(others, &ignore_patterns);
 
     // build a lookup of all file names that are not jpegs
     let mut lookup: HashMap<String, Vec<String>> = HashMap::new();
@@ -21,119 +53,877 @@ pub fn get_file_names(path: &str) -> Result<Vec<ImageNamePair>, Error> {
             .or_insert(vec![other]);
     }
 
+    let jpegs: Vec<(String, Vec<String>)> = match duplicate_stems {
+        DuplicateStems::Separate => jpegs.into_iter().map(|x| (x, vec![])).collect(),
+        DuplicateStems::PreferJpg => merge_same_stem(jpegs, "jpg"),
+        DuplicateStems::PreferJpeg => merge_same_stem(jpegs, "jpeg"),
+    };
+
     // attempt to match other files with jpeg files by name
-    // in the unlikely event that we encounter a jpg and jpeg with the same name
-    // only one of the two jpg files will have other_files associated with it
-    let items: Vec<ImageNamePair> = jpegs
+    // jpegs are sorted by file name so when a jpg and jpeg share a name (and are kept separate)
+    // the other files always go to the first one in sorted order
+    let mut items: Vec<ImageNamePair> = jpegs
         .into_iter()
-        .map(|jpeg| {
+        .map(|(jpeg, mut other_file_names)| {
             let name = get_lowercase_name_without_extension(&jpeg);
-            match lookup.remove(&name) {
-                Some(files) => ImageNamePair {
-                    jpg_file_name: jpeg,
-                    other_file_names: files,
-                    date_time: None,
-                    is_starred: false,
-                },
-                None => ImageNamePair {
-                    jpg_file_name: jpeg,
-                    other_file_names: vec![],
-                    date_time: None,
-                    is_starred: false,
-                },
+            if let Some(files) = lookup.remove(&name) {
+                other_file_names.extend(files);
             }
+            let sidecars = take_full_name_sidecars(
+                &mut lookup,
+                std::iter::once(&jpeg).chain(other_file_names.iter()),
+            );
+            other_file_names.extend(sidecars);
+
+            new_image_name_pair(jpeg, other_file_names)
         })
         .collect();
 
+    // photos taken in raw only mode are shown by themselves, along with any sidecar files.
+    // A stem sorts before the full names of its files so their sidecars are still there to take
+    let mut names: Vec<String> = lookup.keys().cloned().collect();
+    names.sort();
+    for name in names {
+        let Some(mut files) = lookup.remove(&name) else {
+            continue;
+        };
+        if let Some(index) = files.iter().position(|x| is_raw(x)) {
+            let raw = files.remove(index);
+            let sidecars = take_full_name_sidecars(&mut lookup, [&raw]);
+            files.extend(sidecars);
+            items.push(new_image_name_pair(raw, files));
+        }
+    }
+    items.sort_by(|a, b| a.jpg_file_name.cmp(&b.jpg_file_name));
+
     Ok(items)
 }
 
-pub fn get_full_path(path: &str, name: &str) -> String {
-    PathBuf::from_str(path)
-        .unwrap_or_else(|_| panic!("not a falid path: {path}"))
-        .join(name)
-        .to_str()
-        .expect("full path is empty")
-        .to_owned()
+/// Sidecars named after the whole file name of a photo, as darktable does e.g. IMG_0001.JPG.xmp,
+/// are under that name in the lookup rather than under the stem
+fn take_full_name_sidecars<'a>(
+    lookup: &mut HashMap<String, Vec<String>>,
+    file_names: impl IntoIterator<Item = &'a String>,
+) -> Vec<String> {
+    file_names
+        .into_iter()
+        .filter_map(|x| lookup.remove(&x.to_lowercase()))
+        .flatten()
+        .collect()
 }
 
-fn get_lowercase_name_without_extension(name: &str) -> String {
-    let name = Path::new(name).file_stem().expect("name is not a file");
-    let name = name.to_str().expect("file name is empty");
-    name.to_lowercase()
-}
-
-fn get_image_file_names(path: &str) -> Result<Vec<String>, Error> {
-    let directory = std::fs::read_dir(path)?;
-    let mut files: Vec<String> = directory
-        .filter_map(|x| {
-            let path = x.expect("cannot read directory");
-            let file_name = path.file_name();
-            let file_name = file_name.to_str().expect("image file name is empty");
-            if file_name.to_lowercase().ends_with(".jpg")
-                || file_name.to_lowercase().ends_with(".jpeg")
-            {
-                Some(file_name.to_owned())
-            } else {
-                None
+/// The photo a folder was opened at, shown while the rest of the folder is listed. Other files with the same
+/// name are only matched to it once the whole folder has been listed. None if it is not a jpg, as a raw file
+/// may turn out to belong to one
+pub fn get_opened_file_name(name: &str) -> Option<ImageNamePair> {
+    is_image(name).then(|| new_image_name_pair(name.to_owned(), vec![]))
+}
+
+pub fn new_image_name_pair(jpg_file_name: String, other_file_names: Vec<String>) -> ImageNamePair {
+    ImageNamePair {
+        jpg_file_name,
+        other_file_names,
+        date_time: None,
+        is_starred: false,
+        rating: 0,
+        rotation: 0,
+        color_label: 0,
+        is_protected: false,
+        is_flagged: false,
+        group_id: None,
+        original: None,
+    }
+}
+
+/// Match each proof with the full resolution files of the same name in the originals folder
+pub fn match_originals(
+    image_pairs: &mut [ImageNamePair],
+    originals_path: &str,
+    duplicate_stems: DuplicateStems,
+) -> Result<(), Error> {
+    let mut lookup: HashMap<String, ImageNamePair> =
+        get_file_names(originals_path, duplicate_stems)?
+            .into_iter()
+            .map(|x| (get_lowercase_name_without_extension(&x.jpg_file_name), x))
+            .collect();
+
+    for pair in image_pairs.iter_mut() {
+        let name = get_lowercase_name_without_extension(&pair.jpg_file_name);
+        match lookup.remove(&name) {
+            Some(original) => {
+                pair.original = Some(Original {
+                    path: originals_path.to_owned(),
+                    jpg_file_name: original.jpg_file_name,
+                    other_file_names: original.other_file_names,
+                })
             }
-        })
-        .collect();
-    files.sort();
+            None => warn!("No original found for proof {}", pair.jpg_file_name),
+        }
+    }
+
+    Ok(())
+}
+
+/// Collapse jpegs that share a name into one, using the one with the preferred extension.
+/// The rest are returned alongside it so that they are exported together
+fn merge_same_stem(jpegs: Vec<String>, preferred_extension: &str) -> Vec<(String, Vec<String>)> {
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut lookup: HashMap<String, usize> = HashMap::new();
+    for jpeg in jpegs {
+        let name = get_lowercase_name_without_extension(&jpeg);
+        match lookup.get(&name) {
+            Some(index) => groups[*index].push(jpeg),
+            None => {
+                lookup.insert(name, groups.len());
+                groups.push(vec![jpeg]);
+            }
+        }
+    }
 
-    Ok(files)
+    groups
+        .into_iter()
+        .map(|mut group| {
+            let index = group
+                .iter()
+                .position(|x| {
+                    x.to_lowercase()
+                        .ends_with(&format!(".{preferred_extension}"))
+                })
+                .unwrap_or(0);
+            let jpeg = group.remove(index);
+            (jpeg, group)
+        })
+        .collect()
 }
 
-fn get_other_file_names(path: &str) -> Result<Vec<String>, Error> {
-    let directory = std::fs::read_dir(path)?;
-    let mut files: Vec<String> = directory
-        .filter_map(|x| {
-            let path = x.expect("cannot read directory");
-            let path = path.file_name();
-            let path = path.to_str().expect("image file name is empty");
-            if !path.to_lowercase().ends_with(".jpg") && !path.to_lowercase().ends_with(".jpeg") {
-                Some(path.to_owned())
-            } else {
+/// Glob patterns (one per line) read from the optional .photofarmignore file in the folder.
+/// Blank lines and lines starting with # are skipped
+fn get_ignore_patterns(path: &str) -> Vec<Pattern> {
+    let contents = match get_full_path(path, IGNORE_FILE_NAME).map(fs::read_to_string) {
+        Ok(Ok(contents)) => contents,
+        _ => return vec![],
+    };
+
+    contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| match Pattern::new(line) {
+            Ok(pattern) => Some(pattern),
+            Err(e) => {
+                warn!("Invalid pattern '{line}' in {IGNORE_FILE_NAME}: {e}");
                 None
             }
         })
-        .collect();
-    files.sort();
+        .collect()
+}
+
+fn remove_ignored(file_names: Vec<String>, patterns: &[Pattern]) -> Vec<String> {
+    let options = MatchOptions {
+        case_sensitive: false,
+        ..MatchOptions::new()
+    };
 
-    Ok(files)
+    file_names
+        .into_iter()
+        .filter(|name| !patterns.iter().any(|x| x.matches_with(name, options)))
+        .collect()
 }
 
-pub fn export_image(path: &str, name: &str, image: &[u8]) -> Result<(), Error> {
-    let mut to_path =
-        PathBuf::from_str(path).unwrap_or_else(|_| panic!("not a falid path: {path}"));
-    to_path.push("export");
-    let to_path = to_path.to_str().expect("path is empty");
-    fs::create_dir_all(to_path)?;
-    let to_file = get_full_path(to_path, name);
-    fs::write(to_file, image)?;
+/// false for folders on read only media (e.g. a DVD) or without write permission
+pub fn is_writable(path: &str) -> bool {
+    // permissions alone do not reveal read only media so try to create a file
+    let Ok(probe) = get_full_path(path, ".photofarm_write_test") else {
+        return false;
+    };
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+    {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            true
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            // left behind by a previous run that was interrupted
+            fs::remove_file(&probe).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+/// the user's cache folder for Photo Farm e.g. ~/.cache/photo-farm, if one can be found
+pub fn user_cache_dir() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|x| PathBuf::from(x).join(".cache")))
+    };
+
+    dir.map(|x| x.join("photo-farm"))
+}
+
+/// the user's config folder for Photo Farm e.g. ~/.config/photo-farm, if one can be found
+pub fn user_config_dir() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|x| PathBuf::from(x).join(".config")))
+    };
+
+    dir.map(|x| x.join("photo-farm"))
+}
+
+/// hash of the file contents, identical files in different folders have the same hash
+pub fn content_hash(path: &str, name: &str) -> Result<String, Error> {
+    let file = fs::File::open(get_full_path(path, name)?)?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(file)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// the file `name` in the folder `path`, an error rather than a panic if the result is not valid unicode
+pub fn get_full_path(path: &str, name: &str) -> Result<String, Error> {
+    Path::new(path)
+        .join(name)
+        .into_os_string()
+        .into_string()
+        .map_err(|x| Error::InvalidPath(PathBuf::from(x)))
+}
+
+/// the name of a directory entry, files with names that are not valid unicode cannot be shown
+fn entry_file_name(entry: std::io::Result<fs::DirEntry>) -> Result<String, Error> {
+    entry?
+        .file_name()
+        .into_string()
+        .map_err(|x| Error::InvalidPath(PathBuf::from(x)))
+}
+
+fn get_lowercase_extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn is_image(name: &str) -> bool {
+    IMAGE_EXTENSIONS.contains(&get_lowercase_extension(name).as_str())
+}
+
+pub fn is_raw(name: &str) -> bool {
+    RAW_EXTENSIONS.contains(&get_lowercase_extension(name).as_str())
+}
+
+/// whether `list_photo_names` returns files with this name. Builds without the `heif` feature do not list
+/// HEIC / HEIF photos, even though a build with it may have stored them in the same database
+pub fn is_listed(name: &str) -> bool {
+    is_image(name) || is_raw(name)
+}
+
+fn is_jpeg(name: &str) -> bool {
+    matches!(get_lowercase_extension(name).as_str(), "jpg" | "jpeg")
+}
+
+/// HEIC / HEIF photos need their own decoder
+pub fn is_heif(name: &str) -> bool {
+    matches!(get_lowercase_extension(name).as_str(), "heic" | "heif")
+}
+
+fn get_lowercase_name_without_extension(name: &str) -> String {
+    // the whole name is used when it has no stem, e.g. ".."
+    Path::new(name)
+        .file_stem()
+        .and_then(|x| x.to_str())
+        .unwrap_or(name)
+        .to_lowercase()
+}
+
+/// The images and all other files in the folder, each sorted by name.
+/// The folder is only read once as this is slow for large folders, especially on network drives.
+/// Files with names that are not valid unicode are left out rather than failing the whole folder
+fn list_folder(path: &str) -> Result<(Vec<String>, Vec<String>), Error> {
+    let mut images = Vec::new();
+    let mut others = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let file_name = match entry_file_name(entry) {
+            Ok(file_name) => file_name,
+            Err(e) => {
+                warn!("Skipping a file in {path}: {e}");
+                continue;
+            }
+        };
+        if is_image(&file_name) {
+            images.push(file_name);
+        } else {
+            others.push(file_name);
+        }
+    }
+    images.sort();
+    others.sort();
+
+    Ok((images, others))
+}
+
+/// Show a file in the file manager, selected where the platform supports it. The path is passed as its own
+/// argument rather than through a shell so that nothing in a file name is run as a command
+pub fn reveal_in_file_manager(path: &str, name: &str) -> Result<(), Error> {
+    let file_name = get_full_path(path, name)?;
+
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = Command::new("explorer");
+        command.arg("/select,").arg(&file_name);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(&file_name);
+        command
+    };
+    // file managers on linux cannot be asked to select a file so the folder is opened instead
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = {
+        let mut command = Command::new("xdg-open");
+        command.arg(Path::new(&file_name).parent().unwrap_or(Path::new(path)));
+        command
+    };
+
+    command.spawn()?;
     Ok(())
 }
 
-pub fn export(path: &str, image_pairs: &[&ImageNamePair]) -> Result<(), Error> {
+/// names of the photos and raw files in the folder, whether or not they are ignored
+pub fn list_photo_names(path: &str) -> Result<Vec<String>, Error> {
+    let (mut images, others) = list_folder(path)?;
+    images.extend(others.into_iter().filter(|x| is_raw(x)));
+    Ok(images)
+}
+
+/// the export folder inside the folder being viewed, used unless another destination is chosen
+pub fn default_export_folder(path: &str) -> PathBuf {
     let mut to_path =
         PathBuf::from_str(path).unwrap_or_else(|_| panic!("not a falid path: {path}"));
     to_path.push("export");
-    let to_path = to_path.to_str().expect("path is empty");
-    fs::create_dir_all(to_path)?;
+    to_path
+}
+
+/// where a re-encoded photo is written, these are always jpegs. Other photos keep their extension in the name,
+/// `scan.png` becomes `scan.png.jpg`, so that they cannot overwrite a jpeg with the same stem
+fn exported_image_file(dest: &Path, name: &str) -> PathBuf {
+    if is_jpeg(name) {
+        dest.join(name)
+    } else {
+        dest.join(format!("{name}.jpg"))
+    }
+}
+
+pub fn exported_image_exists(dest: &Path, name: &str) -> bool {
+    exported_image_file(dest, name).exists()
+}
+
+/// write a re-encoded photo to the destination, a jpeg there with the same name is replaced
+pub fn export_image(dest: &Path, name: &str, image: &[u8]) -> Result<(), Error> {
+    fs::create_dir_all(dest)?;
+    fs::write(exported_image_file(dest, name), image)?;
+    Ok(())
+}
+
+/// write a re-encoded photo in place of the copy `export` has just made, a copied png or tiff is removed
+/// so that only the jpeg is left
+pub fn replace_exported_image(dest: &Path, name: &str, image: &[u8]) -> Result<(), Error> {
+    export_image(dest, name, image)?;
+    if !is_jpeg(name) {
+        fs::remove_file(dest.join(name))?;
+    }
+    Ok(())
+}
 
+/// the folder inside the export folder that a photo taken at `date_time` is exported to
+pub fn export_subfolder(
+    dest: &Path,
+    layout: ExportLayout,
+    date_time: Option<DateTime<FixedOffset>>,
+) -> PathBuf {
+    match (layout, date_time) {
+        (ExportLayout::Flat, _) => dest.to_path_buf(),
+        (ExportLayout::ByDate, Some(date_time)) => {
+            dest.join(date_time.format("%Y-%m-%d").to_string())
+        }
+        (ExportLayout::ByDate, None) => dest.join(UNDATED_FOLDER_NAME),
+    }
+}
+
+/// Copy the photos and their raw files to the destination folder, or to a folder in it for the day each was taken.
+/// Files already there are never overwritten. Returns the photos that were copied this time and the folder of each
+pub fn export<'a>(
+    path: &str,
+    dest: &Path,
+    image_pairs: &[&'a ImageNamePair],
+    layout: ExportLayout,
+) -> Result<Vec<(&'a ImageNamePair, PathBuf)>, Error> {
+    fs::create_dir_all(dest)?;
+
+    let mut copied = vec![];
+    for pair in image_pairs {
+        // proofs are exported as their full resolution original when there is one
+        let (from_path, jpg_file_name, other_file_names) = match &pair.original {
+            Some(original) => (
+                original.path.as_str(),
+                &original.jpg_file_name,
+                &original.other_file_names,
+            ),
+            None => (path, &pair.jpg_file_name, &pair.other_file_names),
+        };
+
+        // the date is read from the file when it was not cached, a proof may have lost its exif
+        let date_time = match layout {
+            ExportLayout::Flat => None,
+            ExportLayout::ByDate => pair
+                .date_time
+                .or_else(|| metadata::read_capture_time(from_path, jpg_file_name).ok()),
+        };
+        let folder = export_subfolder(dest, layout, date_time);
+        fs::create_dir_all(&folder)?;
+
+        let is_copied = copy_file(from_path, &folder, jpg_file_name)?;
+        for other in other_file_names.iter() {
+            copy_file(from_path, &folder, other)?;
+        }
+        // a photo that was exported before keeps the sidecar it has there
+        if is_copied {
+            write_xmp_sidecar(&folder, pair)?;
+            copied.push((*pair, folder.clone()));
+        }
+    }
+
+    Ok(copied)
+}
+
+/// Write the rating and star of an exported photo to its xmp sidecar so that other tools can see them.
+/// A sidecar that is already there, for example one written by Lightroom, keeps everything else in it
+pub fn write_xmp_sidecar(dest: &Path, pair: &ImageNamePair) -> Result<(), Error> {
+    let (jpg_file_name, other_file_names) = match &pair.original {
+        Some(original) => (&original.jpg_file_name, &original.other_file_names),
+        None => (&pair.jpg_file_name, &pair.other_file_names),
+    };
+    let file_name = match other_file_names
+        .iter()
+        .find(|x| get_lowercase_extension(x) == "xmp")
+    {
+        Some(xmp) => dest.join(xmp),
+        None => dest.join(Path::new(jpg_file_name).with_extension("xmp")),
+    };
+
+    let rating = pair.rating.to_string();
+    let starred = if pair.is_starred { "True" } else { "False" };
+    let xmp = if file_name.exists() {
+        let xmp = fs::read_to_string(&file_name)?;
+        let xmp =
+            set_xmp_property(&xmp, ("xmp", XMP_NAMESPACE), "Rating", &rating).and_then(|xmp| {
+                set_xmp_property(
+                    &xmp,
+                    ("photofarm", PHOTO_FARM_NAMESPACE),
+                    "Starred",
+                    starred,
+                )
+            });
+        match xmp {
+            Some(xmp) => xmp,
+            None => {
+                warn!("Not updating {file_name:?} because it has no rdf:Description");
+                return Ok(());
+            }
+        }
+    } else {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="{XMP_NAMESPACE}"
+    xmlns:photofarm="{PHOTO_FARM_NAMESPACE}"
+    xmp:Rating="{rating}"
+    photofarm:Starred="{starred}"/>
+ </rdf:RDF>
+</x:xmpmeta>
+"#
+        )
+    };
+
+    fs::write(file_name, xmp)?;
+    Ok(())
+}
+
+/// The sidecar with a property set to `value`, replacing the attribute or element already there or
+/// adding it to the first description. None when there is no description to add it to
+fn set_xmp_property(
+    xmp: &str,
+    (prefix, namespace): (&str, &str),
+    property: &str,
+    value: &str,
+) -> Option<String> {
+    let name = format!("{prefix}:{property}");
+
+    let attribute = format!("{name}=");
+    if let Some(start) = xmp.find(&attribute) {
+        let start = start + attribute.len();
+        let quote = xmp[start..].chars().next()?;
+        let start = start + quote.len_utf8();
+        let end = start + xmp[start..].find(quote)?;
+        return Some(format!("{}{value}{}", &xmp[..start], &xmp[end..]));
+    }
+
+    let element = format!("<{name}>");
+    if let Some(start) = xmp.find(&element) {
+        let start = start + element.len();
+        let end = start + xmp[start..].find('<')?;
+        return Some(format!("{}{value}{}", &xmp[..start], &xmp[end..]));
+    }
+
+    let description = "<rdf:Description";
+    let start = xmp.find(description)? + description.len();
+    let mut attributes = String::new();
+    if !xmp.contains(&format!("xmlns:{prefix}=")) {
+        attributes.push_str(&format!(" xmlns:{prefix}=\"{namespace}\""));
+    }
+    attributes.push_str(&format!(" {name}=\"{value}\""));
+    Some(format!("{}{attributes}{}", &xmp[..start], &xmp[start..]))
+}
+
+/// Write the names of the images and their raw files to selected.txt in the folder, one per line.
+/// Returns the name of the file written
+pub fn write_manifest(path: &str, image_pairs: &[&ImageNamePair]) -> Result<String, Error> {
+    let mut contents = String::new();
     for pair in image_pairs {
-        copy_file(path, to_path, &pair.jpg_file_name)?;
+        contents.push_str(&format!("{}\n", pair.jpg_file_name));
         for other in pair.other_file_names.iter() {
-            copy_file(path, to_path, other)?;
+            contents.push_str(&format!("{other}\n"));
         }
     }
 
+    let file_name = get_full_path(path, &format!("{MANIFEST_FILE_NAME}.txt"))?;
+    fs::write(&file_name, contents)?;
+    Ok(file_name)
+}
+
+/// The same images as `write_manifest` in selected.csv, one row per image with its raw files separated by `;`
+pub fn write_manifest_csv(path: &str, image_pairs: &[&ImageNamePair]) -> Result<String, Error> {
+    let mut csv = String::from("name,other_files,rating,color_label,date_taken\n");
+    for pair in image_pairs {
+        let color_label = match pair.color_label {
+            1 => "red",
+            2 => "yellow",
+            3 => "green",
+            _ => "",
+        };
+        let date_taken = pair
+            .date_time
+            .map(|x| x.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+
+        // quotes in a quoted csv field are escaped by doubling them
+        csv.push_str(&format!(
+            "\"{}\",\"{}\",{},{color_label},{date_taken}\n",
+            pair.jpg_file_name.replace('"', "\"\""),
+            pair.other_file_names.join(";").replace('"', "\"\""),
+            pair.rating,
+        ));
+    }
+
+    let file_name = get_full_path(path, &format!("{MANIFEST_FILE_NAME}.csv"))?;
+    fs::write(&file_name, csv)?;
+    Ok(file_name)
+}
+
+/// Move an image and its raw files into a trash folder so that it can be recovered by hand.
+/// Only the files in the folder being viewed are moved, never the originals of a proof
+pub fn move_to_trash(path: &str, pair: &ImageNamePair) -> Result<(), Error> {
+    let to_path = get_full_path(path, TRASH_FOLDER_NAME)?;
+    fs::create_dir_all(&to_path)?;
+
+    move_file(path, &to_path, &pair.jpg_file_name)?;
+    for other in pair.other_file_names.iter() {
+        move_file(path, &to_path, other)?;
+    }
+
     Ok(())
 }
 
-fn copy_file(from_path: &str, to_path: &str, name: &str) -> Result<(), Error> {
-    let from_file = get_full_path(from_path, name);
-    let to_file = get_full_path(to_path, name);
-    fs::copy(from_file, to_file)?;
+/// Move an image and its raw files out of the trash folder, undoing `move_to_trash`.
+/// Nothing is moved back over a file that has since been put in its place
+pub fn restore_from_trash(path: &str, pair: &ImageNamePair) -> Result<(), Error> {
+    let from_path = get_full_path(path, TRASH_FOLDER_NAME)?;
+    for name in std::iter::once(&pair.jpg_file_name).chain(pair.other_file_names.iter()) {
+        if Path::new(&get_full_path(path, name)?).exists() {
+            return Err(Error::AlreadyExists(name.clone()));
+        }
+    }
+
+    move_file(&from_path, path, &pair.jpg_file_name)?;
+    for other in pair.other_file_names.iter() {
+        move_file(&from_path, path, other)?;
+    }
+
     Ok(())
 }
+
+fn move_file(from_path: &str, to_path: &str, name: &str) -> Result<(), Error> {
+    let from_file = get_full_path(from_path, name)?;
+    let to_file = get_full_path(to_path, name)?;
+    fs::rename(from_file, to_file)?;
+    Ok(())
+}
+
+/// returns false when the file was already in the destination and has been left alone
+fn copy_file(from_path: &str, dest: &Path, name: &str) -> Result<bool, Error> {
+    let from_file = get_full_path(from_path, name)?;
+    let to_file = dest.join(name);
+    if to_file.exists() {
+        warn!("Not exporting {name} because {to_file:?} already exists");
+        return Ok(false);
+    }
+
+    fs::copy(from_file, to_file)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a folder of empty files in the temp folder, removed when the test is done
+    struct TestFolder(PathBuf);
+
+    impl TestFolder {
+        fn new(test: &str, files: &[&str]) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("photo-farm-{test}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            for file in files {
+                fs::write(path.join(file), b"").unwrap();
+            }
+            Self(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TestFolder {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// each photo shown and the files that go with it
+    fn names(items: &[ImageNamePair]) -> Vec<(&str, Vec<&str>)> {
+        items
+            .iter()
+            .map(|x| {
+                let others = x.other_file_names.iter().map(|x| x.as_str()).collect();
+                (x.jpg_file_name.as_str(), others)
+            })
+            .collect()
+    }
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn merge_same_stem_prefers_jpg() {
+        let jpegs = strings(&["IMG_0001.jpeg", "IMG_0001.jpg", "IMG_0002.JPEG"]);
+        let merged = merge_same_stem(jpegs, "jpg");
+        assert_eq!(
+            merged,
+            vec![
+                ("IMG_0001.jpg".to_owned(), strings(&["IMG_0001.jpeg"])),
+                ("IMG_0002.JPEG".to_owned(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_same_stem_prefers_jpeg() {
+        let jpegs = strings(&["IMG_0001.JPEG", "IMG_0001.JPG", "IMG_0002.jpg"]);
+        let merged = merge_same_stem(jpegs, "jpeg");
+        assert_eq!(
+            merged,
+            vec![
+                ("IMG_0001.JPEG".to_owned(), strings(&["IMG_0001.JPG"])),
+                ("IMG_0002.jpg".to_owned(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn merged_jpegs_keep_their_raw_files() {
+        let folder = TestFolder::new(
+            "merged_jpegs",
+            &[
+                "IMG_0001.jpg",
+                "IMG_0001.jpeg",
+                "IMG_0001.cr2",
+                "IMG_0002.jpg",
+                "IMG_0003.cr2",
+            ],
+        );
+
+        let items = get_file_names(folder.path(), DuplicateStems::PreferJpg).unwrap();
+        assert_eq!(
+            names(&items),
+            vec![
+                ("IMG_0001.jpg", vec!["IMG_0001.jpeg", "IMG_0001.cr2"]),
+                ("IMG_0002.jpg", vec![]),
+                ("IMG_0003.cr2", vec![]),
+            ]
+        );
+
+        let items = get_file_names(folder.path(), DuplicateStems::PreferJpeg).unwrap();
+        assert_eq!(
+            names(&items),
+            vec![
+                ("IMG_0001.jpeg", vec!["IMG_0001.jpg", "IMG_0001.cr2"]),
+                ("IMG_0002.jpg", vec![]),
+                ("IMG_0003.cr2", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn separate_jpegs_give_the_raw_to_the_first() {
+        let folder = TestFolder::new(
+            "separate_jpegs",
+            &["IMG_0001.jpg", "IMG_0001.jpeg", "IMG_0001.cr2"],
+        );
+        let items = get_file_names(folder.path(), DuplicateStems::Separate).unwrap();
+        assert_eq!(
+            names(&items),
+            vec![
+                ("IMG_0001.jpeg", vec!["IMG_0001.cr2"]),
+                ("IMG_0001.jpg", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn jpeg_with_two_raws() {
+        let folder = TestFolder::new(
+            "two_raws",
+            &[
+                "IMG_0001.JPG",
+                "IMG_0001.CR2",
+                "IMG_0001.dng",
+                "IMG_0002.jpg",
+            ],
+        );
+        let items = get_file_names(folder.path(), DuplicateStems::default()).unwrap();
+        assert_eq!(
+            names(&items),
+            vec![
+                ("IMG_0001.JPG", vec!["IMG_0001.CR2", "IMG_0001.dng"]),
+                ("IMG_0002.jpg", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn orphan_raws_are_shown_by_themselves() {
+        let folder = TestFolder::new(
+            "orphan_raws",
+            &[
+                "IMG_0001.jpg",
+                "IMG_0002.nef",
+                "IMG_0002.xmp",
+                "IMG_0003.cr2",
+                "notes.txt",
+            ],
+        );
+        let items = get_file_names(folder.path(), DuplicateStems::default()).unwrap();
+        assert_eq!(
+            names(&items),
+            vec![
+                ("IMG_0001.jpg", vec![]),
+                ("IMG_0002.nef", vec!["IMG_0002.xmp"]),
+                ("IMG_0003.cr2", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn sidecars_named_after_the_whole_file_name() {
+        let folder = TestFolder::new(
+            "darktable_sidecars",
+            &[
+                "IMG_0001.JPG",
+                "IMG_0001.JPG.xmp",
+                "IMG_0002.jpg",
+                "IMG_0002.CR2",
+                "IMG_0002.CR2.xmp",
+                "IMG_0003.nef",
+                "IMG_0003.nef.xmp",
+            ],
+        );
+        let items = get_file_names(folder.path(), DuplicateStems::default()).unwrap();
+        assert_eq!(
+            names(&items),
+            vec![
+                ("IMG_0001.JPG", vec!["IMG_0001.JPG.xmp"]),
+                ("IMG_0002.jpg", vec!["IMG_0002.CR2", "IMG_0002.CR2.xmp"]),
+                ("IMG_0003.nef", vec!["IMG_0003.nef.xmp"]),
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn names_that_are_not_unicode_are_skipped() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let folder = TestFolder::new("not_unicode", &["IMG_0001.jpg"]);
+        fs::write(folder.0.join(OsStr::from_bytes(b"IMG_\xff.jpg")), b"").unwrap();
+
+        let errors: Vec<Error> = fs::read_dir(&folder.0)
+            .unwrap()
+            .map(entry_file_name)
+            .filter_map(Result::err)
+            .collect();
+        assert!(matches!(errors.as_slice(), [Error::InvalidPath(_)]));
+
+        let items = get_file_names(folder.path(), DuplicateStems::default()).unwrap();
+        assert_eq!(names(&items), vec![("IMG_0001.jpg", vec![])]);
+        let photos = list_photo_names(folder.path()).unwrap();
+        assert_eq!(photos, strings(&["IMG_0001.jpg"]));
+    }
+
+    #[test]
+    fn only_photos_this_build_can_show_are_listed() {
+        assert!(is_listed("IMG_0001.JPG"));
+        assert!(is_listed("IMG_0001.CR2"));
+        assert!(!is_listed("IMG_0001.xmp"));
+        assert_eq!(is_listed("IMG_0001.HEIC"), cfg!(feature = "heif"));
+    }
+
+    #[test]
+    fn exported_pngs_do_not_overwrite_jpegs() {
+        let dest = Path::new("export");
+        assert_eq!(
+            exported_image_file(dest, "IMG_0001.JPG"),
+            dest.join("IMG_0001.JPG")
+        );
+        assert_eq!(
+            exported_image_file(dest, "IMG_0001.png"),
+            dest.join("IMG_0001.png.jpg")
+        );
+    }
+}
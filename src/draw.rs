@@ -1,70 +1,449 @@
-use image::DynamicImage;
+use image::{DynamicImage, Rgb, RgbImage};
+use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
 use speedy2d::color::Color;
 use speedy2d::dimen::{UVec2, Vec2};
-use speedy2d::font::{Font, TextAlignment, TextLayout, TextOptions};
+use speedy2d::font::{TextAlignment, TextOptions};
 use speedy2d::image::{ImageDataType, ImageHandle, ImageSmoothingMode};
+use speedy2d::shape::Rectangle;
 use speedy2d::Graphics2D;
 use std::io::Cursor;
-use std::sync::atomic::{AtomicI32, Ordering};
-use std::sync::Arc;
+use std::sync::OnceLock;
 
-use crate::calculate_position_middle;
+use crate::config::CalibrationConfig;
+use crate::fonts::FontSet;
+use crate::makernote::FocusPoint;
 use crate::metadata::ImageMetadata;
+use crate::progress::Progress;
+use crate::{calculate_position_middle, ImageNamePair, THUMBNAIL_SIZE};
 
-pub fn file_name(graphics: &mut Graphics2D, file_name: &str, font: &Font) {
+const EXIF_DUMP_LINE_HEIGHT: f32 = 26.0;
+const EXIF_DUMP_MAX_VALUE_CHARS: usize = 100;
+// space around each thumbnail in the grid
+const GRID_GAP: u32 = 8;
+// thumbnails are scaled down to this height in the filmstrip
+const FILMSTRIP_HEIGHT: f32 = 90.0;
+// room below the filmstrip for the labels and progress text
+const FILMSTRIP_BOTTOM_MARGIN: f32 = 40.0;
+
+// space above and below a table that is too tall for the screen
+const TABLE_MARGIN: f32 = 50.0;
+
+// widest a bar chart of camera settings is drawn, including its labels
+const CHART_MAX_WIDTH: f32 = 450.0;
+const CHART_ROW_HEIGHT: f32 = 28.0;
+// room to the left of the bars for the values and to the right for the counts
+const CHART_LABEL_WIDTH: f32 = 90.0;
+const CHART_COUNT_WIDTH: f32 = 60.0;
+
+// longest edge of the whole image shown in the corner while zooming
+pub const ZOOM_NAVIGATOR_SIZE: u32 = 200;
+
+// size of the color label swatch in the top left corner
+const COLOR_LABEL_SIZE: f32 = 24.0;
+
+/// the file name is moved along to make room for the color label swatch when there is one
+pub fn file_name(
+    graphics: &mut Graphics2D,
+    file_name: &str,
+    font: &FontSet,
+    has_color_label: bool,
+) {
     let text = font.layout_text(
         file_name,
         20.0,
         TextOptions::new().with_wrap_to_width(200.0, TextAlignment::Left),
     );
 
+    let x = if has_color_label {
+        COLOR_LABEL_SIZE + 20.0
+    } else {
+        10.0
+    };
+    graphics.draw_text(Vec2 { x, y: 5.0 }, Color::from_rgb(0.9, 0.9, 0.8), &text);
+}
+
+/// small note in the bottom left corner of the screen
+pub fn label(size: UVec2, graphics: &mut Graphics2D, font: &FontSet, label: &str) {
+    let text = font.layout_text(label, 20.0, TextOptions::new());
     graphics.draw_text(
-        Vec2 { x: 10.0, y: 5.0 },
+        Vec2::new(10.0, size.y as f32 - text.height() - 10.0),
         Color::from_rgb(0.9, 0.9, 0.8),
         &text,
     );
 }
 
-pub fn star(size: UVec2, graphics: &mut Graphics2D) {
+/// one star for each rating point from the right hand edge, a single star for a starred photo without a rating
+pub fn star(size: UVec2, graphics: &mut Graphics2D, rating: u8) {
     let image_bytes = include_bytes!("../img/star_24px.png");
     let file_bytes = Cursor::new(image_bytes);
     let image = graphics
         .create_image_from_file_bytes(None, ImageSmoothingMode::NearestNeighbor, file_bytes)
         .unwrap(); // complicated error struct
-    let position = Vec2 {
-        x: size.x as f32 - image.size().x as f32 - 10.0,
-        y: 10.0,
-    };
 
-    graphics.draw_image(position, &image);
+    let width = image.size().x as f32;
+    for i in 0..rating.max(1) {
+        let position = Vec2 {
+            x: size.x as f32 - (width + 2.0) * (i + 1) as f32 - 8.0,
+            y: 10.0,
+        };
+
+        graphics.draw_image(position, &image);
+    }
 }
 
-pub fn image(size: UVec2, file_bytes: &[u8], graphics: &mut Graphics2D) -> ImageHandle {
-    let file_bytes = Cursor::new(file_bytes);
-    let image = graphics
-        .create_image_from_file_bytes(None, ImageSmoothingMode::NearestNeighbor, file_bytes)
+/// number of (columns, rows) of thumbnails that fit on the screen
+pub fn grid_page_size(size: UVec2) -> (usize, usize) {
+    let columns = size.x.saturating_sub(GRID_GAP) / (THUMBNAIL_SIZE.x + GRID_GAP);
+    let rows = size.y.saturating_sub(GRID_GAP) / (THUMBNAIL_SIZE.y + GRID_GAP);
+    (columns.max(1) as usize, rows.max(1) as usize)
+}
+
+/// A page of thumbnails with the selected one outlined. A cell without a thumbnail
+/// is still being generated by the cache thread
+pub fn grid(
+    size: UVec2,
+    graphics: &mut Graphics2D,
+    font: &FontSet,
+    thumbnails: &[(&ImageNamePair, Option<&ImageHandle>)],
+    selected_index: usize,
+) {
+    let (columns, _) = grid_page_size(size);
+    let cell = Vec2::new(
+        (THUMBNAIL_SIZE.x + GRID_GAP) as f32,
+        (THUMBNAIL_SIZE.y + GRID_GAP) as f32,
+    );
+
+    // center the grid on the screen
+    let grid_width = columns as f32 * cell.x + GRID_GAP as f32;
+    let origin = Vec2::new((size.x as f32 - grid_width) / 2.0, GRID_GAP as f32);
+
+    let star_bytes = Cursor::new(include_bytes!("../img/star_24px.png"));
+    let star = graphics
+        .create_image_from_file_bytes(None, ImageSmoothingMode::NearestNeighbor, star_bytes)
         .unwrap(); // complicated error struct
-    let position = calculate_position_middle(size, &image);
-    graphics.draw_image(position, &image);
-    image
+
+    for (i, (image_file, thumbnail)) in thumbnails.iter().enumerate() {
+        let top_left = Vec2::new(
+            origin.x + GRID_GAP as f32 + (i % columns) as f32 * cell.x,
+            origin.y + (i / columns) as f32 * cell.y,
+        );
+        let bottom_right = Vec2::new(
+            top_left.x + THUMBNAIL_SIZE.x as f32,
+            top_left.y + THUMBNAIL_SIZE.y as f32,
+        );
+
+        match thumbnail {
+            Some(thumbnail) => {
+                let position = Vec2::new(
+                    top_left.x + (THUMBNAIL_SIZE.x - thumbnail.size().x) as f32 / 2.0,
+                    top_left.y + (THUMBNAIL_SIZE.y - thumbnail.size().y) as f32 / 2.0,
+                );
+                graphics.draw_image(position, thumbnail);
+            }
+            None => {
+                graphics.draw_rectangle(
+                    Rectangle::new(top_left, bottom_right),
+                    Color::from_gray(0.15),
+                );
+                let text = font.layout_text("generating...", 20.0, TextOptions::new());
+                let position = Vec2::new(
+                    top_left.x + (THUMBNAIL_SIZE.x as f32 - text.width()) / 2.0,
+                    top_left.y + (THUMBNAIL_SIZE.y as f32 - text.height()) / 2.0,
+                );
+                graphics.draw_text(position, Color::from_rgb(0.6, 0.6, 0.5), &text);
+            }
+        }
+
+        if image_file.is_starred || image_file.rating > 0 {
+            let width = star.size().x as f32;
+            for star_index in 0..image_file.rating.max(1) {
+                let position = Vec2::new(
+                    bottom_right.x - (width + 2.0) * (star_index + 1) as f32 - 4.0,
+                    top_left.y + 6.0,
+                );
+                graphics.draw_image(position, &star);
+            }
+        }
+
+        if i == selected_index {
+            highlight(graphics, top_left, bottom_right);
+        }
+    }
+}
+
+/// A strip of thumbnails along the bottom of the screen with the current image outlined in the middle.
+/// `thumbnails` are the images either side of the current image, which is at `current_index`
+pub fn filmstrip(
+    size: UVec2,
+    graphics: &mut Graphics2D,
+    thumbnails: &[(&ImageNamePair, Option<&ImageHandle>)],
+    current_index: usize,
+) {
+    let gap = GRID_GAP as f32;
+    let cell = Vec2::new(
+        FILMSTRIP_HEIGHT * THUMBNAIL_SIZE.x as f32 / THUMBNAIL_SIZE.y as f32,
+        FILMSTRIP_HEIGHT,
+    );
+
+    // the current image stays in the middle even at the start or end of the folder
+    let left = (size.x as f32 - cell.x) / 2.0 - current_index as f32 * (cell.x + gap);
+    let top = size.y as f32 - FILMSTRIP_BOTTOM_MARGIN - cell.y;
+    let width = thumbnails.len() as f32 * (cell.x + gap) - gap;
+    graphics.draw_rectangle(
+        Rectangle::new(
+            Vec2::new(left - gap, top - gap),
+            Vec2::new(left + width + gap, top + cell.y + gap),
+        ),
+        Color::from_rgba(0.0, 0.0, 0.0, 0.6),
+    );
+
+    for (i, (_, thumbnail)) in thumbnails.iter().enumerate() {
+        let top_left = Vec2::new(left + i as f32 * (cell.x + gap), top);
+        let bottom_right = top_left + cell;
+
+        match thumbnail {
+            Some(thumbnail) => {
+                // keep the aspect ratio of portrait photos
+                let scale =
+                    (cell.x / thumbnail.size().x as f32).min(cell.y / thumbnail.size().y as f32);
+                let image_size = Vec2::new(
+                    thumbnail.size().x as f32 * scale,
+                    thumbnail.size().y as f32 * scale,
+                );
+                let position = top_left + (cell - image_size) / 2.0;
+                graphics.draw_rectangle_image(
+                    Rectangle::new(position, position + image_size),
+                    thumbnail,
+                );
+            }
+            None => graphics.draw_rectangle(
+                Rectangle::new(top_left, bottom_right),
+                Color::from_gray(0.15),
+            ),
+        }
+
+        if i == current_index {
+            highlight(graphics, top_left, bottom_right);
+        }
+    }
 }
 
-pub fn progress_text(
+/// Two images side by side, each scaled to fit half of the screen.
+/// An image that has not been resized yet is a grey placeholder
+pub fn compare(
     size: UVec2,
     graphics: &mut Graphics2D,
-    font: &Font,
-    progress_percentage: Arc<AtomicI32>,
+    left: Option<&ImageHandle>,
+    right: Option<&ImageHandle>,
 ) {
-    let percentage = progress_percentage.load(Ordering::Relaxed);
+    let gap = GRID_GAP as f32;
+    let half = Vec2::new((size.x as f32 - gap) / 2.0, size.y as f32);
+
+    for (i, image) in [left, right].into_iter().enumerate() {
+        let top_left = Vec2::new(i as f32 * (half.x + gap), 0.0);
+        match image {
+            Some(image) => {
+                let scale = (half.x / image.size().x as f32).min(half.y / image.size().y as f32);
+                let image_size =
+                    Vec2::new(image.size().x as f32 * scale, image.size().y as f32 * scale);
+                let position = top_left + (half - image_size) / 2.0;
+                graphics
+                    .draw_rectangle_image(Rectangle::new(position, position + image_size), image);
+            }
+            None => graphics.draw_rectangle(
+                Rectangle::new(top_left, top_left + half),
+                Color::from_gray(0.15),
+            ),
+        }
+    }
+}
+
+/// the thick outline around the selected thumbnail
+fn highlight(graphics: &mut Graphics2D, top_left: Vec2, bottom_right: Vec2) {
+    let color = Color::from_rgb(0.9, 0.9, 0.8);
+    let corners = [
+        top_left,
+        Vec2::new(bottom_right.x, top_left.y),
+        bottom_right,
+        Vec2::new(top_left.x, bottom_right.y),
+        top_left,
+    ];
+    for pair in corners.windows(2) {
+        graphics.draw_line(pair[0], pair[1], 3.0, color);
+    }
+}
+
+/// a red, yellow or green square in the top left corner, opposite the star
+pub fn color_label(graphics: &mut Graphics2D, color_label: u8) {
+    let color = match color_label {
+        1 => Color::from_rgb(0.85, 0.2, 0.2),
+        2 => Color::from_rgb(0.9, 0.8, 0.2),
+        3 => Color::from_rgb(0.3, 0.75, 0.3),
+        _ => return,
+    };
+
+    let top_left = Vec2::new(10.0, 10.0);
+    graphics.draw_rectangle(
+        Rectangle::new(
+            top_left,
+            top_left + Vec2::new(COLOR_LABEL_SIZE, COLOR_LABEL_SIZE),
+        ),
+        color,
+    );
+}
+
+pub fn protected(size: UVec2, graphics: &mut Graphics2D) {
+    // a small padlock drawn below the star
+    let color = Color::from_rgb(0.9, 0.9, 0.8);
+    let x = size.x as f32 - 34.0;
+    let y = 44.0;
+
+    // shackle
+    let shackle = [(6.0, 10.0), (6.0, 4.0), (18.0, 4.0), (18.0, 10.0)];
+    for pair in shackle.windows(2) {
+        let (x0, y0) = pair[0];
+        let (x1, y1) = pair[1];
+        graphics.draw_line(
+            Vec2::new(x + x0, y + y0),
+            Vec2::new(x + x1, y + y1),
+            2.0,
+            color,
+        );
+    }
+
+    // body
+    graphics.draw_rectangle(
+        Rectangle::new(Vec2::new(x + 2.0, y + 10.0), Vec2::new(x + 22.0, y + 24.0)),
+        color,
+    );
+}
+
+pub fn flagged(size: UVec2, graphics: &mut Graphics2D) {
+    // a small orange flag drawn below the padlock
+    let color = Color::from_rgb(1.0, 0.6, 0.1);
+    let x = size.x as f32 - 34.0;
+    let y = 78.0;
+
+    // pole
+    graphics.draw_line(
+        Vec2::new(x + 4.0, y),
+        Vec2::new(x + 4.0, y + 24.0),
+        2.0,
+        color,
+    );
+
+    // cloth
+    graphics.draw_triangle(
+        [
+            Vec2::new(x + 5.0, y),
+            Vec2::new(x + 22.0, y + 6.0),
+            Vec2::new(x + 5.0, y + 12.0),
+        ],
+        color,
+    );
+}
+
+pub fn calibration(size: UVec2, graphics: &mut Graphics2D, config: &CalibrationConfig) {
+    if config.values.is_empty() || config.segment_length <= 0.0 {
+        return;
+    }
+
+    let width = size.x as f32;
+    let height = size.y as f32;
+    let strip = config.strip_width;
+
+    // top and bottom edges
+    let mut x = 0.0;
+    let mut i = 0;
+    while x < width {
+        let color = Color::from_gray(config.values[i % config.values.len()]);
+        let x1 = (x + config.segment_length).min(width);
+        graphics.draw_rectangle(
+            Rectangle::new(Vec2::new(x, 0.0), Vec2::new(x1, strip)),
+            color,
+        );
+        graphics.draw_rectangle(
+            Rectangle::new(Vec2::new(x, height - strip), Vec2::new(x1, height)),
+            color,
+        );
+        x = x1;
+        i += 1;
+    }
+
+    // left and right edges between the top and bottom strips
+    let mut y = strip;
+    let mut i = 0;
+    while y < height - strip {
+        let color = Color::from_gray(config.values[i % config.values.len()]);
+        let y1 = (y + config.segment_length).min(height - strip);
+        graphics.draw_rectangle(
+            Rectangle::new(Vec2::new(0.0, y), Vec2::new(strip, y1)),
+            color,
+        );
+        graphics.draw_rectangle(
+            Rectangle::new(Vec2::new(width - strip, y), Vec2::new(width, y1)),
+            color,
+        );
+        y = y1;
+        i += 1;
+    }
+}
+
+pub fn image(
+    size: UVec2,
+    file_bytes: &[u8],
+    upscale: f32,
+    graphics: &mut Graphics2D,
+) -> ImageHandle {
+    let image = screen_texture(file_bytes, upscale, graphics);
+    photo(size, graphics, &image, upscale);
+    image
+}
+
+/// Where a photo from the cache is drawn, in the middle of the screen. Photos cached at a lower resolution
+/// than the screen are enlarged by up to `upscale`, as far as they still fit
+pub fn photo_rect(size: UVec2, image: &ImageHandle, upscale: f32) -> Rectangle {
+    let (width, height) = (image.size().x as f32, image.size().y as f32);
+    let scale = if upscale > 1.0 {
+        upscale.min((size.x as f32 / width).min(size.y as f32 / height))
+    } else {
+        1.0
+    };
+    let image_size = Vec2::new(width * scale, height * scale);
+    let position = (Vec2::new(size.x as f32, size.y as f32) - image_size) / 2.0;
+    Rectangle::new(position, position + image_size)
+}
+
+/// draw a photo at `photo_rect`, the GPU does any enlarging
+pub fn photo(size: UVec2, graphics: &mut Graphics2D, image: &ImageHandle, upscale: f32) {
+    graphics.draw_rectangle_image(photo_rect(size, image, upscale), image);
+}
+
+/// upload an encoded image to the GPU without drawing it
+pub fn texture(file_bytes: &[u8], graphics: &mut Graphics2D) -> ImageHandle {
+    let file_bytes = Cursor::new(file_bytes);
+    graphics
+        .create_image_from_file_bytes(None, ImageSmoothingMode::NearestNeighbor, file_bytes)
+        .unwrap() // complicated error struct
+}
+
+pub fn progress_text(size: UVec2, graphics: &mut Graphics2D, font: &FontSet, progress: &Progress) {
+    let percentage = progress.percentage();
 
     // only draw progress below 100 percent
     if percentage < 100 {
-        let percentage = format!("{percentage} %",);
+        let percentage = match progress.remaining() {
+            Some(remaining) => {
+                let seconds = remaining.as_secs();
+                format!("{percentage} %  {}:{:02} left", seconds / 60, seconds % 60)
+            }
+            None => format!("{percentage} %"),
+        };
 
         let text = font.layout_text(
             &percentage,
             20.0,
-            TextOptions::new().with_wrap_to_width(200.0, TextAlignment::Left),
+            TextOptions::new().with_wrap_to_width(300.0, TextAlignment::Left),
         );
 
         graphics.draw_text(
@@ -78,57 +457,474 @@ pub fn progress_text(
     }
 }
 
-pub fn image_full(img: DynamicImage, graphics: &mut Graphics2D) -> ImageHandle {
-    let size = UVec2 {
-        x: img.width(),
-        y: img.height(),
+pub fn empty(size: UVec2, graphics: &mut Graphics2D, font: &FontSet) {
+    table(
+        size,
+        graphics,
+        font,
+        "All photos reviewed\n\nO\nF5\nCTRL+Z\nESC",
+        "The folder has no more photos\n\nOpen another folder\nScan the folder again\nUndo the last delete\nExit",
+        0.0,
+    );
+}
+
+/// a message that goes away by itself after a moment
+pub fn notice(size: UVec2, graphics: &mut Graphics2D, font: &FontSet, message: &str) {
+    dialog(size, graphics, font, message);
+}
+
+pub fn confirm(size: UVec2, graphics: &mut Graphics2D, font: &FontSet, message: &str) {
+    dialog(
+        size,
+        graphics,
+        font,
+        &format!("{message}\n\nY / ENTER to confirm, N / ESC to cancel"),
+    );
+}
+
+/// ask the user to type something, `input` is what they have typed so far
+pub fn prompt(size: UVec2, graphics: &mut Graphics2D, font: &FontSet, message: &str, input: &str) {
+    dialog(
+        size,
+        graphics,
+        font,
+        &format!("{message}\n\n> {input}_\n\nENTER to accept, ESC to cancel"),
+    );
+}
+
+/// `broken_image` shows a picture of a broken photo above the message
+pub fn error(
+    size: UVec2,
+    graphics: &mut Graphics2D,
+    font: &FontSet,
+    message: &str,
+    broken_image: bool,
+) {
+    if broken_image {
+        let image = texture(include_bytes!("../img/broken.png"), graphics);
+        let x = size.x as f32 / 2.0 - image.size().x as f32 / 2.0;
+        let y = size.y as f32 / 2.0 - image.size().y as f32 - 80.0;
+        graphics.draw_image(Vec2::new(x, y), &image);
+    }
+
+    dialog(
+        size,
+        graphics,
+        font,
+        &format!("{message}\n\nESC to skip to the next photo"),
+    );
+}
+
+/// a line of text centered along the top of the screen
+pub fn status(size: UVec2, graphics: &mut Graphics2D, font: &FontSet, status: &str) {
+    let text = font.layout_text(status, 20.0, TextOptions::new());
+    let x = size.x as f32 / 2.0 - text.width() / 2.0;
+    graphics.draw_text(Vec2::new(x, 5.0), Color::from_rgb(0.9, 0.9, 0.8), &text);
+}
+
+fn dialog(size: UVec2, graphics: &mut Graphics2D, font: &FontSet, text: &str) {
+    let text = font.layout_text(
+        text,
+        20.0,
+        TextOptions::new().with_wrap_to_width(600.0, TextAlignment::Left),
+    );
+
+    let padding = 20.0;
+    let x = size.x as f32 / 2.0 - text.width() / 2.0;
+    let y = size.y as f32 / 2.0 - text.height() / 2.0;
+
+    graphics.draw_rectangle(
+        Rectangle::new(
+            Vec2::new(x - padding, y - padding),
+            Vec2::new(x + text.width() + padding, y + text.height() + padding),
+        ),
+        Color::from_rgba(0.0, 0.0, 0.0, 0.85),
+    );
+    graphics.draw_text(Vec2::new(x, y), Color::from_rgb(0.9, 0.9, 0.8), &text);
+}
+
+/// Tick marks along the top and left edges of the displayed image.
+/// Ticks are in millimeters when the physical pixel size is known, otherwise in source image pixels
+pub fn ruler(
+    graphics: &mut Graphics2D,
+    font: &FontSet,
+    image: &Rectangle,
+    source_width: u32,
+    pixels_per_mm: Option<f64>,
+) {
+    if source_width == 0 {
+        return;
+    }
+
+    // screen pixels per source image pixel
+    let scale = image.width() / source_width as f32;
+    let (screen_per_unit, unit) = match pixels_per_mm {
+        Some(pixels_per_mm) => (pixels_per_mm as f32 * scale, "mm"),
+        None => (scale, "px"),
+    };
+
+    if screen_per_unit <= 0.0 {
+        return;
+    }
+
+    // pick a 1, 2, 5 step so that ticks are at least 8 screen pixels apart
+    let mut step = 1.0;
+    'outer: for magnitude in [1.0, 10.0, 100.0, 1000.0, 10000.0] {
+        for multiple in [1.0, 2.0, 5.0] {
+            step = multiple * magnitude;
+            if step * screen_per_unit >= 8.0 {
+                break 'outer;
+            }
+        }
+    }
+
+    let spacing = step * screen_per_unit;
+    let color = Color::from_rgb(0.9, 0.9, 0.8);
+    let edges = [
+        (Vec2::new(1.0, 0.0), image.width()),
+        (Vec2::new(0.0, 1.0), image.height()),
+    ];
+
+    for (direction, length) in edges {
+        // ticks grow inwards from the edge
+        let normal = Vec2::new(direction.y, direction.x);
+        let mut i = 0;
+        while i as f32 * spacing <= length {
+            let start = *image.top_left() + direction * (i as f32 * spacing);
+            let major = i % 5 == 0;
+            let tick = if major { 14.0 } else { 6.0 };
+            graphics.draw_line(start, start + normal * tick, 1.0, color);
+
+            if major && i > 0 {
+                let text = font.layout_text(
+                    &format!("{} {unit}", i as f32 * step),
+                    14.0,
+                    TextOptions::new(),
+                );
+                graphics.draw_text(start + normal * (tick + 2.0), color, &text);
+            }
+            i += 1;
+        }
+    }
+}
+
+pub fn focus_points(graphics: &mut Graphics2D, image: &Rectangle, points: &[FocusPoint]) {
+    let image_position = *image.top_left();
+    let (width, height) = (image.width(), image.height());
+    for point in points {
+        let top_left = image_position
+            + Vec2::new(
+                (point.x - point.width / 2.0) * width,
+                (point.y - point.height / 2.0) * height,
+            );
+        let bottom_right = image_position
+            + Vec2::new(
+                (point.x + point.width / 2.0) * width,
+                (point.y + point.height / 2.0) * height,
+            );
+        outline(
+            graphics,
+            top_left,
+            bottom_right,
+            Color::from_rgb(1.0, 0.2, 0.2),
+        );
+    }
+}
+
+fn outline(graphics: &mut Graphics2D, top_left: Vec2, bottom_right: Vec2, color: Color) {
+    let top_right = Vec2::new(bottom_right.x, top_left.y);
+    let bottom_left = Vec2::new(top_left.x, bottom_right.y);
+    graphics.draw_line(top_left, top_right, 2.0, color);
+    graphics.draw_line(top_right, bottom_right, 2.0, color);
+    graphics.draw_line(bottom_right, bottom_left, 2.0, color);
+    graphics.draw_line(bottom_left, top_left, 2.0, color);
+}
+
+/// shown while an image is loaded or the starred photos are exported, turned by `angle` radians
+pub fn hourglass(size: UVec2, graphics: &mut Graphics2D, angle: f32) {
+    // decoded once rather than for every frame of the animation
+    static HOURGLASS: OnceLock<RgbImage> = OnceLock::new();
+    let img = HOURGLASS.get_or_init(|| {
+        image::load_from_memory(include_bytes!("../img/hourglass.jpg"))
+            .expect("hourglass.jpg is a valid jpeg")
+            .to_rgb8()
+    });
+
+    let turned = rotate_about_center(img, angle, Interpolation::Bilinear, Rgb([0, 0, 0]));
+    image_uncompressed(size, DynamicImage::ImageRgb8(turned), graphics);
+}
+
+/// upload a photo cached for the screen, smoothed when `upscale` means it is enlarged as it is drawn
+pub fn screen_texture(file_bytes: &[u8], upscale: f32, graphics: &mut Graphics2D) -> ImageHandle {
+    let smoothing = if upscale > 1.0 {
+        ImageSmoothingMode::Linear
+    } else {
+        ImageSmoothingMode::NearestNeighbor
     };
+    graphics
+        .create_image_from_file_bytes(None, smoothing, Cursor::new(file_bytes))
+        .unwrap() // complicated error struct
+}
+
+/// draw an image that has not been through the jpeg encoder, centered on the screen
+pub fn image_uncompressed(
+    size: UVec2,
+    img: DynamicImage,
+    graphics: &mut Graphics2D,
+) -> ImageHandle {
+    let img = img.to_rgb8();
     let image = graphics
         .create_image_from_raw_pixels(
             ImageDataType::RGB,
             ImageSmoothingMode::NearestNeighbor,
-            size,
-            img.as_bytes(),
+            UVec2::new(img.width(), img.height()),
+            img.as_raw(),
         )
         .unwrap(); // complicated error struct
-
-    graphics.draw_image(Vec2 { x: 0.0, y: 0.0 }, &image);
+    let position = calculate_position_middle(size, &image);
+    graphics.draw_image(position, &image);
     image
 }
 
+/// a small copy of the image being zoomed into for `zoom_navigator`, made once per image
+pub fn zoom_navigator_texture(graphics: &mut Graphics2D, img: &DynamicImage) -> ImageHandle {
+    let img = img
+        .thumbnail(ZOOM_NAVIGATOR_SIZE, ZOOM_NAVIGATOR_SIZE)
+        .to_rgb8();
+    graphics
+        .create_image_from_raw_pixels(
+            ImageDataType::RGB,
+            ImageSmoothingMode::Linear,
+            UVec2::new(img.width(), img.height()),
+            img.as_raw(),
+        )
+        .unwrap() // complicated error struct
+}
+
+/// The whole image in the bottom right corner with the part on the screen outlined, and the zoom as a percentage.
+/// `crop` is the visible region in pixels of the full size image, which is `image_size`
+pub fn zoom_navigator(
+    size: UVec2,
+    graphics: &mut Graphics2D,
+    font: &FontSet,
+    navigator: &ImageHandle,
+    image_size: UVec2,
+    crop: &Rectangle<u32>,
+    zoom: f32,
+) {
+    let margin = 20.0;
+    let navigator_size = Vec2::new(navigator.size().x as f32, navigator.size().y as f32);
+    let top_left =
+        Vec2::new(size.x as f32, size.y as f32) - navigator_size - Vec2::new(margin, margin);
+    graphics.draw_rectangle(
+        Rectangle::new(
+            top_left - Vec2::new(4.0, 4.0),
+            top_left + navigator_size + Vec2::new(4.0, 4.0),
+        ),
+        Color::from_rgba(0.0, 0.0, 0.0, 0.6),
+    );
+    graphics.draw_image(top_left, navigator);
+
+    let scale = Vec2::new(
+        navigator_size.x / image_size.x as f32,
+        navigator_size.y / image_size.y as f32,
+    );
+    let crop_top_left = top_left
+        + Vec2::new(
+            crop.top_left().x as f32 * scale.x,
+            crop.top_left().y as f32 * scale.y,
+        );
+    let crop_bottom_right = top_left
+        + Vec2::new(
+            crop.bottom_right().x as f32 * scale.x,
+            crop.bottom_right().y as f32 * scale.y,
+        );
+    outline(
+        graphics,
+        crop_top_left,
+        crop_bottom_right,
+        Color::from_rgb(1.0, 0.8, 0.2),
+    );
+
+    // 100% is one screen pixel for each pixel of the photo
+    let text = font.layout_text(&format!("{:.0}%", zoom * 100.0), 20.0, TextOptions::new());
+    graphics.draw_text(
+        Vec2::new(top_left.x, top_left.y - text.height() - 8.0),
+        Color::from_rgb(0.9, 0.9, 0.8),
+        &text,
+    );
+}
+
+/// returns how far the fields can be scrolled, zero when they fit on the screen
 pub fn metadata(
     name: &str,
     size: UVec2,
     graphics: &mut Graphics2D,
-    font: &Font,
+    font: &FontSet,
     metadata: &ImageMetadata,
-) {
-    let col0 = format!(
-        "{}\n{}\n{}\n{}\n{}\n{}\n{}",
+    scroll: f32,
+) -> f32 {
+    let mut col0 = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
         "File Name",
         "Camera Model",
+        "Lens Make",
+        "Lens Model",
         "Date Taken",
         "Exposure Time",
         "Aperture Value",
         "ISO Speed Rating",
         "Focal Length",
+        "White Balance",
+        "Metering Mode",
+        "Flash",
+        "GPS Location",
     );
 
-    let col1 = format!(
-        "{}\n{}\n{}\n{}\n{}\n{}\n{}",
+    let location = match (metadata.gps_latitude, metadata.gps_longitude) {
+        (Some(latitude), Some(longitude)) => format!("{latitude:.6}, {longitude:.6}"),
+        _ => String::new(),
+    };
+
+    let mut col1 = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
         name,
         metadata.model.as_deref().unwrap_or_default(),
+        metadata.lens_make.as_deref().unwrap_or_default(),
+        metadata.lens_model.as_deref().unwrap_or_default(),
         metadata.date_time.as_deref().unwrap_or_default(),
         metadata.exposure_time.as_deref().unwrap_or_default(),
         metadata.f_number.as_deref().unwrap_or_default(),
         metadata.iso.as_deref().unwrap_or_default(),
         metadata.focal_length.as_deref().unwrap_or_default(),
+        metadata.white_balance.as_deref().unwrap_or_default(),
+        metadata.metering_mode.as_deref().unwrap_or_default(),
+        metadata.flash.as_deref().unwrap_or_default(),
+        location,
+    );
+
+    // only some cameras record a picture style that we can read
+    if let Some(picture_style) = &metadata.picture_style {
+        col0.push_str("\nPicture Style");
+        col1.push_str(&format!("\n{picture_style}"));
+    }
+
+    table(size, graphics, font, &col0, &col1, scroll)
+}
+
+/// a label and a count on each row
+pub fn stats(size: UVec2, graphics: &mut Graphics2D, font: &FontSet, stats: &[(String, String)]) {
+    let col0: Vec<&str> = stats.iter().map(|(label, _)| label.as_str()).collect();
+    let col1: Vec<&str> = stats.iter().map(|(_, count)| count.as_str()).collect();
+    table(
+        size,
+        graphics,
+        font,
+        &col0.join("\n"),
+        &col1.join("\n"),
+        0.0,
+    );
+}
+
+/// Bar charts side by side, each a title above the most used values and how many photos were taken with them
+pub fn shoot_stats(
+    size: UVec2,
+    graphics: &mut Graphics2D,
+    font: &FontSet,
+    charts: &[(String, Vec<(String, usize)>)],
+) {
+    let text_color = Color::from_rgb(0.9, 0.9, 0.8);
+    let bar_color = Color::from_rgb(0.6, 0.6, 0.5);
+
+    let chart_width = (size.x as f32 / charts.len().max(1) as f32).min(CHART_MAX_WIDTH);
+    let left = size.x as f32 / 2.0 - chart_width * charts.len() as f32 / 2.0;
+    let rows = charts.iter().map(|(_, bars)| bars.len()).max().unwrap_or(0);
+    let height = (rows + 1) as f32 * CHART_ROW_HEIGHT;
+    let top = (size.y as f32 / 2.0 - height / 2.0).max(TABLE_MARGIN);
+    let bar_space = chart_width - CHART_LABEL_WIDTH - CHART_COUNT_WIDTH;
+
+    for (index, (title, bars)) in charts.iter().enumerate() {
+        let x = left + chart_width * index as f32;
+        let title = font.layout_text(title, 20.0, TextOptions::new());
+        graphics.draw_text(Vec2::new(x, top), text_color, &title);
+
+        let most = bars.iter().map(|(_, count)| *count).max().unwrap_or(1) as f32;
+        for (row, (label, count)) in bars.iter().enumerate() {
+            let y = top + (row + 1) as f32 * CHART_ROW_HEIGHT;
+            let label = font.layout_text(label, 18.0, TextOptions::new());
+            graphics.draw_text(Vec2::new(x, y), text_color, &label);
+
+            let bar_end = x + CHART_LABEL_WIDTH + bar_space * *count as f32 / most;
+            graphics.draw_rectangle(
+                Rectangle::new(
+                    Vec2::new(x + CHART_LABEL_WIDTH, y + 4.0),
+                    Vec2::new(bar_end, y + CHART_ROW_HEIGHT - 8.0),
+                ),
+                bar_color,
+            );
+
+            let count = font.layout_text(&count.to_string(), 18.0, TextOptions::new());
+            graphics.draw_text(Vec2::new(bar_end + 8.0, y), text_color, &count);
+        }
+    }
+}
+
+/// number of exif fields that fit on the screen at once
+pub fn exif_dump_page_size(size: UVec2) -> usize {
+    // leave room for the heading and a margin at the bottom
+    ((size.y as f32 - 150.0) / EXIF_DUMP_LINE_HEIGHT).max(1.0) as usize
+}
+
+pub fn exif_dump(
+    name: &str,
+    size: UVec2,
+    graphics: &mut Graphics2D,
+    font: &FontSet,
+    fields: &[(String, String)],
+    scroll: usize,
+) {
+    let page_size = exif_dump_page_size(size);
+    let visible = fields.iter().skip(scroll).take(page_size);
+    let heading = format!(
+        "{}  -  fields {} to {} of {}  (UP / DOWN / PAGE UP / PAGE DOWN to scroll)",
+        name,
+        (scroll + 1).min(fields.len()),
+        (scroll + page_size).min(fields.len()),
+        fields.len()
+    );
+    let heading = font.layout_text(&heading, 20.0, TextOptions::new());
+    graphics.draw_text(
+        Vec2::new(50.0, 50.0),
+        Color::from_rgb(0.9, 0.9, 0.8),
+        &heading,
     );
 
-    table(size, graphics, font, &col0, &col1);
+    for (i, (tag, value)) in visible.enumerate() {
+        let y = 100.0 + i as f32 * EXIF_DUMP_LINE_HEIGHT;
+
+        // maker notes and thumbnails can be thousands of bytes long, one line per field is enough
+        let value = match value.char_indices().nth(EXIF_DUMP_MAX_VALUE_CHARS) {
+            Some((end, _)) => format!("{}...", &value[..end]),
+            None => value.clone(),
+        };
+
+        let tag = font.layout_text(tag, 18.0, TextOptions::new());
+        let value = font.layout_text(&value, 18.0, TextOptions::new());
+        graphics.draw_text(Vec2::new(50.0, y), Color::from_rgb(0.9, 0.9, 0.8), &tag);
+        graphics.draw_text(Vec2::new(450.0, y), Color::from_rgb(0.6, 0.6, 0.5), &value);
+    }
 }
 
-fn table(size: UVec2, graphics: &mut Graphics2D, font: &Font, col0: &str, col1: &str) {
+/// Two columns of text centered on the screen. A table taller than the screen starts at the top and is moved up
+/// by `scroll` pixels instead, returns how far it can be scrolled
+fn table(
+    size: UVec2,
+    graphics: &mut Graphics2D,
+    font: &FontSet,
+    col0: &str,
+    col1: &str,
+    scroll: f32,
+) -> f32 {
     let left_text = font.layout_text(
         col0,
         20.0,
@@ -143,7 +939,13 @@ fn table(size: UVec2, graphics: &mut Graphics2D, font: &Font, col0: &str, col1:
 
     let x_gap = 50.0;
     let x = size.x as f32 / 2.0 - (left_text.width() + right_text.width() + x_gap) / 2.0;
-    let y = size.y as f32 / 2.0 - left_text.height() / 2.0;
+    let height = left_text.height().max(right_text.height());
+    let max_scroll = (height + TABLE_MARGIN * 2.0 - size.y as f32).max(0.0);
+    let y = if max_scroll > 0.0 {
+        TABLE_MARGIN - scroll.clamp(0.0, max_scroll)
+    } else {
+        size.y as f32 / 2.0 - height / 2.0
+    };
 
     graphics.draw_text(Vec2 { x, y }, Color::from_rgb(0.9, 0.9, 0.8), &left_text);
     graphics.draw_text(
@@ -154,40 +956,106 @@ fn table(size: UVec2, graphics: &mut Graphics2D, font: &Font, col0: &str, col1:
         Color::from_rgb(0.6, 0.6, 0.5),
         &right_text,
     );
+
+    max_scroll
 }
 
-pub fn help(size: UVec2, graphics: &mut Graphics2D, font: &Font) {
-    let col0 = format!(
-        "{}\n\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
-        "Photo Farm",
-        "F1",
+const HELP: &[(&str, &str)] = &[
+    ("F1", "Toggle help"),
+    (
+        "G",
+        "Toggle thumbnail grid, arrows to select and ENTER to view",
+    ),
+    (
+        "P",
+        "Toggle slideshow, UP / DOWN to change how long photos are shown",
+    ),
+    (
         "F3",
-        "SPACE",
+        "Toggle EXIF metadata, UP / DOWN to scroll when it does not fit",
+    ),
+    ("SHIFT+F3", "Toggle all EXIF fields"),
+    ("SHIFT+O", "Show the photo in the file manager"),
+    ("C", "Copy the path of the photo to the clipboard"),
+    ("F2", "Toggle counts of starred and rated photos"),
+    (
+        "SHIFT+F2",
+        "Toggle charts of the ISOs, apertures and focal lengths used most",
+    ),
+    ("SPACE", "Toggle star"),
+    ("SHIFT+SPACE", "Star all photos shown"),
+    ("CTRL+SPACE", "Remove all stars"),
+    ("L", "Toggle protect from deletion"),
+    ("K", "Toggle maybe flag, kept apart from stars"),
+    ("J / SHIFT+J", "Next / previous flagged photo"),
+    (
+        "N / SHIFT+N",
+        "Next / previous photo that has not been rated or starred",
+    ),
+    ("DELETE", "Move photo and its raw files to 'trash' folder"),
+    ("CTRL+Z", "Undo the last delete"),
+    ("R / SHIFT+R", "Rotate photo clockwise / counter-clockwise"),
+    ("CTRL+R", "Toggle turning photos by their exif orientation"),
+    (
         "LEFT CTRL",
-        "ESC",
-        "LEFT",
-        "RIGHT",
-        "E",
-        "S",
-        "I",
-        "1 2 3 4 5 6 7 8 9 0",
-    );
+        "Hold to zoom in at the mouse, scroll to change zoom, arrows to pan",
+    ),
+    ("+ / -", "Zoom in / out until ESC is pressed"),
+    ("ESC", "Exit"),
+    (
+        "LEFT / RIGHT",
+        "Previous / next burst, photos taken close together are skipped together",
+    ),
+    ("UP / DOWN", "Previous / next photo, one at a time within a burst"),
+    (
+        "F8",
+        "Group photos that look the same so LEFT / RIGHT skip them together",
+    ),
+    (
+        "SHIFT+UP / DOWN",
+        "Jump back / forward several photos (10 by default)",
+    ),
+    ("HOME / END", "First / last photo that matches the filter"),
+    (
+        "X",
+        "Mark photo to compare, two marked photos are shown side by side (LEFT / RIGHT to swap, F to flicker between them, UP / DOWN to flicker slower / faster)",
+    ),
+    ("E", "Export starred photos to 'export' folder"),
+    (
+        "S / SHIFT+S",
+        "Cycle filter: starred, rated 1-5 or more, red / yellow / green label",
+    ),
+    ("W", "Write starred photos to a playlist file"),
+    ("SHIFT+W", "Write the starred file names to selected.txt"),
+    ("I", "Toggle show file name"),
+    ("V", "Toggle filmstrip of neighbouring photos"),
+    ("B", "Toggle calibration border"),
+    ("[ ]", "Jump to the most under / over exposed photo"),
+    ("M", "Toggle measurement ruler"),
+    ("A", "Toggle autofocus points (Canon and Sony)"),
+    ("T", "Toggle show photos taken today only"),
+    ("D", "Show photos taken on a date or between two dates"),
+    ("F4", "Toggle a fresh decode to compare against the cache"),
+    ("F5", "Scan the folder again (reloads .photofarmignore)"),
+    ("F6", "Toggle sort by file name / date taken"),
+    ("1 2 3 4 5", "Rate photo"),
+    ("0", "Clear rating"),
+    ("6 7 8", "Toggle red / yellow / green color label"),
+    (
+        "SHIFT+1 2 3 4 5 6 7 8 9 0",
+        "Resize to 10-100% and export starred photos to 'export' folder",
+    ),
+];
 
+pub fn help(size: UVec2, graphics: &mut Graphics2D, font: &FontSet) {
+    let keys: Vec<&str> = HELP.iter().map(|(key, _)| *key).collect();
+    let descriptions: Vec<&str> = HELP.iter().map(|(_, description)| *description).collect();
+    let col0 = format!("{}\n\n{}", "Photo Farm", keys.join("\n"));
     let col1 = format!(
-        "{}\n\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
+        "{}\n\n{}",
         "An image viewer by David Haig",
-        "Toggle help",
-        "Toggle EXIF metadata",
-        "Toggle star",
-        "Hold to zoom in to 1:1",
-        "Exit",
-        "Previous photo",
-        "Next photo",
-        "Export starred photos to 'export' folder",
-        "Toggle show starred photos only",
-        "Toggle show file name",
-        "Resize to 10-100% and export starred photos to 'export' folder",
+        descriptions.join("\n")
     );
 
-    table(size, graphics, font, &col0, &col1);
+    table(size, graphics, font, &col0, &col1, 0.0);
 }
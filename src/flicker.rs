@@ -0,0 +1,70 @@
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use speedy2d::window::UserEventSender;
+
+use crate::ticker::spawn_ticker;
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(250);
+const MIN_INTERVAL: Duration = Duration::from_millis(50);
+const MAX_INTERVAL: Duration = Duration::from_secs(2);
+// UP and DOWN change the interval by this much
+const STEP: Duration = Duration::from_millis(50);
+// the timer may wake the window slightly before the interval has passed
+const TOLERANCE: Duration = Duration::from_millis(10);
+
+/// Shows the two images marked for comparison one after the other in the same place, so that the eye catches
+/// small differences between them. Like the slideshow a thread wakes the window with a user event, the thread
+/// stops when this is dropped
+pub struct Flicker {
+    interval: Duration,
+    last_swapped: Instant,
+    /// index of the marked image on the screen
+    showing: usize,
+    interval_tx: Sender<Duration>,
+}
+
+impl Flicker {
+    pub fn start(interval: Duration, user_event_sender: Arc<Mutex<UserEventSender<()>>>) -> Self {
+        let interval = interval.clamp(MIN_INTERVAL, MAX_INTERVAL);
+        let interval_tx = spawn_ticker(interval, user_event_sender);
+
+        Self {
+            interval,
+            last_swapped: Instant::now(),
+            showing: 0,
+            interval_tx,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// slower by a step, or faster when `slower` is false
+    pub fn adjust(&mut self, slower: bool) {
+        let interval = if slower {
+            self.interval + STEP
+        } else {
+            self.interval.saturating_sub(STEP)
+        };
+        self.interval = interval.clamp(MIN_INTERVAL, MAX_INTERVAL);
+        // the thread has gone if the window is closing
+        let _ = self.interval_tx.send(self.interval);
+    }
+
+    /// user events are also sent by other threads so check that the image has been shown long enough
+    pub fn is_due(&self) -> bool {
+        self.last_swapped.elapsed() + TOLERANCE >= self.interval
+    }
+
+    pub fn swap(&mut self) {
+        self.showing = 1 - self.showing;
+        self.last_swapped = Instant::now();
+    }
+
+    pub fn showing(&self) -> usize {
+        self.showing
+    }
+}
@@ -0,0 +1,72 @@
+use std::rc::Rc;
+
+use speedy2d::font::{Font, FormattedTextBlock, TextLayout, TextOptions};
+
+use crate::Error;
+
+/// A font for drawing with and the same font parsed by rusttype to find out which characters it has glyphs for
+struct Face {
+    font: Font,
+    glyphs: rusttype::Font<'static>,
+}
+
+impl Face {
+    fn new(bytes: Vec<u8>) -> Result<Self, Error> {
+        let font = Font::new(&bytes).map_err(|e| Error::Font(format!("{e:?}")))?;
+        let glyphs = rusttype::Font::try_from_vec(bytes)
+            .ok_or_else(|| Error::Font("not a ttf or otf file".to_owned()))?;
+        Ok(Self { font, glyphs })
+    }
+
+    /// characters in the text this font would draw as a box
+    fn missing(&self, text: &str) -> usize {
+        text.chars()
+            .filter(|c| !c.is_whitespace() && !c.is_control())
+            .filter(|c| self.glyphs.glyph(*c).id().0 == 0)
+            .count()
+    }
+}
+
+/// speedy2d does not fall back to another font for characters missing from the one it is given, so a whole
+/// piece of text is laid out with the first font that has all of its characters. File names and camera
+/// strings in scripts the primary font does not cover are then drawn with a fallback font rather than boxes
+pub struct FontSet {
+    // the primary font first
+    faces: Vec<Face>,
+}
+
+impl FontSet {
+    pub fn new(primary: Vec<u8>) -> Result<Self, Error> {
+        Ok(Self {
+            faces: vec![Face::new(primary)?],
+        })
+    }
+
+    /// used for text the fonts before it cannot draw
+    pub fn add_fallback(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.faces.push(Face::new(bytes)?);
+        Ok(())
+    }
+
+    /// laid out with the first font that can draw every character, or the one missing the fewest
+    pub fn layout_text(
+        &self,
+        text: &str,
+        scale: f32,
+        options: TextOptions,
+    ) -> Rc<FormattedTextBlock> {
+        let mut best = &self.faces[0];
+        let mut best_missing = usize::MAX;
+        for face in &self.faces {
+            let missing = face.missing(text);
+            if missing < best_missing {
+                best = face;
+                best_missing = missing;
+            }
+            if missing == 0 {
+                break;
+            }
+        }
+        best.font.layout_text(text, scale, options)
+    }
+}
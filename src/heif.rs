@@ -0,0 +1,39 @@
+use image::DynamicImage;
+
+use crate::Error;
+
+/// Decode a HEIC / HEIF photo, as taken by most phones. libheif applies the rotation and mirroring
+/// stored in the file so the exif orientation must not be applied again
+#[cfg(feature = "heif")]
+pub fn load(file_name: &str) -> Result<DynamicImage, Error> {
+    use image::RgbImage;
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let heif_error = |e: libheif_rs::HeifError| Error::Heif(e.to_string());
+    let lib_heif = LibHeif::new();
+    let context = HeifContext::read_from_file(file_name).map_err(heif_error)?;
+    let handle = context.primary_image_handle().map_err(heif_error)?;
+    let image = lib_heif
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)
+        .map_err(heif_error)?;
+
+    let no_pixels = || Error::Heif(format!("no pixels decoded from {file_name}"));
+    let plane = image.planes().interleaved.ok_or_else(no_pixels)?;
+
+    // rows are padded to the stride
+    let row_len = plane.width as usize * 3;
+    let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+
+    let img = RgbImage::from_raw(plane.width, plane.height, pixels).ok_or_else(no_pixels)?;
+    Ok(DynamicImage::ImageRgb8(img))
+}
+
+#[cfg(not(feature = "heif"))]
+pub fn load(file_name: &str) -> Result<DynamicImage, Error> {
+    Err(Error::Heif(format!(
+        "{file_name} cannot be opened without the heif feature"
+    )))
+}
@@ -1,14 +1,140 @@
 #![allow(dead_code)]
 
-use chrono::NaiveDateTime;
-use log::error;
+use std::time::Duration;
 
-use crate::{metadata, ImageNamePair};
+use chrono::NaiveDate;
+
+use crate::config::{SortOrder, StartPosition};
+use crate::ImageNamePair;
+
+// photos taken this close together are one burst unless configured otherwise
+const DEFAULT_GROUP_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Only show images taken between two dates (inclusive), images without a date are never shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateFilter {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl DateFilter {
+    /// a single date e.g. "2023-01-31" or a range e.g. "2023-01-31..2023-02-02"
+    pub fn parse(s: &str) -> Option<Self> {
+        let parse_date = |x: &str| NaiveDate::parse_from_str(x.trim(), "%Y-%m-%d").ok();
+        let (from, to) = match s.split_once("..") {
+            Some((from, to)) => (parse_date(from)?, parse_date(to)?),
+            None => (parse_date(s)?, parse_date(s)?),
+        };
+
+        Some(Self {
+            from: from.min(to),
+            to: from.max(to),
+        })
+    }
+
+    pub fn matches(&self, image: &ImageNamePair) -> bool {
+        match image.date_time {
+            Some(date_time) => (self.from..=self.to).contains(&date_time.date_naive()),
+            None => false,
+        }
+    }
+}
+
+impl std::fmt::Display for DateFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.from == self.to {
+            write!(f, "{}", self.from)
+        } else {
+            write!(f, "{} to {}", self.from, self.to)
+        }
+    }
+}
+
+/// Only navigate to images the user has marked in a particular way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFilter {
+    All,
+    Starred,
+    Flagged,
+    /// rated this many stars or more
+    MinRating(u8),
+    ColorLabel(u8),
+}
+
+// the order the filters are cycled through
+const FILTER_CYCLE: [ImageFilter; 11] = [
+    ImageFilter::All,
+    ImageFilter::Starred,
+    ImageFilter::Flagged,
+    ImageFilter::MinRating(1),
+    ImageFilter::MinRating(2),
+    ImageFilter::MinRating(3),
+    ImageFilter::MinRating(4),
+    ImageFilter::MinRating(5),
+    ImageFilter::ColorLabel(1),
+    ImageFilter::ColorLabel(2),
+    ImageFilter::ColorLabel(3),
+];
+
+impl ImageFilter {
+    pub fn matches(&self, image: &ImageNamePair) -> bool {
+        match self {
+            Self::All => true,
+            Self::Starred => image.is_starred,
+            Self::Flagged => image.is_flagged,
+            Self::MinRating(rating) => image.rating >= *rating,
+            Self::ColorLabel(color_label) => image.color_label == *color_label,
+        }
+    }
+
+    /// the filter after this one, or before it when going `backwards`
+    pub fn cycle(self, backwards: bool) -> Self {
+        let len = FILTER_CYCLE.len();
+        let index = FILTER_CYCLE.iter().position(|x| *x == self).unwrap_or(0);
+        let index = if backwards {
+            (index + len - 1) % len
+        } else {
+            (index + 1) % len
+        };
+        FILTER_CYCLE[index]
+    }
+}
+
+impl std::fmt::Display for ImageFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "All"),
+            Self::Starred => write!(f, "Starred"),
+            Self::Flagged => write!(f, "Flagged"),
+            Self::MinRating(5) => write!(f, "Rated 5"),
+            Self::MinRating(rating) => write!(f, "Rated {rating} or more"),
+            Self::ColorLabel(1) => write!(f, "Red label"),
+            Self::ColorLabel(2) => write!(f, "Yellow label"),
+            Self::ColorLabel(3) => write!(f, "Green label"),
+            Self::ColorLabel(color_label) => write!(f, "Color label {color_label}"),
+        }
+    }
+}
+
+/// file names are already sorted when the folder is read
+pub fn sort(images: &mut [ImageNamePair], order: SortOrder) {
+    match order {
+        SortOrder::FileName => images.sort_by(|a, b| a.jpg_file_name.cmp(&b.jpg_file_name)),
+        SortOrder::DateTaken => images.sort_by(|a, b| {
+            (a.date_time.is_none(), a.date_time, &a.jpg_file_name).cmp(&(
+                b.date_time.is_none(),
+                b.date_time,
+                &b.jpg_file_name,
+            ))
+        }),
+    }
+}
 
 pub struct Images {
     path: String,
     inner: Vec<ImageNamePair>,
     index: usize,
+    group_threshold: Duration,
 }
 
 impl Images {
@@ -18,6 +144,54 @@ impl Images {
             path: path.to_owned(),
             inner: image_file_names,
             index,
+            group_threshold: DEFAULT_GROUP_THRESHOLD,
+        }
+    }
+
+    /// open a folder without a particular image in mind, `last_viewed` is the image showing when it was last closed
+    pub fn new_at(
+        path: &str,
+        start: StartPosition,
+        image_file_names: Vec<ImageNamePair>,
+        last_viewed: Option<&str>,
+    ) -> Self {
+        let index = match start {
+            StartPosition::First => 0,
+            StartPosition::Last => image_file_names.len().saturating_sub(1),
+            StartPosition::FirstUnrated => image_file_names
+                .iter()
+                .position(|x| !x.is_starred && x.rating == 0)
+                .unwrap_or(0),
+            // it may have been deleted or moved since
+            StartPosition::LastViewed => last_viewed
+                .and_then(|name| {
+                    image_file_names
+                        .iter()
+                        .position(|x| x.jpg_file_name == name)
+                })
+                .unwrap_or(0),
+        };
+
+        Self {
+            path: path.to_owned(),
+            inner: image_file_names,
+            index,
+            group_threshold: DEFAULT_GROUP_THRESHOLD,
+        }
+    }
+
+    /// photos taken no more than this far apart are skipped over by `next_group` and `prev_group`
+    pub fn set_group_threshold(&mut self, group_threshold: Duration) {
+        self.group_threshold = group_threshold;
+    }
+
+    /// replace all images after the folder has been scanned again, staying on the current image if it still exists
+    pub fn replace(&mut self, image_file_names: Vec<ImageNamePair>) {
+        let name = self.inner.get(self.index).map(|x| x.jpg_file_name.clone());
+        self.inner = image_file_names;
+        self.index = self.index.min(self.inner.len().saturating_sub(1));
+        if let Some(name) = name {
+            self.go_to(&name);
         }
     }
 
@@ -32,6 +206,10 @@ impl Images {
     }
 
     pub fn next(&mut self) {
+        if self.inner.is_empty() {
+            return;
+        }
+
         if self.index == self.inner.len() - 1 {
             self.index = 0
         } else {
@@ -39,62 +217,46 @@ impl Images {
         }
     }
 
-    fn set_date_time(&mut self) {
-        let path = &self.path.clone();
-        let current = self.current_mut();
-
-        if current.date_time.is_none() {
-            match metadata::_get_date_time(path, &current.jpg_file_name) {
-                Ok(date_time) => current.date_time = Some(date_time),
-                Err(e) => {
-                    error!("error fetching exif date time {e:?}");
-                }
-            }
-        }
-    }
-
+    /// Move to the first image of the next burst of photos, wrapping around to the start of the folder.
+    /// Starred images are never skipped over
     pub fn next_group(&mut self) {
-        // use this until groups are working properly
-        self.next();
-
-        /*
-        loop {
-            let from = self.current().date_time;
+        for _ in 0..self.inner.len() {
+            let from = self.index;
             if self.index == self.inner.len() - 1 {
                 self.index = 0;
                 break;
-            } else {
-                self.index += 1;
-                if self.current().is_starred {
-                    break;
-                }
-                if !self.is_in_group(from) {
-                    break;
-                }
             }
-        }*/
-    }
-
-    fn is_in_group(&mut self, from: Option<NaiveDateTime>) -> bool {
-        self.set_date_time();
 
-        let current = self.current();
-        if from.is_none() || current.date_time.is_none() {
-            return false;
+            self.index += 1;
+            if self.current().is_starred || !self.is_in_group(from) {
+                break;
+            }
         }
+    }
 
-        let from = from.unwrap();
-        let date_time = current.date_time.unwrap();
+    /// is the current image part of the same burst as the image at index `from`, either because they were
+    /// taken close together or because a similarity scan found that they look the same.
+    /// Capture dates are read when the folder is scanned, images without one are only grouped by the scan
+    fn is_in_group(&self, from: usize) -> bool {
+        let from = &self.inner[from];
+        if from.group_id.is_some() && from.group_id == self.current().group_id {
+            return true;
+        }
 
-        // the difference in timestamp seconds is more than 1 seconds
-        if (date_time.timestamp() - from.timestamp()).abs() > 1 {
+        let (Some(from), Some(date_time)) = (from.date_time, self.current().date_time) else {
             return false;
-        }
+        };
 
-        true
+        // capture times keep fractions of a second which comparing whole seconds would throw away
+        let millis = (date_time - from).num_milliseconds().unsigned_abs();
+        millis as u128 <= self.group_threshold.as_millis()
     }
 
     pub fn prev(&mut self) {
+        if self.inner.is_empty() {
+            return;
+        }
+
         if self.index == 0 {
             self.index = self.inner.len() - 1
         } else {
@@ -102,52 +264,148 @@ impl Images {
         }
     }
 
+    /// move forwards (or backwards when negative) by several images, wrapping around at either end
+    pub fn step(&mut self, count: isize) {
+        if self.inner.is_empty() {
+            return;
+        }
+
+        let len = self.inner.len() as isize;
+        self.index = (self.index as isize + count).rem_euclid(len) as usize;
+    }
+
+    /// Move to the first image of the previous burst of photos, wrapping around to the end of the folder.
+    /// Starred images are never skipped over
     pub fn prev_group(&mut self) {
-        // use this until groups are working properly
-        self.prev();
+        if self.inner.is_empty() {
+            return;
+        }
 
-        /*
-        loop {
-            let from = self.current().date_time;
-            if self.index == 0 {
-                self.index = self.inner.len() - 1;
+        // the last image of the previous burst, then back to where that burst started
+        self.prev();
+        while self.index > 0 && !self.current().is_starred {
+            if !self.is_in_group(self.index - 1) {
                 break;
-            } else {
-                self.index -= 1;
-                if self.current().is_starred {
-                    break;
-                }
-                if !self.is_in_group(from) {
-                    break;
-                }
             }
-        }*/
+            self.index -= 1;
+        }
     }
 
-    pub fn next_starred(&mut self) {
-        if self.all().iter().any(|x| x.is_starred) {
+    /// move to the next image that passes the filter, staying put if there are none
+    pub fn next_matching(&mut self, filter: impl Fn(&ImageNamePair) -> bool) {
+        if self.all().iter().any(&filter) {
             loop {
                 self.next();
 
-                if self.current().is_starred {
+                if filter(self.current()) {
                     break;
                 }
             }
         }
     }
 
-    pub fn prev_starred(&mut self) {
-        if self.all().iter().any(|x| x.is_starred) {
+    /// move to the previous image that passes the filter, staying put if there are none
+    pub fn prev_matching(&mut self, filter: impl Fn(&ImageNamePair) -> bool) {
+        if self.all().iter().any(&filter) {
             loop {
                 self.prev();
 
-                if self.current().is_starred {
+                if filter(self.current()) {
                     break;
                 }
             }
         }
     }
 
+    /// move to the next image flagged as a maybe, staying put if there are none
+    pub fn next_flagged(&mut self) {
+        self.next_matching(|x| x.is_flagged);
+    }
+
+    /// move to the previous image flagged as a maybe, staying put if there are none
+    pub fn prev_flagged(&mut self) {
+        self.prev_matching(|x| x.is_flagged);
+    }
+
+    /// Move to the next image without a rating, where `is_rated` decides whether a star or flag counts as one.
+    /// Returns false, staying put, once every image has been rated
+    pub fn next_unrated(&mut self, is_rated: impl Fn(&ImageNamePair) -> bool) -> bool {
+        let unrated = |x: &ImageNamePair| x.rating == 0 && !is_rated(x);
+        if !self.inner.iter().any(unrated) {
+            return false;
+        }
+
+        self.next_matching(unrated);
+        true
+    }
+
+    /// `next_unrated` going backwards
+    pub fn prev_unrated(&mut self, is_rated: impl Fn(&ImageNamePair) -> bool) -> bool {
+        let unrated = |x: &ImageNamePair| x.rating == 0 && !is_rated(x);
+        if !self.inner.iter().any(unrated) {
+            return false;
+        }
+
+        self.prev_matching(unrated);
+        true
+    }
+
+    /// move to the first image that passes the filter, staying put if there are none
+    pub fn first(&mut self, filter: impl Fn(&ImageNamePair) -> bool) {
+        if let Some(index) = self.inner.iter().position(filter) {
+            self.index = index;
+        }
+    }
+
+    /// move to the last image that passes the filter, staying put if there are none
+    pub fn last(&mut self, filter: impl Fn(&ImageNamePair) -> bool) {
+        if let Some(index) = self.inner.iter().rposition(filter) {
+            self.index = index;
+        }
+    }
+
+    /// put the images in a different order, staying on the current image
+    pub fn sort(&mut self, order: SortOrder) {
+        let name = self.inner.get(self.index).map(|x| x.jpg_file_name.clone());
+        sort(&mut self.inner, order);
+        if let Some(name) = name {
+            self.go_to(&name);
+        }
+    }
+
+    /// drop the current image, the next one becomes current.
+    /// When the last image in the folder is removed the collection is empty
+    pub fn remove_current(&mut self) -> ImageNamePair {
+        let removed = self.inner.remove(self.index);
+        if self.index >= self.inner.len() {
+            self.index = 0;
+        }
+        removed
+    }
+
+    /// put an image back where it was before `remove_current` and make it the current image
+    pub fn insert(&mut self, index: usize, image: ImageNamePair) {
+        self.index = index.min(self.inner.len());
+        self.inner.insert(self.index, image);
+    }
+
+    /// make the image with this name the current image, returns false if it is not found
+    pub fn go_to(&mut self, name: &str) -> bool {
+        match self.inner.iter().position(|x| x.jpg_file_name == name) {
+            Some(index) => {
+                self.index = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// there are no images left, for example when they have all been removed from the folder.
+    /// `current` must not be called when this is true
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
     pub fn current(&self) -> &ImageNamePair {
         &self.inner[self.index]
     }
@@ -163,4 +421,9 @@ impl Images {
     pub fn all(&self) -> &Vec<ImageNamePair> {
         &self.inner
     }
+
+    /// for changes to many images at once, the order and number of images stay the same
+    pub fn all_mut(&mut self) -> &mut [ImageNamePair] {
+        &mut self.inner
+    }
 }
@@ -0,0 +1,77 @@
+use speedy2d::window::VirtualKeyCode;
+
+/// A key by the name used in photo-farm.toml e.g. "K", "5", "F2", "Space" or "PageUp", ignoring case
+pub fn parse(name: &str) -> Option<VirtualKeyCode> {
+    let key = match name.to_uppercase().as_str() {
+        "A" => VirtualKeyCode::A,
+        "B" => VirtualKeyCode::B,
+        "C" => VirtualKeyCode::C,
+        "D" => VirtualKeyCode::D,
+        "E" => VirtualKeyCode::E,
+        "F" => VirtualKeyCode::F,
+        "G" => VirtualKeyCode::G,
+        "H" => VirtualKeyCode::H,
+        "I" => VirtualKeyCode::I,
+        "J" => VirtualKeyCode::J,
+        "K" => VirtualKeyCode::K,
+        "L" => VirtualKeyCode::L,
+        "M" => VirtualKeyCode::M,
+        "N" => VirtualKeyCode::N,
+        "O" => VirtualKeyCode::O,
+        "P" => VirtualKeyCode::P,
+        "Q" => VirtualKeyCode::Q,
+        "R" => VirtualKeyCode::R,
+        "S" => VirtualKeyCode::S,
+        "T" => VirtualKeyCode::T,
+        "U" => VirtualKeyCode::U,
+        "V" => VirtualKeyCode::V,
+        "W" => VirtualKeyCode::W,
+        "X" => VirtualKeyCode::X,
+        "Y" => VirtualKeyCode::Y,
+        "Z" => VirtualKeyCode::Z,
+        "0" => VirtualKeyCode::Key0,
+        "1" => VirtualKeyCode::Key1,
+        "2" => VirtualKeyCode::Key2,
+        "3" => VirtualKeyCode::Key3,
+        "4" => VirtualKeyCode::Key4,
+        "5" => VirtualKeyCode::Key5,
+        "6" => VirtualKeyCode::Key6,
+        "7" => VirtualKeyCode::Key7,
+        "8" => VirtualKeyCode::Key8,
+        "9" => VirtualKeyCode::Key9,
+        "F1" => VirtualKeyCode::F1,
+        "F2" => VirtualKeyCode::F2,
+        "F3" => VirtualKeyCode::F3,
+        "F4" => VirtualKeyCode::F4,
+        "F5" => VirtualKeyCode::F5,
+        "F6" => VirtualKeyCode::F6,
+        "F7" => VirtualKeyCode::F7,
+        "F8" => VirtualKeyCode::F8,
+        "F9" => VirtualKeyCode::F9,
+        "F10" => VirtualKeyCode::F10,
+        "F11" => VirtualKeyCode::F11,
+        "F12" => VirtualKeyCode::F12,
+        "UP" => VirtualKeyCode::Up,
+        "DOWN" => VirtualKeyCode::Down,
+        "LEFT" => VirtualKeyCode::Left,
+        "RIGHT" => VirtualKeyCode::Right,
+        "HOME" => VirtualKeyCode::Home,
+        "END" => VirtualKeyCode::End,
+        "PAGEUP" => VirtualKeyCode::PageUp,
+        "PAGEDOWN" => VirtualKeyCode::PageDown,
+        "SPACE" => VirtualKeyCode::Space,
+        "RETURN" | "ENTER" => VirtualKeyCode::Return,
+        "ESCAPE" => VirtualKeyCode::Escape,
+        "DELETE" => VirtualKeyCode::Delete,
+        "INSERT" => VirtualKeyCode::Insert,
+        "BACKSPACE" => VirtualKeyCode::Backspace,
+        "TAB" => VirtualKeyCode::Tab,
+        "[" => VirtualKeyCode::LBracket,
+        "]" => VirtualKeyCode::RBracket,
+        "LCONTROL" => VirtualKeyCode::LControl,
+        "RCONTROL" => VirtualKeyCode::RControl,
+        _ => return None,
+    };
+
+    Some(key)
+}
@@ -1,34 +1,102 @@
 #![windows_subsystem = "windows"]
 
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::BufReader;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicI32, Ordering};
-use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{env, thread};
 
-use chrono::{NaiveDateTime, ParseError};
+use args::Args;
+use cache::{CacheMessage, RESOLUTION_SETTLE};
+use chrono::{DateTime, FixedOffset, Local, ParseError};
+use config::{Config, ExportConfig, SortOrder, SpaceKey};
+use decode::{DecodeCache, Decoded};
+use flicker::Flicker;
+use fonts::FontSet;
 use image::imageops::FilterType;
 use image::{DynamicImage, GenericImageView};
-use images::Images;
-use log::{error, info};
-use metadata::ImageMetadata;
+use images::{DateFilter, ImageFilter, Images};
+use log::{error, info, warn};
+use makernote::FocusPoint;
+use metadata::{ImageMetadata, ShotSettings};
+use progress::Progress;
+use slideshow::Slideshow;
 use speedy2d::color::Color;
 use speedy2d::dimen::{UVec2, Vec2};
-use speedy2d::font::Font;
 use speedy2d::image::ImageHandle;
-use speedy2d::window::{KeyScancode, UserEventSender, VirtualKeyCode, WindowHandler, WindowHelper};
+use speedy2d::shape::Rectangle;
+use speedy2d::window::{
+    KeyScancode, ModifiersState, MouseScrollDistance, UserEventSender, VirtualKeyCode,
+    WindowHandler, WindowHelper, WindowStartupInfo,
+};
 use speedy2d::{Graphics2D, Window};
+use spinner::Spinner;
 use sqlite::Connection;
+use textures::TextureCache;
 use thiserror::Error;
 
+mod args;
+mod cache;
+mod caption;
+mod config;
 mod db;
+mod decode;
 mod disk;
 mod draw;
+mod flicker;
+mod fonts;
+mod heif;
 mod images;
+mod keys;
+mod makernote;
 mod metadata;
+mod playlist;
+mod progress;
+mod raw;
+mod slideshow;
+mod spinner;
+mod textures;
+mod ticker;
+mod watch;
+
+// quality of the resized images in the cache, exports use the configured quality
+const CACHE_JPEG_QUALITY: u8 = 90;
+
+// deletes that can be undone, older ones have to be recovered from the trash folder by hand
+const UNDO_LIMIT: usize = 10;
+
+// pixels the metadata moves for each press of UP or DOWN
+const METADATA_SCROLL_STEP: f32 = 40.0;
+
+// photos whose perceptual hashes differ by no more than this many of the 64 bits look the same
+const SIMILAR_HASH_DISTANCE: u32 = 5;
+
+// the most used values shown for each camera setting
+const SHOOT_STATS_BARS: usize = 10;
+
+// zoom steps by this factor for each line the mouse wheel scrolls
+const ZOOM_STEP: f32 = 1.25;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 8.0;
+// the arrow keys pan the zoomed photo by this fraction of the screen
+const PAN_STEP: f32 = 0.1;
+
+/// size of the images in the grid, these are cached alongside the full screen images
+pub const THUMBNAIL_SIZE: UVec2 = UVec2 { x: 320, y: 240 };
+// photos shown either side of the current photo in the filmstrip
+const FILMSTRIP_NEIGHBOURS: usize = 3;
+// thumbnails kept on the GPU, more than a page of the grid on a large screen
+const THUMBNAIL_TEXTURES: usize = 256;
+// photos either side of the current photo uploaded to the GPU before they are navigated to
+const PRELOAD_NEIGHBOURS: usize = 1;
+// how long a notice such as a path copied to the clipboard stays on the screen
+const NOTICE_DURATION: Duration = Duration::from_millis(1500);
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -40,27 +108,89 @@ pub enum Error {
     Log(#[from] log::SetLoggerError),
     #[error("sqlite error: {0:?}")]
     Sqlite(#[from] sqlite::Error),
-    #[error("expected 2 args")]
+    #[error("usage: photo-farm <image file or folder> [--originals <folder>] [--export-quality <1-100>] [--export-max-size <pixels>] [--export-to <folder>] [--rebuild-cache] [--vacuum-cache]")]
     InvalidArgs,
     #[error("exif error: {0:?}")]
     Exif(#[from] exif::Error),
+    #[error("no jpeg images found in {0}")]
+    NoImages(String),
+    #[error("unable to read photo-farm.toml: {0}")]
+    ConfigFile(#[from] toml::de::Error),
+    #[error("invalid setting in {0}")]
+    InvalidConfig(String),
+    #[error("path is not valid unicode: {0:?}")]
+    InvalidPath(PathBuf),
+    #[error("{0} is already in the folder")]
+    AlreadyExists(String),
+    #[error("unable to decode image: {0}")]
+    Decode(String),
+    #[error("heif error: {0}")]
+    Heif(String),
+    #[error("no jpeg preview found in raw file {0}")]
+    NoRawPreview(String),
     #[error("no image metadata")]
     NoImageMetadata,
     #[error("no exif DateTime metadata found")]
     NoExifDateTime,
     #[error("invalid DateTime exif tag: {0:?}")]
     ExifDateTime((String, ParseError)),
+    #[error("unable to load font: {0}")]
+    Font(String),
+    #[error("clipboard error: {0}")]
+    Clipboard(#[from] arboard::Error),
 }
 
-#[derive(Debug)]
+impl Error {
+    /// the photo itself is damaged, for example a truncated download or a file that is still syncing
+    fn is_broken_image(&self) -> bool {
+        matches!(
+            self,
+            Error::Image(_) | Error::Decode(_) | Error::Heif(_) | Error::NoRawPreview(_)
+        )
+    }
+
+    /// the photo has no exif or no capture date in it, unlike a file that could not be read this time
+    fn is_missing_date(&self) -> bool {
+        matches!(
+            self,
+            Error::NoExifDateTime | Error::Exif(exif::Error::NotFound(_))
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ImageNamePair {
-    /// name of the jpg file e.g. "IMG_0771.JPG"
+    /// name of the jpg file e.g. "IMG_0771.JPG", or of the raw file when there is no jpg
     pub jpg_file_name: String,
     /// for example .cr2 raw files with the same name as the jpg
     /// e.g. vec!["IMG_0771.CR2"]
     pub other_file_names: Vec<String>,
     pub is_starred: bool,
-    pub date_time: Option<NaiveDateTime>,
+    /// 0 is unrated, otherwise 1 to 5 stars
+    pub rating: u8,
+    /// quarter turns clockwise chosen by the user, applied after the exif orientation
+    pub rotation: u8,
+    /// 0 is no label, otherwise 1 red, 2 yellow or 3 green
+    pub color_label: u8,
+    /// protected images are never moved or deleted, even by batch operations
+    pub is_protected: bool,
+    /// a maybe from a first pass, kept apart from the stars given to final selects
+    pub is_flagged: bool,
+    /// when the photo was taken, in the time zone recorded by the camera
+    pub date_time: Option<DateTime<FixedOffset>>,
+    /// shared by neighbouring photos that look the same, found by a similarity scan.
+    /// These are skipped over together like a burst
+    pub group_id: Option<i64>,
+    /// the full resolution files this image is a proof of, when viewing a folder of proofs
+    pub original: Option<Original>,
+}
+
+/// Full resolution files in a separate originals folder, matched to a proof by file name stem
+#[derive(Debug, Clone)]
+pub struct Original {
+    pub path: String,
+    pub jpg_file_name: String,
+    pub other_file_names: Vec<String>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -72,6 +202,66 @@ enum RenderState {
     Exporting,
     Help,
     Metadata,
+    ExifDump,
+    Grid,
+    Slideshow,
+    /// the two images marked for comparison side by side
+    Compare,
+    /// how many photos there are, how they have been rated and how many are still to be cached
+    Stats,
+    /// the ISOs, apertures and focal lengths used most across the folder
+    ShootStats,
+    /// something went wrong with the current photo, the message is shown until the user skips it.
+    /// A photo that cannot be decoded is shown as a broken image
+    ErrorMessage {
+        message: String,
+        broken_image: bool,
+    },
+}
+
+/// What the ruler of a photo is drawn from, read from the file the first time the ruler is shown
+#[derive(Debug, Clone, Copy)]
+struct RulerSource {
+    /// size of the image as it is stored in the file
+    width: u32,
+    height: u32,
+    orientation: Option<u32>,
+    pixels_per_mm: Option<f64>,
+}
+
+/// The images either side of the current image are uploaded to the texture cache on the frame after the
+/// current image was first drawn, so that they never hold up the frame that shows it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Preload {
+    /// the current image has not been drawn yet
+    Waiting,
+    /// the current image is on the screen, the neighbours are uploaded on the next frame
+    Due,
+    Done,
+}
+
+/// An image moved to the trash folder that can be put back with CTRL+Z
+#[derive(Debug)]
+struct DeletedImage {
+    /// where the image was in the list of images
+    index: usize,
+    image: ImageNamePair,
+}
+
+/// An action that only runs once the user has confirmed it
+#[derive(Debug, PartialEq, Eq)]
+enum ConfirmAction {
+    Export,
+    /// star every photo that the filters show
+    StarAllShown,
+    ClearAllStars,
+}
+
+/// A question drawn over the current screen, Y or Enter runs the action and N or Escape cancels it
+#[derive(Debug)]
+struct ConfirmState {
+    message: String,
+    action: ConfirmAction,
 }
 
 fn main() -> Result<(), Error> {
@@ -79,43 +269,161 @@ fn main() -> Result<(), Error> {
 
     let args: Vec<String> = env::args().collect();
     info!("Args: {args:?}");
-    if args.len() != 2 {
-        return Err(Error::InvalidArgs);
-    }
+    let args = Args::parse(&args)?;
 
-    let file_name = &args[1];
-    let path = PathBuf::from(file_name);
+    let path = match args.file_name {
+        Some(file_name) => PathBuf::from(file_name),
+        // started without a photo, for example from a shortcut to the program
+        None => match rfd::FileDialog::new().pick_folder() {
+            Some(folder) => folder,
+            None => {
+                info!("No folder was chosen");
+                return Ok(());
+            }
+        },
+    };
 
-    let name = path
-        .file_name()
-        .expect("not a valid file")
-        .to_str()
-        .expect("empty file name");
+    // a file opens at that file, a folder opens at the configured start position
+    let (path, name) = if path.is_dir() {
+        (path.as_path(), None)
+    } else {
+        let invalid = || Error::InvalidPath(path.clone());
+        let name = path.file_name().ok_or_else(invalid)?;
+        let name = name.to_str().ok_or_else(invalid)?;
+        (path.parent().ok_or_else(invalid)?, Some(name))
+    };
 
     let path = path
-        .parent()
-        .expect("not a valid folder")
         .to_str()
-        .expect("empty parent folder")
+        .ok_or_else(|| Error::InvalidPath(path.to_owned()))?
         .to_owned();
 
     info!("Working folder: {path}");
 
-    let connection = Arc::new(Mutex::new(db::get_or_create_db(&path)?));
-    let image_file_names = build_file_list(&path, connection.clone())?;
+    // a broken settings file should not stop the photos from being viewed
+    let mut config = config::load(&path).unwrap_or_else(|e| {
+        error!("Using the default settings because of an error: {e}");
+        Config::default()
+    });
+    config.originals = args.originals;
+    if let Some(quality) = args.export_quality {
+        config.export.quality = quality;
+    }
+    if let Some(max_dimension) = args.export_max_dimension {
+        config.export.max_dimension = Some(max_dimension);
+    }
+    config.export.destination = args.export_to.map(PathBuf::from);
+    // browsing photos on read only media keeps the database elsewhere and disables anything that writes to the folder
+    let read_only = !disk::is_writable(&path);
+    let connection = if read_only {
+        warn!("{path} is read only, starring and exporting are disabled");
+        Arc::new(Mutex::new(db::get_or_create_read_only_db(&path)?))
+    } else {
+        Arc::new(Mutex::new(db::get_or_create_db(&path)?))
+    };
+
+    // ignored files are kept so that their stars are still there if they are no longer ignored. A folder
+    // that cannot be listed or has no photos may be on a drive that is not mounted, so nothing is pruned
+    match disk::list_photo_names(&path) {
+        Ok(names) if !names.is_empty() => {
+            let existing_names: HashSet<String> = names.into_iter().collect();
+            let pruned = db::prune_cache(&existing_names, connection.clone())?;
+            if pruned > 0 {
+                info!("Pruned {pruned} rows for photos no longer in {path}");
+            }
+        }
+        Ok(_) => warn!("No photos in {path}, the cache is not pruned"),
+        Err(e) => warn!("Unable to list {path}, the cache is not pruned: {e:?}"),
+    }
+    if args.vacuum_cache {
+        info!("Compacting the cache");
+        db::vacuum_cache(connection.clone())?;
+    }
+
+    // fall back to caching in this folder only if the shared cache cannot be opened
+    let shared_cache = match config
+        .shared_cache
+        .as_deref()
+        .map(db::get_or_create_shared_db)
+    {
+        Some(Ok(shared_connection)) => Some(Arc::new(Mutex::new(shared_connection))),
+        Some(Err(e)) => {
+            error!("Unable to open shared thumbnail cache: {e:?}");
+            None
+        }
+        None => None,
+    };
+
+    // a photo that was opened is shown straight away and the rest of the folder is listed in the background,
+    // which takes a while for thousands of photos. Rebuilding the cache needs the whole folder
+    let opened = name
+        .filter(|_| !args.rebuild_cache)
+        .and_then(disk::get_opened_file_name);
+    let listing = opened.is_some();
+    let image_file_names = match opened {
+        Some(opened) => look_up(&path, &config, vec![opened], &[], connection.clone())?,
+        None => build_file_list(&path, &config, &[], connection.clone())?,
+    };
     if image_file_names.is_empty() {
-        // no images exit early
-        info!("No images");
-        return Ok(());
+        return Err(Error::NoImages(path));
+    }
+
+    // a folder remembers whether the exif orientation was turned off for it
+    let exif_orientation = db::get_exif_orientation(connection.clone())?;
+    let exif_orientation = Arc::new(AtomicBool::new(
+        exif_orientation.unwrap_or(config.exif_orientation),
+    ));
+    let mut images = match name {
+        Some(name) => Images::new(&path, name, image_file_names),
+        None => {
+            let last_viewed = db::get_last_viewed(connection.clone())?;
+            Images::new_at(
+                &path,
+                config.start_position,
+                image_file_names,
+                last_viewed.as_deref(),
+            )
+        }
+    };
+    images.set_group_threshold(config.navigation.group_threshold);
+    if args.rebuild_cache {
+        let names: Vec<String> = images
+            .all()
+            .iter()
+            .map(|x| x.jpg_file_name.clone())
+            .collect();
+        rebuild_cache(
+            &path,
+            &names,
+            config.resize_filter.filter_type(),
+            exif_orientation.load(Ordering::Relaxed),
+            connection.clone(),
+        )?;
     }
-    let images = Images::new(&path, name, image_file_names);
+
     let window = Window::new_fullscreen_borderless("Image Viewer").expect("cannot create window");
     let screen_resolution = UVec2 { x: 800, y: 600 };
-    let font = Font::new(include_bytes!("../fonts/NotoSans-Regular.ttf")).unwrap();
-    let progress_percentage = Arc::new(AtomicI32::new(100));
+    let font = load_font(&config)?;
+    let progress = Arc::new(Progress::new(config.progress.smoothing));
     let user_event_sender = Arc::new(Mutex::new(window.create_user_event_sender()));
 
-    let (resolution_tx, resolution_rx) = channel();
+    let (cache_tx, cache_rx) = channel();
+
+    // list the whole folder
+    let listing = listing.then(|| {
+        let (listing_tx, listing_rx) = channel();
+        let path_l = path.clone();
+        let config_l = config.clone();
+        let connection_l = connection.clone();
+        let user_event_sender_l = user_event_sender.clone();
+        thread::spawn(move || {
+            let result = build_file_list(&path_l, &config_l, &[], connection_l);
+            let _ = listing_tx.send(result);
+            let locked = user_event_sender_l.lock().unwrap();
+            locked.send_event(()).unwrap();
+        });
+        listing_rx
+    });
 
     let image_file_names: Vec<String> = images
         .all()
@@ -123,21 +431,32 @@ fn main() -> Result<(), Error> {
         .map(|x| x.jpg_file_name.clone())
         .collect();
     let image_index = images.current_index();
+    let originals: HashMap<String, Original> = images
+        .all()
+        .iter()
+        .filter_map(|x| Some((x.jpg_file_name.clone(), x.original.clone()?)))
+        .collect();
     let (tx, rx) = channel();
 
     // listen for resize requests
     let path_c = path.clone();
     let image_file_names_c = image_file_names.clone();
     let connection_c = connection.clone();
-    let progress_percentage_c = progress_percentage.clone();
+    let resize_progress = Arc::new(Progress::new(config.progress.smoothing));
+    let resize_progress_c = resize_progress.clone();
     let user_event_sender_c = user_event_sender.clone();
+    let export_config = config.export.clone();
+    let exif_orientation_c = exif_orientation.clone();
     thread::spawn(move || {
         resize_and_export(
             &path_c,
-            &image_file_names_c,
+            image_file_names_c,
+            originals,
+            export_config,
+            exif_orientation_c,
             connection_c,
             rx,
-            progress_percentage_c,
+            resize_progress_c,
             user_event_sender_c,
         )
     });
@@ -145,253 +464,713 @@ fn main() -> Result<(), Error> {
     // maintain image cache
     let connection_t = connection.clone();
     let path_t = path.clone();
-    let progress_percentage_t = progress_percentage.clone();
+    let progress_t = progress.clone();
+    let filter_t = config.resize_filter.filter_type();
+    let exif_orientation_t = exif_orientation.clone();
+    let cache_failures = Arc::new(Mutex::new(HashMap::new()));
+    let cache_failures_t = cache_failures.clone();
+    let user_event_sender_t = user_event_sender.clone();
+    let num_workers = config.cache_workers.unwrap_or_else(|| {
+        thread::available_parallelism()
+            .map(|x| x.get())
+            .unwrap_or(1)
+    });
     thread::spawn(move || {
-        update_cache(
+        cache::update_cache(
             path_t,
             image_file_names,
             image_index,
             connection_t,
-            progress_percentage_t,
-            user_event_sender,
-            resolution_rx,
+            shared_cache,
+            filter_t,
+            exif_orientation_t,
+            progress_t,
+            cache_failures_t,
+            user_event_sender_t,
+            cache_rx,
+            num_workers,
         )
     });
 
+    // look for photos added while the folder is open
+    let (new_photos_tx, new_photos_rx) = channel();
+    let path_w = path.clone();
+    let user_event_sender_w = user_event_sender.clone();
+    thread::spawn(move || watch::watch_folder(path_w, new_photos_tx, user_event_sender_w));
+
+    let textures = TextureCache::new(config.texture_cache_size);
+    let decoded = DecodeCache::new(
+        path.clone(),
+        config.decode_cache_mb * 1024 * 1024,
+        exif_orientation.clone(),
+        user_event_sender.clone(),
+    );
+    let export_progress = Arc::new(Progress::new(config.progress.smoothing));
+    let similar_progress = Arc::new(Progress::new(config.progress.smoothing));
+    let spinner = Spinner::new(user_event_sender.clone());
     window.run_loop(PhotoWindowHandler {
         image: None,
         images,
         screen_resolution,
+        scale_factor: 1.0,
         connection,
         path,
         state: RenderState::Full,
         font,
-        progress_percentage,
-        resolution_tx,
-        show_only_starred: false,
+        progress,
+        cache_tx,
+        cache_failures,
+        new_photos_rx,
+        exif_orientation,
+        filter: ImageFilter::All,
         show_file_name: false,
-        resize_request_tx: tx,
+        show_filmstrip: false,
+        show_calibration: false,
+        show_ruler: false,
+        show_focus_points: false,
+        focus_points: None,
+        ruler: None,
+        confirm: None,
+        export_tx: tx,
+        config,
+        modifiers: ModifiersState::default(),
+        exif_scroll: 0,
+        metadata_scroll: 0.0,
+        metadata_max_scroll: 0.0,
+        fresh_decode: false,
+        date_filter: None,
+        date_input: None,
+        textures,
+        decoded,
+        read_only,
+        zoom: 1.0,
+        zoom_latched: false,
+        zoom_source: None,
+        zoom_pan: None,
+        mouse_position: Vec2::new(0.0, 0.0),
+        previous_image: None,
+        loading_since: None,
+        resized_at: None,
+        preload: Preload::Waiting,
+        grid_index: 0,
+        compare_marks: Vec::new(),
+        deleted: Vec::new(),
+        thumbnails: TextureCache::new(THUMBNAIL_TEXTURES),
+        user_event_sender,
+        slideshow: None,
+        export_progress,
+        exporting: None,
+        listing,
+        similar_progress,
+        resize_progress,
+        finding_similar: None,
+        flicker: None,
+        shoot_stats: Vec::new(),
+        counting_shoot_stats: None,
+        spinner,
+        clipboard: None,
+        notice: None,
     })
 }
 
+/// Scan the folder and look up what is known about each image. Images in `known` keep what was looked up for
+/// them before, only the others are looked up in the database and have their sidecars and dates read
 fn build_file_list(
     path: &str,
+    config: &Config,
+    known: &[ImageNamePair],
     connection: Arc<Mutex<Connection>>,
 ) -> Result<Vec<ImageNamePair>, Error> {
-    let mut image_file_names = disk::get_file_names(path)?;
+    let mut image_file_names = disk::get_file_names(path, config.duplicate_stems)?;
+    if let Some(originals) = &config.originals {
+        disk::match_originals(&mut image_file_names, originals, config.duplicate_stems)?;
+    }
+
+    look_up(path, config, image_file_names, known, connection)
+}
 
-    let names = db::get_starred_image_names(connection)?;
+/// Look up the stars, ratings, capture dates and everything else known about these images. Images in `known`
+/// keep what was looked up for them before
+fn look_up(
+    path: &str,
+    config: &Config,
+    mut image_file_names: Vec<ImageNamePair>,
+    known: &[ImageNamePair],
+    connection: Arc<Mutex<Connection>>,
+) -> Result<Vec<ImageNamePair>, Error> {
+    let known: HashMap<&str, &ImageNamePair> = known
+        .iter()
+        .map(|x| (x.jpg_file_name.as_str(), x))
+        .collect();
     for file in image_file_names.iter_mut() {
+        if let Some(known) = known.get(file.jpg_file_name.as_str()) {
+            *file = (*known).clone();
+        }
+    }
+
+    let names = db::get_starred_image_names(connection.clone())?;
+    let protected_names = db::get_protected_image_names(connection.clone())?;
+    let flagged_names = db::get_flagged_image_names(connection.clone())?;
+    let date_times = db::get_date_times(connection.clone())?;
+    let ratings = db::get_ratings(connection.clone())?;
+    let color_labels = db::get_color_labels(connection.clone())?;
+    let rotations = db::get_rotations(connection.clone())?;
+    let group_ids = db::get_group_ids(connection.clone())?;
+    for file in image_file_names
+        .iter_mut()
+        .filter(|x| !known.contains_key(x.jpg_file_name.as_str()))
+    {
+        let rating = ratings.get(&file.jpg_file_name).copied();
+        let color_label = color_labels.get(&file.jpg_file_name).copied();
+        file.rating = rating.unwrap_or(0);
+        file.color_label = color_label.unwrap_or(0);
+        if let Some(rotation) = rotations.get(&file.jpg_file_name) {
+            file.rotation = *rotation;
+        }
+        file.group_id = group_ids.get(&file.jpg_file_name).copied();
+        if let Some(date_time) = date_times.get(&file.jpg_file_name) {
+            file.date_time = Some(*date_time);
+        }
         if names.contains(&file.jpg_file_name) {
             file.is_starred = true;
         }
+        if protected_names.contains(&file.jpg_file_name) {
+            file.is_protected = true;
+        }
+        if flagged_names.contains(&file.jpg_file_name) {
+            file.is_flagged = true;
+        }
+
+        // ratings and labels chosen in another tool are used until they are changed here
+        if rating.is_none() || color_label.is_none() {
+            read_xmp_sidecar(path, file, rating.is_none(), color_label.is_none());
+        }
+    }
+
+    // needed up front for burst navigation, date filters and sorting. Known images without a date have
+    // already been marked as having none so only new images are read
+    load_date_times(path, &mut image_file_names, connection)?;
+    if config.sort_order == SortOrder::DateTaken {
+        images::sort(&mut image_file_names, config.sort_order);
     }
 
     Ok(image_file_names)
 }
 
+/// Rating and color label from an xmp file with the same name as the image, for whichever of them has never
+/// been chosen here
+fn read_xmp_sidecar(path: &str, image: &mut ImageNamePair, rating: bool, color_label: bool) {
+    let Some(xmp) = image
+        .other_file_names
+        .iter()
+        .find(|x| x.to_lowercase().ends_with(".xmp"))
+    else {
+        return;
+    };
+
+    match metadata::read_xmp_sidecar(path, xmp) {
+        Ok(sidecar) => {
+            if rating {
+                image.rating = sidecar.rating.unwrap_or(0);
+            }
+            if color_label {
+                image.color_label = sidecar.color_label.unwrap_or(0);
+            }
+        }
+        Err(e) => warn!("Unable to read {xmp}: {e:?}"),
+    }
+}
+
+/// Read the capture date of images that do not have one yet from their exif and remember it in the database
+/// so that this is only slow the first time. Images found to have no date are remembered too
+fn load_date_times(
+    path: &str,
+    images: &mut [ImageNamePair],
+    connection: Arc<Mutex<Connection>>,
+) -> Result<(), Error> {
+    let no_date_names = db::get_no_date_image_names(connection.clone())?;
+    for image in images
+        .iter_mut()
+        .filter(|x| x.date_time.is_none() && !no_date_names.contains(&x.jpg_file_name))
+    {
+        let name = &image.jpg_file_name;
+        match metadata::read_capture_time(path, name) {
+            Ok(date_time) => {
+                db::update_date_time(name, date_time, connection.clone())?;
+                image.date_time = Some(date_time);
+            }
+            // only a photo without a date is remembered, one that could not be read is tried again next time
+            Err(e) if e.is_missing_date() => {
+                info!("No capture date for {name}: {e:?}");
+                db::set_no_date(name, connection.clone())?;
+            }
+            Err(e) => warn!("Unable to read the capture date of {name}: {e:?}"),
+        }
+    }
+
+    Ok(())
+}
+
 fn load_and_insert_image(
     path: &str,
     name: &str,
     size: UVec2,
+    filter: FilterType,
+    exif_orientation: bool,
     connection: Arc<Mutex<Connection>>,
+    shared_cache: Option<Arc<Mutex<Connection>>>,
 ) -> Result<Vec<u8>, Error> {
-    let (img, metadata) = load_image(path, name)?;
-    let resized = resize_jpg(&img, size)?;
-    db::insert_image(name, size, &resized, metadata, connection)?;
-    Ok(resized)
-}
+    // the shared cache is keyed by file contents so it cannot hold images rotated in the viewer
+    // or images that ignore their exif orientation
+    let rotation = db::get_rotation(name, connection.clone())?;
+    let shared = match shared_cache {
+        Some(shared_cache) if rotation == 0 && exif_orientation => {
+            Some((disk::content_hash(path, name)?, shared_cache))
+        }
+        _ => None,
+    };
 
-pub fn calculate_position_middle(screen_resolution: UVec2, image: &ImageHandle) -> Vec2 {
-    let x = (screen_resolution.x - image.size().x) as f32 / 2.0;
-    let y = (screen_resolution.y - image.size().y) as f32 / 2.0;
-    Vec2 { x, y }
+    // an identical file in another folder may already have been resized
+    if let Some((hash, shared_cache)) = &shared {
+        if let Some(resized) = db::try_get_shared_image(hash, size, shared_cache.clone())? {
+            info!("Using shared thumbnail for {name}");
+            let metadata = metadata::get_metadata(path, name).ok();
+            let img = image::load_from_memory(&resized)?;
+            let exposure = metadata::exposure_score(&img);
+            db::insert_image(name, size, &resized, metadata.clone(), connection.clone())?;
+            insert_thumbnail(name, &img, size, filter, metadata, connection.clone())?;
+            db::update_image_exposure(name, exposure, connection)?;
+            return Ok(resized);
+        }
+    }
+
+    let (img, metadata) = load_image(path, name, rotation, exif_orientation)?;
+    let resized = insert_decoded_image(name, &img, metadata, size, filter, connection)?;
+
+    if let Some((hash, shared_cache)) = shared {
+        db::insert_shared_image(&hash, size, &resized, shared_cache)?;
+    }
+
+    Ok(resized)
 }
 
-fn update_cache(
-    path: String,
-    image_file_names: Vec<String>,
-    image_index: usize,
+/// Resize every image again and wait until it is done, for example after the cache quality has changed.
+/// Only the resized images are thrown away, not the stars and other user data. Images are saved one at a time
+/// so if the rebuild is interrupted the background cache resizes the rest as usual the next time
+fn rebuild_cache(
+    path: &str,
+    names: &[String],
+    filter: FilterType,
+    exif_orientation: bool,
     connection: Arc<Mutex<Connection>>,
-    progress_percentage: Arc<AtomicI32>,
-    user_event_sender: Arc<Mutex<UserEventSender<()>>>,
-    resolution_rx: Receiver<UVec2>,
 ) -> Result<(), Error> {
-    // start resizing from one after the current photo (so we don't duplicate effort on startup)
-    // then continue resizing from start
-    let image_file_names: Vec<&String> = image_file_names
-        .iter()
-        .skip(image_index + 1)
-        .chain(image_file_names.iter().take(image_index + 1))
-        .collect();
+    // the window does not exist yet so use the resolution most images were last resized to
+    let size = db::get_cached_resolutions(connection.clone())?
+        .into_iter()
+        .find(|x| *x != THUMBNAIL_SIZE);
+    if size.is_none() {
+        warn!("No screen resolution in the cache, only thumbnails are rebuilt");
+    }
 
-    while let Ok(size) = resolution_rx.recv() {
-        // screen resolution can change rapidly on startup, we dont want to do work if not needed
-        thread::sleep(Duration::from_millis(1000));
-        resize_images(
-            &path,
-            &image_file_names,
-            connection.clone(),
-            progress_percentage.clone(),
-            user_event_sender.clone(),
-            &resolution_rx,
-            size,
-        )?;
+    info!("Rebuilding the cache for {} images", names.len());
+    db::remove_all_resized_images(connection.clone())?;
+    for (index, name) in names.iter().enumerate() {
+        for size in size.into_iter().chain([THUMBNAIL_SIZE]) {
+            // the thumbnail is made along with the screen sized image
+            if db::photo_exists(name, size, connection.clone())? {
+                continue;
+            }
+
+            // the shared cache may hold images encoded at the old quality
+            let result = load_and_insert_image(
+                path,
+                name,
+                size,
+                filter,
+                exif_orientation,
+                connection.clone(),
+                None,
+            );
+            if let Err(e) = result {
+                error!("Unable to rebuild {name}: {e:?}");
+            }
+        }
+        info!("Rebuilt {}/{} {name}", index + 1, names.len());
     }
 
-    info!("UpdateCache ended");
     Ok(())
 }
 
-fn resize_images(
-    path: &String,
-    image_file_names: &Vec<&String>,
+/// cache an image decoded by `load_image` resized to `size`, and for the grid
+fn insert_decoded_image(
+    name: &str,
+    img: &DynamicImage,
+    metadata: Option<ImageMetadata>,
+    size: UVec2,
+    filter: FilterType,
     connection: Arc<Mutex<Connection>>,
-    progress_percentage: Arc<AtomicI32>,
-    user_event_sender: Arc<Mutex<UserEventSender<()>>>,
-    resolution_rx: &Receiver<UVec2>,
+) -> Result<Vec<u8>, Error> {
+    let resized = resize_jpg(img, size, filter)?;
+    let exposure = metadata::exposure_score(img);
+    db::insert_image(name, size, &resized, metadata.clone(), connection.clone())?;
+    insert_thumbnail(name, img, size, filter, metadata, connection.clone())?;
+    db::update_image_exposure(name, exposure, connection)?;
+    Ok(resized)
+}
+
+/// resize an image that has just been decoded for the grid too, unless that has been done already
+fn insert_thumbnail(
+    name: &str,
+    img: &DynamicImage,
     size: UVec2,
+    filter: FilterType,
+    metadata: Option<ImageMetadata>,
+    connection: Arc<Mutex<Connection>>,
 ) -> Result<(), Error> {
-    let num_images = image_file_names.len();
-    for (i, image_file) in image_file_names.iter().enumerate() {
-        match resolution_rx.try_recv() {
-            // resolution has changed, we need to start again
-            Ok(size) => {
-                resize_images(
-                    path,
-                    image_file_names,
-                    connection,
-                    progress_percentage,
-                    user_event_sender,
-                    resolution_rx,
-                    size,
-                )?;
-                return Ok(());
-            }
-            Err(TryRecvError::Empty) => {}
-            Err(TryRecvError::Disconnected) => {
-                info!("UpdateCache ended early");
-                return Ok(());
-            }
-        }
+    if size == THUMBNAIL_SIZE || db::photo_exists(name, THUMBNAIL_SIZE, connection.clone())? {
+        return Ok(());
+    }
 
-        if db::photo_exists(image_file, size, connection.clone())? {
-            info!("Photo already exists, skipping...");
-        } else {
-            load_and_insert_image(path, image_file, size, connection.clone())?;
-        }
+    let thumbnail = resize_jpg(img, THUMBNAIL_SIZE, filter)?;
+    db::insert_image(name, THUMBNAIL_SIZE, &thumbnail, metadata, connection)
+}
 
-        // display progress on the screen
-        let percentage = (100.0 * (i + 1) as f64 / num_images as f64).ceil() as i32;
-        progress_percentage.store(percentage, Ordering::Relaxed);
-        let locked = user_event_sender.lock().unwrap();
-        locked.send_event(()).unwrap();
-    }
-    Ok(())
+pub fn calculate_position_middle(screen_resolution: UVec2, image: &ImageHandle) -> Vec2 {
+    let x = (screen_resolution.x - image.size().x) as f32 / 2.0;
+    let y = (screen_resolution.y - image.size().y) as f32 / 2.0;
+    Vec2 { x, y }
 }
 
-fn load_image(path: &str, name: &str) -> Result<(DynamicImage, Option<ImageMetadata>), Error> {
-    let file_name = disk::get_full_path(path, name);
-    let file = File::open(file_name)?;
-    let reader = BufReader::new(&file);
-    let img = image::load(reader, image::ImageFormat::Jpeg).unwrap();
+fn load_image(
+    path: &str,
+    name: &str,
+    rotation: u8,
+    exif_orientation: bool,
+) -> Result<(DynamicImage, Option<ImageMetadata>), Error> {
+    let file_name = disk::get_full_path(path, name)?;
+    let is_heif = disk::is_heif(name);
+    let img = if is_heif {
+        heif::load(&file_name)?
+    } else if disk::is_raw(name) {
+        raw::load_preview(&file_name)?
+    } else {
+        // jpeg, png or tiff
+        let format = image::ImageFormat::from_path(&file_name)?;
+        let file = File::open(file_name)?;
+        let reader = BufReader::new(&file);
+        image::load(reader, format)?
+    };
 
+    // the exif in a heif, png or tiff file is read the same way as a jpeg
     match metadata::get_metadata(path, name) {
         Ok(metadata) => {
             info!("{:?}", metadata);
 
-            // rotate image if it contains exif metadata to do so
-            let img = match metadata.orientation {
-                Some(8) => img.rotate270(),
-                Some(3) => img.rotate180(),
-                Some(6) => img.rotate90(),
-                _ => img, // do nothing
-            };
+            // rotate image if it contains exif metadata to do so unless the user has turned that off,
+            // heif images are already rotated
+            let orientation = metadata
+                .orientation
+                .filter(|_| !is_heif && exif_orientation);
+            let img = apply_orientation(img, orientation, rotation);
 
             Ok((img, Some(metadata)))
         }
         Err(_) => {
-            // some jpegs do not have exif data
-            Ok((img, None))
+            // some jpegs do not have exif data, and screenshots rarely do
+            Ok((apply_orientation(img, None, rotation), None))
         }
     }
 }
 
-fn crop_center(img: DynamicImage, size: UVec2) -> Result<DynamicImage, Error> {
-    let width = size.x;
-    let height = size.y;
+/// Rotate an image the way it is displayed. The full screen, zoomed and exported views all get
+/// their image from load_image which calls this, so any other rotation (e.g. one chosen by the
+/// user) must be added here rather than in one of those views or they will disagree.
+/// `rotation` is the number of quarter turns clockwise chosen by the user
+fn apply_orientation(img: DynamicImage, orientation: Option<u32>, rotation: u8) -> DynamicImage {
+    orientation_turns(orientation, rotation)
+        .into_iter()
+        .fold(img, |img, turn| match turn {
+            Turn::FlipHorizontal => img.fliph(),
+            Turn::FlipVertical => img.flipv(),
+            Turn::Clockwise90 => img.rotate90(),
+            Turn::Clockwise180 => img.rotate180(),
+            Turn::Clockwise270 => img.rotate270(),
+        })
+}
+
+/// Where a focus point of the stored image is drawn, turned by the same steps as `apply_orientation`
+fn orient_focus_point(point: FocusPoint, orientation: Option<u32>, rotation: u8) -> FocusPoint {
+    orientation_turns(orientation, rotation)
+        .into_iter()
+        .fold(point, |point, turn| {
+            let FocusPoint {
+                x,
+                y,
+                width,
+                height,
+            } = point;
+            match turn {
+                Turn::FlipHorizontal => FocusPoint {
+                    x: 1.0 - x,
+                    ..point
+                },
+                Turn::FlipVertical => FocusPoint {
+                    y: 1.0 - y,
+                    ..point
+                },
+                Turn::Clockwise90 => FocusPoint {
+                    x: 1.0 - y,
+                    y: x,
+                    width: height,
+                    height: width,
+                },
+                Turn::Clockwise180 => FocusPoint {
+                    x: 1.0 - x,
+                    y: 1.0 - y,
+                    ..point
+                },
+                Turn::Clockwise270 => FocusPoint {
+                    x: y,
+                    y: 1.0 - x,
+                    width: height,
+                    height: width,
+                },
+            }
+        })
+}
+
+/// true when the displayed image is on its side compared with the image stored in the file
+fn is_quarter_turned(orientation: Option<u32>, rotation: u8) -> bool {
+    let quarter_turns = orientation_turns(orientation, rotation)
+        .into_iter()
+        .filter(|x| matches!(x, Turn::Clockwise90 | Turn::Clockwise270))
+        .count();
+    quarter_turns % 2 == 1
+}
+
+/// A step in turning an image the way it is displayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Turn {
+    FlipHorizontal,
+    FlipVertical,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+/// The steps that undo an exif orientation, 2, 4, 5 and 7 are mirrored, followed by the quarter turns
+/// clockwise chosen by the user
+fn orientation_turns(orientation: Option<u32>, rotation: u8) -> Vec<Turn> {
+    let mut turns = match orientation {
+        Some(2) => vec![Turn::FlipHorizontal],
+        Some(3) => vec![Turn::Clockwise180],
+        Some(4) => vec![Turn::FlipVertical],
+        Some(5) => vec![Turn::Clockwise90, Turn::FlipHorizontal],
+        Some(6) => vec![Turn::Clockwise90],
+        Some(7) => vec![Turn::Clockwise270, Turn::FlipHorizontal],
+        Some(8) => vec![Turn::Clockwise270],
+        _ => vec![], // do nothing
+    };
+
+    match rotation % 4 {
+        1 => turns.push(Turn::Clockwise90),
+        2 => turns.push(Turn::Clockwise180),
+        3 => turns.push(Turn::Clockwise270),
+        _ => {}
+    }
+    turns
+}
+
+/// The part of the image around `center` (0.0 to 1.0 across the image) that fills the screen when magnified
+/// by `zoom`, in pixels of the image. The region is moved so that it never goes past the edges of the image
+fn crop_rect(img: &DynamicImage, center: Vec2, zoom: f32, screen: UVec2) -> Rectangle<u32> {
+    let width = ((screen.x as f32 / zoom) as u32).clamp(1, img.width());
+    let height = ((screen.y as f32 / zoom) as u32).clamp(1, img.height());
+    let x = (center.x * img.width() as f32 - width as f32 / 2.0)
+        .clamp(0.0, (img.width() - width) as f32) as u32;
+    let y = (center.y * img.height() as f32 - height as f32 / 2.0)
+        .clamp(0.0, (img.height() - height) as f32) as u32;
+    Rectangle::new(UVec2::new(x, y), UVec2::new(x + width, y + height))
+}
+
+/// the part of the image in `crop` magnified by `zoom`
+fn crop_region(img: &DynamicImage, crop: &Rectangle<u32>, zoom: f32) -> DynamicImage {
+    let (width, height) = (crop.width(), crop.height());
+    let region = img.crop_imm(crop.top_left().x, crop.top_left().y, width, height);
 
-    if width < img.width() && height < img.height() {
-        let x = img.width() / 2 - width / 2;
-        let y = img.height() / 2 - height / 2;
-        let img = img.crop_imm(x, y, width, height);
-        Ok(img)
+    if zoom == 1.0 {
+        region
     } else {
-        Ok(img)
+        let zoomed_width = ((width as f32 * zoom) as u32).max(1);
+        let zoomed_height = ((height as f32 * zoom) as u32).max(1);
+        region.resize_exact(zoomed_width, zoomed_height, FilterType::Triangle)
     }
 }
 
-fn resize_jpg(img: &DynamicImage, size: UVec2) -> Result<Vec<u8>, Error> {
-    // this takes a long time
-    let resized = img.resize(size.x, size.y, FilterType::CatmullRom); // cubic filter
-    let buf = encode_jpg(&resized)?;
+/// resize for the cache, `filter` trades quality for speed. The image is still stored under `size`
+/// when it is smaller than that so that it is found again
+fn resize_jpg(img: &DynamicImage, size: UVec2, filter: FilterType) -> Result<Vec<u8>, Error> {
+    let buf = if fits_within(img, size) {
+        encode_jpg(img, CACHE_JPEG_QUALITY)?
+    } else {
+        encode_jpg(&img.resize(size.x, size.y, filter), CACHE_JPEG_QUALITY)?
+    };
     Ok(buf)
 }
 
-fn encode_jpg(img: &DynamicImage) -> Result<Vec<u8>, Error> {
+fn resize_image(img: &DynamicImage, size: UVec2) -> DynamicImage {
+    if fits_within(img, size) {
+        return img.clone();
+    }
+
+    // this takes a long time
+    img.resize(size.x, size.y, FilterType::CatmullRom) // cubic filter
+}
+
+/// small images are never enlarged because that only makes them blurry, they are drawn in the middle of the screen
+fn fits_within(img: &DynamicImage, size: UVec2) -> bool {
+    img.width() <= size.x && img.height() <= size.y
+}
+
+/// `quality` is from 1 to 100
+fn encode_jpg(img: &DynamicImage, quality: u8) -> Result<Vec<u8>, Error> {
     let mut buf_out: Vec<u8> = Vec::new();
 
     {
-        let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf_out, 90);
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf_out, quality);
         encoder.encode_image(img)?;
     }
 
     Ok(buf_out)
 }
 
-fn resolution_ok(screen_resolution: UVec2) -> bool {
-    screen_resolution.x >= 1024
+fn resolution_ok(screen_resolution: UVec2, config: &Config) -> bool {
+    screen_resolution.x >= config.min_resolution_width
+}
+
+/// Group neighbouring photos that look the same so that they are skipped over together, for bursts
+/// taken within the same second that capture times cannot tell apart. Only photos with a cached
+/// thumbnail are compared. This runs on a background thread and returns the group of each photo
+fn find_similar_photos(
+    names: Vec<String>,
+    connection: Arc<Mutex<Connection>>,
+    progress: &Progress,
+    user_event_sender: &Mutex<UserEventSender<()>>,
+) -> Result<Vec<(String, Option<i64>)>, Error> {
+    progress.start(names.len());
+    let mut hashes = Vec::with_capacity(names.len());
+    for name in &names {
+        let thumbnail = db::try_get_image_from_db(name, THUMBNAIL_SIZE, connection.clone())?;
+        let hash = match thumbnail {
+            Some(thumbnail) => Some(metadata::perceptual_hash(&image::load_from_memory(
+                &thumbnail,
+            )?)),
+            None => None,
+        };
+        hashes.push(hash);
+
+        // display progress on the screen
+        progress.complete_one();
+        let locked = user_event_sender.lock().unwrap();
+        locked.send_event(()).unwrap();
+    }
+
+    // each photo joins the group of the one before it when they look alike
+    let mut group_ids: Vec<Option<i64>> = vec![None; hashes.len()];
+    for index in 1..hashes.len() {
+        if let (Some(a), Some(b)) = (hashes[index - 1], hashes[index]) {
+            if (a ^ b).count_ones() <= SIMILAR_HASH_DISTANCE {
+                let group_id = group_ids[index - 1].unwrap_or(index as i64 - 1);
+                group_ids[index - 1] = Some(group_id);
+                group_ids[index] = Some(group_id);
+            }
+        }
+    }
+
+    let grouped = group_ids.iter().filter(|x| x.is_some()).count();
+    info!("{grouped} photos look the same as a neighbour");
+    let group_ids: Vec<(String, Option<i64>)> = names.into_iter().zip(group_ids).collect();
+    db::set_group_ids(&group_ids, connection)?;
+    Ok(group_ids)
+}
+
+/// Messages sent from the window to the background export thread
+#[derive(Debug)]
+enum ExportMessage {
+    /// export the starred images scaled by this factor
+    Resize(f32),
+    /// the folder has been scanned again, these are all the images in it and their originals
+    Files(Vec<String>, HashMap<String, Original>),
 }
 
+#[allow(clippy::too_many_arguments)]
 fn resize_and_export(
     path: &str,
-    image_file_names: &[String],
+    mut image_file_names: Vec<String>,
+    mut originals: HashMap<String, Original>,
+    config: ExportConfig,
+    exif_orientation: Arc<AtomicBool>,
     connection: Arc<Mutex<Connection>>,
-    rx: Receiver<f32>,
-    progress_percentage: Arc<AtomicI32>,
+    rx: Receiver<ExportMessage>,
+    progress: Arc<Progress>,
     user_event_sender: Arc<Mutex<UserEventSender<()>>>,
 ) -> Result<(), Error> {
+    let dest = export_folder(path, &config);
     loop {
         match rx.recv() {
-            Ok(resize_factor) => {
-                //progress_percentage.store(0, Ordering::Relaxed);
+            Ok(ExportMessage::Files(names, new_originals)) => {
+                image_file_names = names;
+                originals = new_originals;
+            }
+            Ok(ExportMessage::Resize(resize_factor)) => {
                 let names = db::get_starred_image_names(connection.clone())?;
+                let rotations = db::get_rotations(connection.clone())?;
+                let date_times = db::get_date_times(connection.clone())?;
                 let starred_images: Vec<&String> = image_file_names
                     .iter()
                     .filter(|x| names.contains(*x))
                     .collect();
 
                 {
-                    progress_percentage.store(0, Ordering::Relaxed);
+                    progress.start(starred_images.len());
                     let locked = user_event_sender.lock().unwrap();
                     locked.send_event(()).unwrap();
                 }
 
-                for (i, starred_image) in starred_images.iter().enumerate() {
-                    let name = starred_image;
-                    let (img, _metadata) = load_image(path, name)?;
-                    let (x, y) = img.dimensions();
-                    let size = UVec2 {
-                        x: (x as f32 * resize_factor) as u32,
-                        y: (y as f32 * resize_factor) as u32,
+                for starred_image in starred_images.iter() {
+                    // proofs are exported from their full resolution original
+                    let (source_path, name) = match originals.get(*starred_image) {
+                        Some(original) => (original.path.as_str(), &original.jpg_file_name),
+                        None => (path, *starred_image),
                     };
-                    let resized = resize_jpg(&img, size)?;
-                    disk::export_image(path, name, &resized)?;
+                    let date_time = date_times.get(*starred_image).copied();
+                    let folder = disk::export_subfolder(&dest, config.layout, date_time);
+                    if disk::exported_image_exists(&folder, name) {
+                        warn!("Not exporting {name} because it has already been exported");
+                        progress.complete_one();
+                        let locked = user_event_sender.lock().unwrap();
+                        locked.send_event(()).unwrap();
+                        continue;
+                    }
+
+                    // a photo that cannot be decoded is skipped so that the rest are still exported
+                    let rotation = rotations.get(*starred_image).copied().unwrap_or(0);
+                    let exif_orientation = exif_orientation.load(Ordering::Relaxed);
+                    let result = export_resized(
+                        source_path,
+                        name,
+                        &folder,
+                        rotation,
+                        exif_orientation,
+                        resize_factor,
+                        &config,
+                    );
+                    if let Err(e) = result {
+                        error!("Unable to export {name}: {e:?}");
+                    }
 
                     // display progress on the screen
-                    let percentage =
-                        (100.0 * (i + 1) as f64 / starred_images.len() as f64).ceil() as i32;
-                    progress_percentage.store(percentage, Ordering::Relaxed);
+                    progress.complete_one();
                     let locked = user_event_sender.lock().unwrap();
                     locked.send_event(()).unwrap();
                 }
@@ -401,220 +1180,1983 @@ fn resize_and_export(
     }
 }
 
-fn export(path: &str, image_file_names: &[ImageNamePair]) -> Result<(), Error> {
-    let starred_images: Vec<&ImageNamePair> =
-        image_file_names.iter().filter(|x| x.is_starred).collect();
-    disk::export(path, &starred_images)?;
-    Ok(())
+/// Let the user choose a photo in another folder and restart the viewer there
+fn open_another_folder(path: &str) {
+    let image_extensions: Vec<String> = disk::IMAGE_EXTENSIONS
+        .iter()
+        .chain(disk::RAW_EXTENSIONS)
+        .flat_map(|x| [x.to_string(), x.to_uppercase()])
+        .collect();
+    let file = rfd::FileDialog::new()
+        .set_directory(path)
+        .add_filter("Images", &image_extensions)
+        .pick_file();
+
+    if let Some(file) = file {
+        info!("Opening {file:?}");
+        match env::current_exe().and_then(|exe| Command::new(exe).arg(file).spawn()) {
+            Ok(_) => std::process::exit(0),
+            Err(e) => error!("Unable to open another folder: {e:?}"),
+        }
+    }
 }
 
-struct PhotoWindowHandler {
-    image: Option<ImageHandle>,
-    images: Images,
-    screen_resolution: UVec2,
-    connection: Arc<Mutex<Connection>>,
-    path: String,
-    state: RenderState,
-    font: Font,
-    progress_percentage: Arc<AtomicI32>,
-    resolution_tx: Sender<UVec2>,
-    show_only_starred: bool,
-    show_file_name: bool,
-    resize_request_tx: Sender<f32>,
+/// The font chosen in the settings, or the built in one when none is chosen or it cannot be loaded, followed
+/// by the fallback fonts that can be loaded
+fn load_font(config: &Config) -> Result<FontSet, Error> {
+    let primary = config.font.as_ref().and_then(|file_name| {
+        let font = std::fs::read(file_name)
+            .map_err(Error::from)
+            .and_then(FontSet::new);
+        match font {
+            Ok(font) => Some(font),
+            Err(e) => {
+                warn!("Using the built in font because {file_name:?} cannot be used: {e}");
+                None
+            }
+        }
+    });
+    let mut font = match primary {
+        Some(font) => font,
+        None => FontSet::new(include_bytes!("../fonts/NotoSans-Regular.ttf").to_vec())?,
+    };
+
+    for file_name in &config.fallback_fonts {
+        let added = std::fs::read(file_name)
+            .map_err(Error::from)
+            .and_then(|bytes| font.add_fallback(bytes));
+        if let Err(e) = added {
+            warn!("Unable to use fallback font {file_name:?}: {e}");
+        }
+    }
+    Ok(font)
 }
 
-impl WindowHandler for PhotoWindowHandler {
-    fn on_user_event(&mut self, helper: &mut WindowHelper<()>, _user_event: ()) {
-        helper.request_redraw()
+/// The ISOs, apertures and focal lengths used most, each with how many photos were taken with them.
+/// The exif of a photo is only read the first time, after that its settings come from the database.
+/// This runs on a background thread as reading the exif of a large folder takes a while
+fn shoot_stats(
+    path: &str,
+    names: &[String],
+    connection: Arc<Mutex<Connection>>,
+) -> Result<Vec<(String, Vec<(String, usize)>)>, Error> {
+    let mut cached = db::get_shot_settings(connection.clone())?;
+    let mut settings = Vec::with_capacity(names.len());
+    for name in names {
+        let known = match cached.remove(name) {
+            Some(known) => known,
+            None => {
+                // a photo without exif is remembered as having no settings
+                let read = metadata::get_shot_settings(path, name).unwrap_or_else(|e| {
+                    info!("No camera settings for {name}: {e:?}");
+                    ShotSettings::default()
+                });
+                db::update_shot_settings(name, read, connection.clone())?;
+                read
+            }
+        };
+        settings.push(known);
     }
 
-    fn on_resize(&mut self, helper: &mut WindowHelper<()>, size_pixels: UVec2) {
-        log::info!("Screen resolution changed to: {size_pixels:?}");
-        self.screen_resolution = size_pixels;
-        self.resolution_tx.send(size_pixels).unwrap();
-        self.image = None;
-        helper.request_redraw();
+    Ok(vec![
+        (
+            "ISO".to_owned(),
+            most_used(settings.iter().filter_map(|x| x.iso).map(|x| x.to_string())),
+        ),
+        (
+            "Aperture".to_owned(),
+            most_used(
+                settings
+                    .iter()
+                    .filter_map(|x| x.f_number)
+                    .map(|x| format!("f/{}", (x * 10.0).round() / 10.0)),
+            ),
+        ),
+        (
+            "Focal length".to_owned(),
+            most_used(
+                settings
+                    .iter()
+                    .filter_map(|x| x.focal_length)
+                    .map(|x| format!("{} mm", x.round())),
+            ),
+        ),
+    ])
+}
+
+/// the values that occur most often and how many times, the most common first
+fn most_used(values: impl Iterator<Item = String>) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
     }
 
-    fn on_draw(&mut self, helper: &mut WindowHelper, graphics: &mut Graphics2D) {
-        graphics.clear_screen(Color::BLACK);
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(SHOOT_STATS_BARS);
+    counts
+}
 
-        if resolution_ok(self.screen_resolution) {
-            let image_file = self.images.current();
-            let name = image_file.jpg_file_name.as_str();
+/// the photos that are exported, written to playlists and listed in the manifest
+fn starred(image_file_names: &[ImageNamePair]) -> Vec<&ImageNamePair> {
+    image_file_names.iter().filter(|x| x.is_starred).collect()
+}
 
-            if self.image.is_none() {
-                match self.state {
-                    RenderState::Full => {
-                        helper.set_cursor_visible(false);
+/// the folder photos are exported to
+fn export_folder(path: &str, config: &ExportConfig) -> PathBuf {
+    match &config.destination {
+        Some(destination) => destination.clone(),
+        None => disk::default_export_folder(path),
+    }
+}
 
-                        match db::try_get_image_from_db(
-                            name,
-                            self.screen_resolution,
-                            self.connection.clone(),
-                        )
-                        .unwrap()
-                        {
-                            Some(db_image) => {
-                                let image =
-                                    draw::image(self.screen_resolution, &db_image, graphics);
-                                self.image = Some(image);
-                            }
+/// Copy the starred images to the export folder, this runs on a background thread and wakes the window
+/// after each photo so that the progress is drawn
+fn export(
+    path: &str,
+    dest: &Path,
+    starred_images: &[ImageNamePair],
+    config: &ExportConfig,
+    exif_orientation: bool,
+    progress: &Progress,
+    user_event_sender: &Mutex<UserEventSender<()>>,
+) -> Result<(), Error> {
+    // the folder is made even when nothing is starred
+    std::fs::create_dir_all(dest)?;
+    progress.start(starred_images.len());
+
+    for image in starred_images {
+        // photos exported before are not touched
+        for (image, folder) in disk::export(path, dest, &[image], config.layout)? {
+            reencode_exported_image(path, &folder, image, config, exif_orientation)?;
+        }
+
+        // display progress on the screen
+        progress.complete_one();
+        let locked = user_event_sender.lock().unwrap();
+        locked.send_event(()).unwrap();
+    }
+
+    Ok(())
+}
+
+/// write a photo to `folder` made smaller by `resize_factor`
+fn export_resized(
+    source_path: &str,
+    name: &str,
+    folder: &Path,
+    rotation: u8,
+    exif_orientation: bool,
+    resize_factor: f32,
+    config: &ExportConfig,
+) -> Result<(), Error> {
+    let (img, metadata) = load_image(source_path, name, rotation, exif_orientation)?;
+    let (x, y) = img.dimensions();
+    let size = UVec2 {
+        x: (x as f32 * resize_factor) as u32,
+        y: (y as f32 * resize_factor) as u32,
+    };
+    let resized = resize_image(&img, size);
+    let resized = match (&config.caption, &metadata) {
+        (Some(caption), Some(metadata)) => caption::burn(resized, metadata, caption),
+        _ => resized,
+    };
+    disk::export_image(folder, name, &encode_jpg(&resized, config.quality)?)?;
+    Ok(())
+}
+
+/// replace a copied jpeg with a captioned, rotated or smaller one, the other files are left as they are
+fn reencode_exported_image(
+    path: &str,
+    dest: &Path,
+    image: &ImageNamePair,
+    config: &ExportConfig,
+    exif_orientation: bool,
+) -> Result<(), Error> {
+    let bake_rotation = config.bake_rotation && image.rotation != 0;
+    if config.caption.is_none() && !bake_rotation && config.max_dimension.is_none() {
+        return Ok(());
+    }
+
+    let (source_path, name) = match &image.original {
+        Some(original) => (original.path.as_str(), &original.jpg_file_name),
+        None => (path, &image.jpg_file_name),
+    };
+    let (img, metadata) = load_image(source_path, name, image.rotation, exif_orientation)?;
+
+    // photos are made smaller so that the longer edge fits but never larger
+    let (img, resized) = match config.max_dimension {
+        Some(max) if img.width().max(img.height()) > max => {
+            (resize_image(&img, UVec2 { x: max, y: max }), true)
+        }
+        _ => (img, false),
+    };
+    // the copy keeps its quality and metadata unless something about the photo has changed
+    let img = match (&config.caption, &metadata) {
+        (Some(caption), Some(metadata)) => caption::burn(img, metadata, caption),
+        _ if bake_rotation || resized => img,
+        _ => return Ok(()),
+    };
+    disk::replace_exported_image(dest, name, &encode_jpg(&img, config.quality)?)?;
+
+    Ok(())
+}
+
+/// keys that star, rate, protect or flag photos which all write to the folder being viewed. E and
+/// SHIFT + digits only write to it when the export folder is inside it
+fn writes_to_folder(
+    virtual_key_code: Option<VirtualKeyCode>,
+    shift: bool,
+    exports_in_place: bool,
+) -> bool {
+    let exports = matches!(virtual_key_code, Some(VirtualKeyCode::E))
+        || (shift && is_digit(virtual_key_code));
+    if exports {
+        return exports_in_place;
+    }
+
+    matches!(
+        virtual_key_code,
+        Some(
+            VirtualKeyCode::Space
+                | VirtualKeyCode::L
+                | VirtualKeyCode::K
+                | VirtualKeyCode::W
+                | VirtualKeyCode::R
+                | VirtualKeyCode::Delete
+        )
+    ) || is_digit(virtual_key_code)
+}
+
+/// keys that move a photo along with its other files, export, write a playlist or manifest, star every photo
+/// shown or compare neighbouring photos, which wait until the whole folder has been listed
+fn needs_whole_folder(virtual_key_code: Option<VirtualKeyCode>, shift: bool) -> bool {
+    matches!(
+        virtual_key_code,
+        Some(VirtualKeyCode::Delete | VirtualKeyCode::E | VirtualKeyCode::W | VirtualKeyCode::F8)
+    ) || (shift && (is_digit(virtual_key_code) || virtual_key_code == Some(VirtualKeyCode::Space)))
+}
+
+fn is_digit(virtual_key_code: Option<VirtualKeyCode>) -> bool {
+    matches!(
+        virtual_key_code,
+        Some(
+            VirtualKeyCode::Key1
+                | VirtualKeyCode::Key2
+                | VirtualKeyCode::Key3
+                | VirtualKeyCode::Key4
+                | VirtualKeyCode::Key5
+                | VirtualKeyCode::Key6
+                | VirtualKeyCode::Key7
+                | VirtualKeyCode::Key8
+                | VirtualKeyCode::Key9
+                | VirtualKeyCode::Key0
+        )
+    )
+}
+
+struct PhotoWindowHandler {
+    image: Option<ImageHandle>,
+    images: Images,
+    screen_resolution: UVec2,
+    /// physical pixels per logical pixel of the screen the window is on
+    scale_factor: f64,
+    connection: Arc<Mutex<Connection>>,
+    path: String,
+    state: RenderState,
+    font: FontSet,
+    progress: Arc<Progress>,
+    cache_tx: Sender<CacheMessage>,
+    /// images the cache thread could not resize and why, by name
+    cache_failures: Arc<Mutex<HashMap<String, String>>>,
+    /// names of photos added to the folder since it was opened
+    new_photos_rx: Receiver<Vec<String>>,
+    /// photos are turned by their exif orientation, shared with the cache and decode threads
+    exif_orientation: Arc<AtomicBool>,
+    /// honored when moving by group with LEFT and RIGHT
+    filter: ImageFilter,
+    show_file_name: bool,
+    show_filmstrip: bool,
+    show_calibration: bool,
+    show_ruler: bool,
+    show_focus_points: bool,
+    /// autofocus points of the named photo and its exif orientation, read from its exif the first time they are drawn
+    focus_points: Option<(String, Vec<FocusPoint>, Option<u32>)>,
+    /// size and physical size of a pixel of the named photo, none if its size could not be read
+    ruler: Option<(String, Option<RulerSource>)>,
+    confirm: Option<ConfirmState>,
+    /// resize requests and file list changes for the export thread
+    export_tx: Sender<ExportMessage>,
+    config: Config,
+    modifiers: ModifiersState,
+    /// index of the first field shown in the exif dump
+    exif_scroll: usize,
+    /// pixels the metadata has been scrolled when it does not fit on the screen
+    metadata_scroll: f32,
+    /// how far the metadata could be scrolled when it was last drawn
+    metadata_max_scroll: f32,
+    /// decode the current image from its file rather than using the cache
+    fresh_decode: bool,
+    /// only images taken on these dates are navigated to
+    date_filter: Option<DateFilter>,
+    /// what has been typed so far while the user is entering a date filter
+    date_input: Option<String>,
+    textures: TextureCache,
+    /// the folder cannot be written to so starring and exporting are disabled
+    read_only: bool,
+    /// magnification while zooming, 1.0 shows the photo pixel for pixel
+    zoom: f32,
+    /// zooming was started with + or - so it carries on after LEFT CTRL is released, until ESC
+    zoom_latched: bool,
+    /// the full size image being zoomed into, its name and a small copy of it for the navigator
+    zoom_source: Option<(String, Arc<Decoded>, ImageHandle)>,
+    /// full size images for zooming and fresh decodes, decoded in the background
+    decoded: DecodeCache,
+    /// the point of the image at the middle of the screen after panning with the arrow keys, from 0.0 to 1.0
+    /// across the image. None while zooming follows the mouse
+    zoom_pan: Option<Vec2>,
+    mouse_position: Vec2,
+    /// the image shown before navigating, displayed while the next one is loading
+    previous_image: Option<ImageHandle>,
+    /// when the current image was first found to be missing from the cache
+    loading_since: Option<Instant>,
+    /// when the screen resolution last changed, the cache thread waits for it to settle
+    resized_at: Option<Instant>,
+    /// whether the images next to the current image have been uploaded to the texture cache
+    preload: Preload,
+    /// index of the image selected in the grid
+    grid_index: usize,
+    /// names of the images marked for comparison, they are shown side by side once there are two
+    compare_marks: Vec<String>,
+    /// the most recent deletes that can be undone, the last one deleted at the end
+    deleted: Vec<DeletedImage>,
+    /// textures for the grid and filmstrip, only made for thumbnails that have been on screen
+    thumbnails: TextureCache,
+    user_event_sender: Arc<Mutex<UserEventSender<()>>>,
+    /// the timer for the slideshow state
+    slideshow: Option<Slideshow>,
+    /// files copied so far by an export, kept apart from the progress of the cache
+    export_progress: Arc<Progress>,
+    /// the result of the export running in the background
+    exporting: Option<Receiver<Result<(), Error>>>,
+    /// the whole folder being listed in the background, only the photo it was opened at is shown until then
+    listing: Option<Receiver<Result<Vec<ImageNamePair>, Error>>>,
+    /// photos compared so far by F8, kept apart from the progress of the cache
+    similar_progress: Arc<Progress>,
+    /// photos resized so far by SHIFT + digits, kept apart from the progress of the cache
+    resize_progress: Arc<Progress>,
+    /// the groups of similar photos being found in the background
+    finding_similar: Option<Receiver<Result<Vec<(String, Option<i64>)>, Error>>>,
+    /// the images marked for comparison take turns on the whole screen rather than being side by side
+    flicker: Option<Flicker>,
+    /// the charts shown by `RenderState::ShootStats`, counted when it is opened
+    shoot_stats: Vec<(String, Vec<(String, usize)>)>,
+    /// the charts being counted in the background
+    counting_shoot_stats: Option<Receiver<Result<Vec<(String, Vec<(String, usize)>)>, Error>>>,
+    /// turns the hourglass while it is on the screen
+    spinner: Spinner,
+    /// opened the first time something is copied and kept, on some platforms the copied text goes with it
+    clipboard: Option<arboard::Clipboard>,
+    /// a short message drawn over the photo and when it was first shown
+    notice: Option<(String, Instant)>,
+}
+
+impl PhotoWindowHandler {
+    fn image_changed(&mut self, helper: &mut WindowHelper) {
+        // let the cache thread know so that it works on images near this one first
+        if !self.images.is_empty() {
+            let name = self.images.current().jpg_file_name.clone();
+            if let Err(e) = db::set_last_viewed(&name, self.connection.clone()) {
+                error!("Unable to remember the last viewed image: {e:?}");
+            }
+            self.cache_tx.send(CacheMessage::Current(name)).unwrap();
+        }
+        // keep showing the old image until the new one is ready
+        self.previous_image = self.image.take().or(self.previous_image.take());
+        self.loading_since = None;
+        // a full resolution photo takes a lot of memory so only the one being zoomed into is kept
+        self.zoom_source = None;
+        self.zoom_pan = None;
+        self.preload = Preload::Waiting;
+        helper.request_redraw();
+    }
+
+    /// read the autofocus points of the current photo unless they were read for it already
+    fn read_focus_points(&mut self) {
+        if self.images.is_empty() {
+            return;
+        }
+
+        let name = &self.images.current().jpg_file_name;
+        if self.focus_points.as_ref().map(|(x, _, _)| x) == Some(name) {
+            return;
+        }
+
+        // the camera did not record focus points or records them in a format that is not supported
+        let (points, orientation) =
+            metadata::get_focus_points(&self.path, name).unwrap_or_default();
+        self.focus_points = Some((name.clone(), points, orientation));
+    }
+
+    /// read the width and pixel size of the current photo unless they were read for it already
+    fn read_ruler(&mut self) {
+        if self.images.is_empty() {
+            return;
+        }
+
+        let name = &self.images.current().jpg_file_name;
+        if self.ruler.as_ref().map(|(x, _)| x) == Some(name) {
+            return;
+        }
+
+        let metadata = metadata::get_metadata(&self.path, name).ok();
+        let source =
+            metadata::get_dimensions(&self.path, name, None)
+                .ok()
+                .map(|(width, height)| RulerSource {
+                    width,
+                    height,
+                    orientation: metadata.as_ref().and_then(|x| x.orientation),
+                    pixels_per_mm: metadata.as_ref().and_then(|x| x.pixels_per_mm()),
+                });
+        self.ruler = Some((name.clone(), source));
+    }
+
+    /// the exif orientation a photo is turned by when it is displayed, none when the user has turned that off
+    /// and for heif images which are already turned by the decoder
+    fn displayed_orientation(&self, name: &str, orientation: Option<u32>) -> Option<u32> {
+        orientation
+            .filter(|_| self.exif_orientation.load(Ordering::Relaxed) && !disk::is_heif(name))
+    }
+
+    /// the size photos are cached at for the screen, the physical resolution unless the logical one was chosen
+    fn cache_resolution(&self) -> UVec2 {
+        if !self.config.cache_logical_resolution || self.scale_factor <= 1.0 {
+            return self.screen_resolution;
+        }
+
+        UVec2::new(
+            ((self.screen_resolution.x as f64 / self.scale_factor).round() as u32).max(1),
+            ((self.screen_resolution.y as f64 / self.scale_factor).round() as u32).max(1),
+        )
+    }
+
+    /// how much photos from the cache are enlarged to fill the screen, 1.0 when they are cached at its resolution
+    fn upscale(&self) -> f32 {
+        let cached = self.cache_resolution();
+        let x = self.screen_resolution.x as f32 / cached.x as f32;
+        let y = self.screen_resolution.y as f32 / cached.y as f32;
+        x.min(y)
+    }
+
+    /// the screen or the cache resolution has changed, photos are resized for it by the cache thread
+    fn resolution_changed(&mut self, helper: &mut WindowHelper) {
+        self.cache_tx
+            .send(CacheMessage::Resolution(self.cache_resolution()))
+            .unwrap();
+        // textures for the old resolution will never be used again
+        self.textures.clear();
+        // keep showing the old image while the resolution settles rather than blanking the screen
+        self.previous_image = self.image.take().or(self.previous_image.take());
+        self.resized_at = Some(Instant::now());
+        self.loading_since = None;
+        helper.request_redraw();
+    }
+
+    /// the point of the image under the mouse from 0.0 to 1.0 across the image, as it is displayed to fit the screen
+    fn zoom_center(&self, (width, height): (u32, u32)) -> Vec2 {
+        let screen = self.screen_resolution;
+        let scale = (screen.x as f32 / width as f32).min(screen.y as f32 / height as f32);
+        let fit_width = width as f32 * scale;
+        let fit_height = height as f32 * scale;
+        let x = (self.mouse_position.x - (screen.x as f32 - fit_width) / 2.0) / fit_width;
+        let y = (self.mouse_position.y - (screen.y as f32 - fit_height) / 2.0) / fit_height;
+        Vec2::new(x.clamp(0.0, 1.0), y.clamp(0.0, 1.0))
+    }
+
+    /// rows of the stats screen, the counts are for the whole folder whatever the filter
+    fn stats(&self) -> Result<Vec<(String, String)>, Error> {
+        let images = self.images.all();
+        let starred = images.iter().filter(|x| x.is_starred).count();
+        let cached = db::get_cached_image_names(self.cache_resolution(), self.connection.clone())?;
+        let uncached = images
+            .iter()
+            .filter(|x| !cached.contains(&x.jpg_file_name))
+            .count();
+
+        let mut stats = vec![
+            ("Photos".to_owned(), images.len().to_string()),
+            ("Starred".to_owned(), starred.to_string()),
+        ];
+        for rating in (0..=5).rev() {
+            let count = images.iter().filter(|x| x.rating == rating).count();
+            let label = match rating {
+                0 => "Unrated".to_owned(),
+                1 => "1 star".to_owned(),
+                _ => format!("{rating} stars"),
+            };
+            stats.push((label, count.to_string()));
+        }
+        stats.push(("Still to cache".to_owned(), uncached.to_string()));
+
+        Ok(stats)
+    }
+
+    /// count the camera settings used across the folder in the background, the charts are shown once
+    /// they have been counted
+    fn start_counting_shoot_stats(&mut self) {
+        if self.counting_shoot_stats.is_some() {
+            return;
+        }
+
+        let names: Vec<String> = self
+            .images
+            .all()
+            .iter()
+            .map(|x| x.jpg_file_name.clone())
+            .collect();
+        let path = self.path.clone();
+        let connection = self.connection.clone();
+        let user_event_sender = self.user_event_sender.clone();
+
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let result = shoot_stats(&path, &names, connection);
+            let _ = tx.send(result);
+            let locked = user_event_sender.lock().unwrap();
+            locked.send_event(()).unwrap();
+        });
+        self.shoot_stats.clear();
+        self.counting_shoot_stats = Some(rx);
+    }
+
+    /// returns true if the key scrolled the exif dump rather than navigating
+    fn scroll_exif_dump(&mut self, virtual_key_code: Option<VirtualKeyCode>) -> bool {
+        // scrolling past the end is clamped when the fields are drawn
+        let page_size = draw::exif_dump_page_size(self.screen_resolution);
+        match virtual_key_code {
+            Some(VirtualKeyCode::Up) => self.exif_scroll = self.exif_scroll.saturating_sub(1),
+            Some(VirtualKeyCode::Down) => self.exif_scroll += 1,
+            Some(VirtualKeyCode::PageUp) => {
+                self.exif_scroll = self.exif_scroll.saturating_sub(page_size)
+            }
+            Some(VirtualKeyCode::PageDown) => self.exif_scroll += page_size,
+            _ => return false,
+        }
+        true
+    }
+
+    /// returns true if the key scrolled the metadata, only when it is too tall for the screen
+    fn scroll_metadata(&mut self, virtual_key_code: Option<VirtualKeyCode>) -> bool {
+        if self.metadata_max_scroll <= 0.0 {
+            return false;
+        }
+
+        let scroll = match virtual_key_code {
+            Some(VirtualKeyCode::Up) => self.metadata_scroll - METADATA_SCROLL_STEP,
+            Some(VirtualKeyCode::Down) => self.metadata_scroll + METADATA_SCROLL_STEP,
+            _ => return false,
+        };
+        self.metadata_scroll = scroll.clamp(0.0, self.metadata_max_scroll);
+        true
+    }
+
+    /// which images are navigated to, `group` is true when moving by group which also honors the image filter
+    fn navigation_filter(&self, group: bool) -> impl Fn(&ImageNamePair) -> bool {
+        let filter = if group { self.filter } else { ImageFilter::All };
+        let date_filter = self.date_filter;
+        move |image| filter.matches(image) && date_filter.is_none_or(|date| date.matches(image))
+    }
+
+    /// Go to the next photo that has not been rated yet, or the previous one when going `backwards`.
+    /// A star or a flag also counts as a rating when the settings say so
+    fn next_unrated(&mut self, helper: &mut WindowHelper, backwards: bool) {
+        let starred_is_rated = self.config.navigation.starred_is_rated;
+        let flagged_is_rated = self.config.navigation.flagged_is_rated;
+        let is_rated = move |image: &ImageNamePair| {
+            (starred_is_rated && image.is_starred) || (flagged_is_rated && image.is_flagged)
+        };
+
+        let found = if backwards {
+            self.images.prev_unrated(is_rated)
+        } else {
+            self.images.next_unrated(is_rated)
+        };
+
+        if found {
+            self.image_changed(helper);
+        } else {
+            self.notice = Some((
+                "Culling complete, every photo has been rated".to_owned(),
+                Instant::now(),
+            ));
+            helper.request_redraw();
+        }
+    }
+
+    fn is_filtered(&self) -> bool {
+        self.filter != ImageFilter::All || self.date_filter.is_some()
+    }
+
+    fn set_filter(&mut self, helper: &mut WindowHelper, filter: ImageFilter) {
+        self.filter = filter;
+        if !filter.matches(self.images.current()) {
+            self.images.next_matching(|x| filter.matches(x));
+            self.image_changed(helper);
+        } else {
+            helper.request_redraw();
+        }
+    }
+
+    fn set_date_filter(&mut self, helper: &mut WindowHelper, date_filter: Option<DateFilter>) {
+        self.date_filter = date_filter;
+        if let Some(date_filter) = date_filter {
+            if !date_filter.matches(self.images.current()) {
+                self.images.next_matching(|x| date_filter.matches(x));
+            }
+        }
+        self.image_changed(helper);
+    }
+
+    fn set_rating(&mut self, helper: &mut WindowHelper, rating: u8) -> Result<(), Error> {
+        let image = self.images.current_mut();
+        image.rating = rating;
+        db::update_image_rating(&image.jpg_file_name, rating, self.connection.clone())?;
+        helper.request_redraw();
+        Ok(())
+    }
+
+    /// pressing the key of the label the image already has removes it
+    fn toggle_color_label(
+        &mut self,
+        helper: &mut WindowHelper,
+        color_label: u8,
+    ) -> Result<(), Error> {
+        let image = self.images.current_mut();
+        image.color_label = if image.color_label == color_label {
+            0
+        } else {
+            color_label
+        };
+        db::update_image_color_label(
+            &image.jpg_file_name,
+            image.color_label,
+            self.connection.clone(),
+        )?;
+        helper.request_redraw();
+        Ok(())
+    }
+
+    /// turn the current image by `quarter_turns` clockwise, it is resized again the next time it is shown
+    fn rotate(&mut self, helper: &mut WindowHelper, quarter_turns: u8) -> Result<(), Error> {
+        let image = self.images.current_mut();
+        image.rotation = (image.rotation + quarter_turns) % 4;
+        let name = image.jpg_file_name.clone();
+        db::update_image_rotation(&name, image.rotation, self.connection.clone())?;
+        db::remove_resized_images(&name, self.connection.clone())?;
+
+        // resized again straight away, with its thumbnail, even when only the grid is showing
+        self.cache_tx
+            .send(CacheMessage::Load(name.clone()))
+            .unwrap();
+        self.textures.remove(&name);
+        self.thumbnails.remove(&name);
+        self.image_changed(helper);
+        Ok(())
+    }
+
+    /// move the current image to the trash folder and show the next one
+    fn delete(&mut self, helper: &mut WindowHelper) -> Result<(), Error> {
+        let image = self.images.current();
+        if image.is_protected {
+            warn!("{} is protected from deletion", image.jpg_file_name);
+            self.notice = Some((
+                "Protected photos are not deleted".to_owned(),
+                Instant::now(),
+            ));
+            helper.request_redraw();
+            return Ok(());
+        }
+
+        disk::move_to_trash(&self.path, image)?;
+
+        let index = self.images.current_index();
+        let removed = self.images.remove_current();
+        let name = removed.jpg_file_name.clone();
+        db::delete_photo(&name, self.connection.clone())?;
+        self.textures.remove(&name);
+        self.thumbnails.remove(&name);
+
+        if self.deleted.len() == UNDO_LIMIT {
+            self.deleted.remove(0);
+        }
+        self.deleted.push(DeletedImage {
+            index,
+            image: removed,
+        });
+        self.send_file_names();
+
+        // the deleted image is not shown while the next one loads, and the empty
+        // folder screen is shown once the last image has gone
+        self.image_changed(helper);
+        self.previous_image = None;
+        Ok(())
+    }
+
+    /// move the most recently deleted image back from the trash folder and show it
+    fn undo_delete(&mut self, helper: &mut WindowHelper) -> Result<(), Error> {
+        let Some(deleted) = self.deleted.pop() else {
+            self.notice = Some(("Nothing to undo".to_owned(), Instant::now()));
+            helper.request_redraw();
+            return Ok(());
+        };
+        if let Err(e) = disk::restore_from_trash(&self.path, &deleted.image) {
+            // leave it on the stack so that the user can try again once the problem is fixed
+            self.deleted.push(deleted);
+            return Err(e);
+        }
+
+        info!("Restored {} from the trash", deleted.image.jpg_file_name);
+        db::restore_photo(&deleted.image, self.connection.clone())?;
+        self.images.insert(deleted.index, deleted.image);
+        self.send_file_names();
+        self.image_changed(helper);
+        Ok(())
+    }
+
+    /// Turn photos by their exif orientation or show them as they are stored, for cameras that write the
+    /// wrong orientation. Every resized image is made again and the choice is remembered for this folder
+    fn toggle_exif_orientation(&mut self, helper: &mut WindowHelper) -> Result<(), Error> {
+        let exif_orientation = !self.exif_orientation.load(Ordering::Relaxed);
+        info!(
+            "Exif orientation is now {}",
+            if exif_orientation { "on" } else { "off" }
+        );
+        self.exif_orientation
+            .store(exif_orientation, Ordering::Relaxed);
+        db::set_exif_orientation(exif_orientation, self.connection.clone())?;
+        db::remove_all_resized_images(self.connection.clone())?;
+
+        self.send_file_names();
+        self.textures.clear();
+        self.thumbnails.clear();
+        self.image_changed(helper);
+        Ok(())
+    }
+
+    /// tell the cache and export threads that images have been added or removed, and forget the comparison
+    /// marks of images that have gone
+    fn send_file_names(&mut self) {
+        let names: Vec<String> = self
+            .images
+            .all()
+            .iter()
+            .map(|x| x.jpg_file_name.clone())
+            .collect();
+        self.compare_marks.retain(|x| names.contains(x));
+        if self.state == RenderState::Compare && self.compare_marks.len() < 2 {
+            self.stop_comparing();
+        }
+
+        let originals = self
+            .images
+            .all()
+            .iter()
+            .filter_map(|x| Some((x.jpg_file_name.clone(), x.original.clone()?)))
+            .collect();
+        self.export_tx
+            .send(ExportMessage::Files(names.clone(), originals))
+            .unwrap();
+        self.cache_tx.send(CacheMessage::Files(names)).unwrap();
+    }
+
+    fn refresh(&mut self, helper: &mut WindowHelper) -> Result<(), Error> {
+        // scan the folder again, this also reloads the .photofarmignore file. Everything is looked up again
+        // as files may have been edited
+        let image_file_names =
+            build_file_list(&self.path, &self.config, &[], self.connection.clone())?;
+        if image_file_names.is_empty() {
+            info!("No images found on refresh");
+        }
+
+        self.images.replace(image_file_names);
+        self.send_file_names();
+        // files may have been edited since they were last displayed
+        self.textures.clear();
+        self.thumbnails.clear();
+        self.focus_points = None;
+        self.ruler = None;
+        self.image_changed(helper);
+        Ok(())
+    }
+
+    /// Group neighbouring photos that look the same on a background thread, the groups are used once it
+    /// has finished
+    fn start_finding_similar_photos(&mut self, helper: &mut WindowHelper) {
+        if self.finding_similar.is_some() {
+            info!("Already looking for similar photos");
+            return;
+        }
+
+        let names: Vec<String> = self
+            .images
+            .all()
+            .iter()
+            .map(|x| x.jpg_file_name.clone())
+            .collect();
+        let connection = self.connection.clone();
+        let progress = self.similar_progress.clone();
+        let user_event_sender = self.user_event_sender.clone();
+
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let result = find_similar_photos(names, connection, &progress, &user_event_sender);
+            let _ = tx.send(result);
+            let locked = user_event_sender.lock().unwrap();
+            locked.send_event(()).unwrap();
+        });
+        self.finding_similar = Some(rx);
+        helper.request_redraw();
+    }
+
+    /// show the whole folder once it has been listed, staying on the current photo
+    fn listing_finished(
+        &mut self,
+        helper: &mut WindowHelper,
+        image_file_names: Vec<ImageNamePair>,
+    ) -> Result<(), Error> {
+        info!("Listed {} images", image_file_names.len());
+
+        // the photos shown while listing may have been starred or rated since they were looked up by
+        // the listing, so they are looked up again along with the files that have been matched to them
+        let shown: HashSet<String> = self
+            .images
+            .all()
+            .iter()
+            .map(|x| x.jpg_file_name.clone())
+            .collect();
+        let known: Vec<ImageNamePair> = image_file_names
+            .iter()
+            .filter(|x| !shown.contains(&x.jpg_file_name))
+            .cloned()
+            .collect();
+        let image_file_names = image_file_names
+            .into_iter()
+            .map(|x| match shown.contains(&x.jpg_file_name) {
+                true => ImageNamePair {
+                    original: x.original,
+                    ..disk::new_image_name_pair(x.jpg_file_name, x.other_file_names)
+                },
+                false => x,
+            })
+            .collect();
+        let image_file_names = look_up(
+            &self.path,
+            &self.config,
+            image_file_names,
+            &known,
+            self.connection.clone(),
+        )?;
+
+        self.images.replace(image_file_names);
+        self.send_file_names();
+        self.image_changed(helper);
+        Ok(())
+    }
+
+    /// show photos that have been added to the folder, staying on the current photo. Only the new photos are
+    /// looked up, the others keep what is already known about them
+    fn add_new_photos(&mut self, helper: &mut WindowHelper) -> Result<(), Error> {
+        let was_empty = self.images.is_empty();
+        let image_file_names = build_file_list(
+            &self.path,
+            &self.config,
+            self.images.all(),
+            self.connection.clone(),
+        )?;
+        self.images.replace(image_file_names);
+        self.send_file_names();
+
+        if was_empty {
+            self.image_changed(helper);
+        } else {
+            helper.request_redraw();
+        }
+        Ok(())
+    }
+
+    /// returns true if the key was used by the grid rather than the single photo view
+    fn grid_key(
+        &mut self,
+        helper: &mut WindowHelper,
+        virtual_key_code: Option<VirtualKeyCode>,
+    ) -> bool {
+        let (columns, _) = draw::grid_page_size(self.screen_resolution);
+        let last = self.images.all().len() - 1;
+        match virtual_key_code {
+            Some(VirtualKeyCode::Left) => self.grid_index = self.grid_index.saturating_sub(1),
+            Some(VirtualKeyCode::Right) => self.grid_index = (self.grid_index + 1).min(last),
+            Some(VirtualKeyCode::Up) => self.grid_index = self.grid_index.saturating_sub(columns),
+            Some(VirtualKeyCode::Down) => self.grid_index = (self.grid_index + columns).min(last),
+            Some(VirtualKeyCode::Return) => {
+                // view the selected image
+                let name = self.images.all()[self.grid_index].jpg_file_name.clone();
+                self.images.go_to(&name);
+                self.state = RenderState::Full;
+                self.image_changed(helper);
+            }
+            Some(VirtualKeyCode::G) | Some(VirtualKeyCode::Escape) => {
+                // back to the image that was being viewed
+                self.state = RenderState::Full;
+                self.image = None;
+            }
+            _ => return false,
+        }
+        helper.request_redraw();
+        true
+    }
+
+    /// the key that a key pressed by the user acts as, from the [keys] in photo-farm.toml
+    fn remap(&self, virtual_key_code: Option<VirtualKeyCode>) -> Option<VirtualKeyCode> {
+        virtual_key_code.map(|x| self.config.key_bindings.get(&x).copied().unwrap_or(x))
+    }
+
+    /// mark or unmark the current image, comparing once two images are marked
+    fn mark_for_compare(&mut self, helper: &mut WindowHelper) {
+        let name = self.images.current().jpg_file_name.clone();
+        match self.compare_marks.iter().position(|x| *x == name) {
+            Some(index) => {
+                self.compare_marks.remove(index);
+            }
+            None => self.compare_marks.push(name),
+        }
+
+        if self.compare_marks.len() == 2 {
+            self.state = RenderState::Compare;
+            self.image = None;
+        }
+        helper.request_redraw();
+    }
+
+    /// the arrow keys pan the zoomed photo instead of moving to another photo
+    fn zoom_key(
+        &mut self,
+        helper: &mut WindowHelper,
+        virtual_key_code: Option<VirtualKeyCode>,
+    ) -> bool {
+        let (dx, dy) = match virtual_key_code {
+            Some(VirtualKeyCode::Left) => (-PAN_STEP, 0.0),
+            Some(VirtualKeyCode::Right) => (PAN_STEP, 0.0),
+            Some(VirtualKeyCode::Up) => (0.0, -PAN_STEP),
+            Some(VirtualKeyCode::Down) => (0.0, PAN_STEP),
+            _ => return false,
+        };
+
+        // nothing to pan until the full size image has been decoded
+        let pan = self.zoom_source.as_ref().map(|(_, decoded, _)| {
+            let img = &decoded.img;
+            let center = self
+                .zoom_pan
+                .unwrap_or_else(|| self.zoom_center(img.dimensions()));
+            // start from where the crop stopped at the edge of the photo so that panning back moves straight away
+            let crop = crop_rect(img, center, self.zoom, self.screen_resolution);
+            let x = crop.top_left().x as f32 + crop.width() as f32 * (0.5 + dx);
+            let y = crop.top_left().y as f32 + crop.height() as f32 * (0.5 + dy);
+            Vec2::new(
+                (x / img.width() as f32).clamp(0.0, 1.0),
+                (y / img.height() as f32).clamp(0.0, 1.0),
+            )
+        });
+        if pan.is_some() {
+            self.zoom_pan = pan;
+        }
+        helper.request_redraw();
+        true
+    }
+
+    fn compare_key(
+        &mut self,
+        helper: &mut WindowHelper,
+        virtual_key_code: Option<VirtualKeyCode>,
+    ) -> bool {
+        match virtual_key_code {
+            Some(VirtualKeyCode::Left) | Some(VirtualKeyCode::Right) => {
+                self.compare_marks.swap(0, 1)
+            }
+            Some(VirtualKeyCode::F) => {
+                self.flicker = match self.flicker {
+                    Some(_) => None,
+                    None => Some(Flicker::start(
+                        flicker::DEFAULT_INTERVAL,
+                        self.user_event_sender.clone(),
+                    )),
+                }
+            }
+            Some(VirtualKeyCode::Up) | Some(VirtualKeyCode::Down) if self.flicker.is_some() => {
+                let flicker = self.flicker.as_mut().expect("flickering");
+                flicker.adjust(virtual_key_code == Some(VirtualKeyCode::Up));
+                info!("Flicker interval: {:?}", flicker.interval());
+            }
+            Some(VirtualKeyCode::X) | Some(VirtualKeyCode::Escape) => self.stop_comparing(),
+            // other keys would act on the current photo, which is hidden while comparing
+            _ => return true,
+        }
+        helper.request_redraw();
+        true
+    }
+
+    /// back to the image that was being viewed, marking starts again
+    fn stop_comparing(&mut self) {
+        self.compare_marks.clear();
+        self.flicker = None;
+        self.state = RenderState::Full;
+        self.image = None;
+    }
+
+    /// the screen sized texture of an image, none if the cache thread has not resized it yet
+    fn screen_texture(
+        &mut self,
+        name: &str,
+        graphics: &mut Graphics2D,
+    ) -> Result<Option<ImageHandle>, Error> {
+        let resolution = self.cache_resolution();
+        if let Some(texture) = self.textures.get(name, resolution) {
+            return Ok(Some(texture));
+        }
+
+        let resized = db::try_get_image_from_db(name, resolution, self.connection.clone())?;
+        Ok(resized.map(|resized| {
+            let texture = draw::screen_texture(&resized, self.upscale(), graphics);
+            self.textures.insert(name, resolution, texture.clone());
+            texture
+        }))
+    }
+
+    /// switch between file name and date taken order, staying on the current image
+    fn toggle_sort_order(&mut self, helper: &mut WindowHelper) {
+        let order = match self.config.sort_order {
+            SortOrder::FileName => SortOrder::DateTaken,
+            SortOrder::DateTaken => SortOrder::FileName,
+        };
+
+        // kept in the config so that the folder stays in this order when it is scanned again
+        self.config.sort_order = order;
+        self.images.sort(order);
+        self.send_file_names();
+        self.image_changed(helper);
+    }
+
+    fn stop_slideshow(&mut self) {
+        self.slideshow = None;
+        self.state = RenderState::Full;
+    }
+
+    /// returns true if the key was used by the slideshow. Other keys are handled as usual and stop the
+    /// slideshow if they navigate or leave it
+    fn slideshow_key(
+        &mut self,
+        helper: &mut WindowHelper,
+        virtual_key_code: Option<VirtualKeyCode>,
+    ) -> bool {
+        let Some(slideshow) = &mut self.slideshow else {
+            return false;
+        };
+
+        match virtual_key_code {
+            Some(VirtualKeyCode::Up) => slideshow.adjust(1),
+            Some(VirtualKeyCode::Down) => slideshow.adjust(-1),
+            Some(VirtualKeyCode::P) | Some(VirtualKeyCode::Escape) => {
+                self.stop_slideshow();
+                helper.request_redraw();
+                return true;
+            }
+            _ => return false,
+        }
+
+        info!("Slideshow interval: {:?}", slideshow.interval());
+        true
+    }
+
+    /// put the full path of the current photo on the clipboard, e.g. to paste it into a terminal
+    fn copy_path(&mut self, helper: &mut WindowHelper) -> Result<(), Error> {
+        let file_name = disk::get_full_path(&self.path, &self.images.current().jpg_file_name)?;
+        let clipboard = match &mut self.clipboard {
+            Some(clipboard) => clipboard,
+            None => self.clipboard.insert(arboard::Clipboard::new()?),
+        };
+        clipboard.set_text(file_name.clone())?;
+
+        info!("Copied {file_name} to the clipboard");
+        self.notice = Some((format!("Copied {file_name}"), Instant::now()));
+        helper.request_redraw();
+        Ok(())
+    }
+
+    fn show_error(&mut self, e: Error) {
+        error!("{e:?}");
+        self.state = RenderState::ErrorMessage {
+            message: e.to_string(),
+            broken_image: e.is_broken_image(),
+        };
+        self.image = None;
+    }
+
+    /// Export the starred photos on a background thread, the window goes back to the photo once it has finished
+    fn start_export(&mut self) {
+        let path = self.path.clone();
+        let dest = export_folder(&self.path, &self.config.export);
+        let starred_images: Vec<ImageNamePair> =
+            starred(self.images.all()).into_iter().cloned().collect();
+        let config = self.config.export.clone();
+        let exif_orientation = self.exif_orientation.load(Ordering::Relaxed);
+        let progress = self.export_progress.clone();
+        let user_event_sender = self.user_event_sender.clone();
+
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let result = export(
+                &path,
+                &dest,
+                &starred_images,
+                &config,
+                exif_orientation,
+                &progress,
+                &user_event_sender,
+            );
+            let _ = tx.send(result);
+            let locked = user_event_sender.lock().unwrap();
+            locked.send_event(()).unwrap();
+        });
+        self.exporting = Some(rx);
+    }
+
+    /// resume normal viewing
+    fn stop_zooming(&mut self, helper: &mut WindowHelper) {
+        self.state = RenderState::Full;
+        self.zoom = 1.0;
+        self.zoom_latched = false;
+        self.zoom_source = None;
+        self.zoom_pan = None;
+        self.image = None;
+        helper.request_redraw();
+    }
+
+    fn ask(&mut self, message: &str, action: ConfirmAction) {
+        self.confirm = Some(ConfirmState {
+            message: message.to_owned(),
+            action,
+        });
+    }
+
+    fn run_confirmed(
+        &mut self,
+        helper: &mut WindowHelper,
+        action: ConfirmAction,
+    ) -> Result<(), Error> {
+        match action {
+            ConfirmAction::Export => {
+                self.state = RenderState::ExportRequested;
+                self.image = None;
+            }
+            ConfirmAction::StarAllShown => {
+                let filter = self.navigation_filter(true);
+                for image in self.images.all_mut() {
+                    if !filter(image) {
+                        continue;
+                    }
+                    image.is_starred = true;
+                    db::update_image_is_starred(
+                        &image.jpg_file_name,
+                        true,
+                        self.connection.clone(),
+                    )?;
+                }
+            }
+            ConfirmAction::ClearAllStars => {
+                db::set_all_starred(false, self.connection.clone())?;
+                for image in self.images.all_mut() {
+                    image.is_starred = false;
+                }
+            }
+        }
+        helper.request_redraw();
+        Ok(())
+    }
+
+    fn jump_to_exposure_extreme(
+        &mut self,
+        helper: &mut WindowHelper,
+        brightest: bool,
+    ) -> Result<(), Error> {
+        // only analysed images have an exposure score
+        let exposures = db::get_exposures(self.connection.clone())?;
+        let scored = self
+            .images
+            .all()
+            .iter()
+            .filter_map(|x| exposures.get(&x.jpg_file_name).map(|e| (x, *e)));
+        let extreme = if brightest {
+            scored.max_by(|a, b| a.1.total_cmp(&b.1))
+        } else {
+            scored.min_by(|a, b| a.1.total_cmp(&b.1))
+        };
+
+        if let Some((image, _)) = extreme {
+            let name = image.jpg_file_name.clone();
+            self.images.go_to(&name);
+            self.image_changed(helper);
+        }
+        Ok(())
+    }
+
+    /// The thumbnails of these images, uploading the ones that are not on the GPU yet. Only these images are
+    /// read from the database. The cache thread sends an event as each thumbnail is done, so this looks again
+    /// while any are missing
+    fn load_thumbnails(
+        &mut self,
+        range: Range<usize>,
+        graphics: &mut Graphics2D,
+    ) -> Result<Vec<Option<ImageHandle>>, Error> {
+        let mut thumbnails = Vec::with_capacity(range.len());
+        for image_file in &self.images.all()[range] {
+            let name = &image_file.jpg_file_name;
+            if let Some(texture) = self.thumbnails.get(name, THUMBNAIL_SIZE) {
+                thumbnails.push(Some(texture));
+                continue;
+            }
+
+            let bytes = db::try_get_image_from_db(name, THUMBNAIL_SIZE, self.connection.clone())?;
+            let texture = bytes.map(|x| draw::texture(&x, graphics));
+            if let Some(texture) = &texture {
+                self.thumbnails
+                    .insert(name, THUMBNAIL_SIZE, texture.clone());
+            }
+            thumbnails.push(texture);
+        }
+
+        Ok(thumbnails)
+    }
+
+    /// draw the current image or whichever screen is showing, along with the overlays
+    fn draw_current(
+        &mut self,
+        helper: &mut WindowHelper,
+        graphics: &mut Graphics2D,
+    ) -> Result<(), Error> {
+        if self.show_focus_points && self.state == RenderState::Full {
+            self.read_focus_points();
+        }
+        if self.show_ruler && self.state == RenderState::Full {
+            self.read_ruler();
+        }
+
+        let image_file = self.images.current();
+        let name = image_file.jpg_file_name.as_str();
+
+        if self.image.is_none() {
+            match self.state {
+                RenderState::ErrorMessage {
+                    ref message,
+                    broken_image,
+                } => {
+                    helper.set_cursor_visible(true);
+                    draw::error(
+                        self.screen_resolution,
+                        graphics,
+                        &self.font,
+                        message,
+                        broken_image,
+                    )
+                }
+                RenderState::Full if self.fresh_decode => {
+                    // bypass the cache to show resize and compression artifacts by comparison
+                    helper.set_cursor_visible(false);
+                    match self.decoded.get(name, image_file.rotation)? {
+                        Some(decoded) => {
+                            let resized = resize_image(&decoded.img, self.screen_resolution);
+                            let image =
+                                draw::image_uncompressed(self.screen_resolution, resized, graphics);
+                            self.image = Some(image);
+                        }
+                        None => {
+                            draw::hourglass(self.screen_resolution, graphics, self.spinner.angle())
+                        }
+                    }
+                }
+                RenderState::Full | RenderState::Slideshow => {
+                    helper.set_cursor_visible(false);
+
+                    // going back to a recently viewed image reuses its texture
+                    let resolution = self.cache_resolution();
+                    if let Some(image) = self.textures.get(name, resolution) {
+                        draw::photo(self.screen_resolution, graphics, &image, self.upscale());
+                        self.image = Some(image);
+                    } else {
+                        match db::try_get_image_from_db(name, resolution, self.connection.clone())?
+                        {
+                            Some(db_image) => {
+                                let image = draw::image(
+                                    self.screen_resolution,
+                                    &db_image,
+                                    self.upscale(),
+                                    graphics,
+                                );
+                                self.textures.insert(name, resolution, image.clone());
+                                self.image = Some(image);
+                            }
+                            // a slideshow, or a window still being resized, waits for the cache rather than showing the hourglass
+                            None if self.state == RenderState::Slideshow
+                                || self
+                                    .resized_at
+                                    .is_some_and(|x| x.elapsed() < RESOLUTION_SETTLE)
+                                || self
+                                    .loading_since
+                                    .get_or_insert_with(Instant::now)
+                                    .elapsed()
+                                    < self.config.loading_indicator_delay =>
+                            {
+                                // the cache thread works on the current image first so it may be
+                                // ready soon, show the old image meanwhile rather than flashing the hourglass
+                                if let Some(previous) = &self.previous_image {
+                                    draw::photo(
+                                        self.screen_resolution,
+                                        graphics,
+                                        previous,
+                                        self.upscale(),
+                                    );
+                                }
+                                helper.request_redraw();
+                            }
                             None => {
                                 // draw an hourglass to the screen to indicate loading
-                                let image_bytes = include_bytes!("../img/hourglass.jpg");
-                                draw::image(self.screen_resolution, image_bytes, graphics);
-                                helper.request_redraw();
+                                draw::hourglass(
+                                    self.screen_resolution,
+                                    graphics,
+                                    self.spinner.angle(),
+                                );
+                                self.cache_tx
+                                    .send(CacheMessage::Load(name.to_owned()))
+                                    .unwrap();
                                 self.state = RenderState::LoadingFull;
+                                self.loading_since = None;
                             }
                         }
                     }
-                    RenderState::Zooming => {
-                        helper.set_cursor_visible(true);
-                        let (img, _) = load_image(&self.path, name).unwrap();
-                        let img = crop_center(img, self.screen_resolution).unwrap();
-                        draw::image_full(img, graphics);
-                    }
-                    RenderState::LoadingFull => {
-                        let resized = load_and_insert_image(
-                            &self.path,
-                            name,
-                            self.screen_resolution,
-                            self.connection.clone(),
-                        )
-                        .unwrap();
+                }
+                RenderState::Zooming => {
+                    helper.set_cursor_visible(true);
 
-                        let image = draw::image(self.screen_resolution, &resized, graphics);
-                        self.image = Some(image);
-                        self.state = RenderState::Full;
-                    }
-                    RenderState::ExportRequested => {
-                        let image_bytes = include_bytes!("../img/hourglass.jpg");
-                        draw::image(self.screen_resolution, image_bytes, graphics);
-                        helper.request_redraw();
-                        self.state = RenderState::Exporting;
-                    }
-                    RenderState::Exporting => {
-                        export(&self.path, self.images.all()).unwrap();
-                        self.state = RenderState::Full;
-                        helper.request_redraw();
+                    // decode once while the zoom key is held, the view is redrawn as the mouse moves
+                    if self.zoom_source.as_ref().is_none_or(|(x, _, _)| x != name) {
+                        match self.decoded.get(name, image_file.rotation)? {
+                            Some(decoded) => {
+                                let navigator =
+                                    draw::zoom_navigator_texture(graphics, &decoded.img);
+                                self.zoom_source = Some((name.to_owned(), decoded, navigator));
+                            }
+                            None => draw::hourglass(
+                                self.screen_resolution,
+                                graphics,
+                                self.spinner.angle(),
+                            ),
+                        }
                     }
-                    RenderState::Help => draw::help(self.screen_resolution, graphics, &self.font),
-                    RenderState::Metadata => {
-                        let metadata = metadata::get_metadata(&self.path, name).unwrap();
-                        draw::metadata(
-                            name,
+
+                    if let Some((_, decoded, navigator)) = &self.zoom_source {
+                        let img = &decoded.img;
+                        let center = self
+                            .zoom_pan
+                            .unwrap_or_else(|| self.zoom_center(img.dimensions()));
+                        let crop = crop_rect(img, center, self.zoom, self.screen_resolution);
+                        let region = crop_region(img, &crop, self.zoom);
+                        draw::image_uncompressed(self.screen_resolution, region, graphics);
+                        draw::zoom_navigator(
                             self.screen_resolution,
                             graphics,
                             &self.font,
-                            &metadata,
-                        )
+                            navigator,
+                            UVec2::new(img.width(), img.height()),
+                            &crop,
+                            self.zoom,
+                        );
                     }
                 }
-            } else {
-                let image = self.image.as_ref().expect("no image set");
-                let position = calculate_position_middle(self.screen_resolution, image);
-                graphics.draw_image(position, image);
+                RenderState::LoadingFull => {
+                    // the cache thread resizes the image next and wakes the window when it is done
+                    if let Some(message) = self.cache_failures.lock().unwrap().remove(name) {
+                        return Err(Error::Decode(message));
+                    }
+
+                    let resolution = self.cache_resolution();
+                    let resized =
+                        db::try_get_image_from_db(name, resolution, self.connection.clone())?;
+                    match resized {
+                        Some(resized) => {
+                            let image = draw::image(
+                                self.screen_resolution,
+                                &resized,
+                                self.upscale(),
+                                graphics,
+                            );
+                            self.textures.insert(name, resolution, image.clone());
+                            self.image = Some(image);
+                            self.state = RenderState::Full;
+                        }
+                        // drawn again once the image has been resized
+                        None => {
+                            draw::hourglass(self.screen_resolution, graphics, self.spinner.angle())
+                        }
+                    }
+                }
+                RenderState::ExportRequested => {
+                    self.start_export();
+                    self.state = RenderState::Exporting;
+                    draw::hourglass(self.screen_resolution, graphics, self.spinner.angle());
+                }
+                RenderState::Exporting => {
+                    // the export thread wakes the window after each photo and once it has finished
+                    draw::hourglass(self.screen_resolution, graphics, self.spinner.angle());
+                    draw::progress_text(
+                        self.screen_resolution,
+                        graphics,
+                        &self.font,
+                        &self.export_progress,
+                    );
+                }
+                RenderState::Help => draw::help(self.screen_resolution, graphics, &self.font),
+                RenderState::Stats => {
+                    let stats = self.stats()?;
+                    draw::stats(self.screen_resolution, graphics, &self.font, &stats)
+                }
+                RenderState::ShootStats if self.counting_shoot_stats.is_some() => {
+                    draw::hourglass(self.screen_resolution, graphics, self.spinner.angle())
+                }
+                RenderState::ShootStats => draw::shoot_stats(
+                    self.screen_resolution,
+                    graphics,
+                    &self.font,
+                    &self.shoot_stats,
+                ),
+                RenderState::Compare if self.flicker.is_some() => {
+                    helper.set_cursor_visible(false);
+                    let showing = self.flicker.as_ref().expect("flickering").showing();
+                    let name = self.compare_marks[showing].clone();
+                    match self.screen_texture(&name, graphics)? {
+                        Some(texture) => {
+                            draw::photo(self.screen_resolution, graphics, &texture, self.upscale())
+                        }
+                        // drawn when the cache thread's event redraws the screen
+                        None => {
+                            draw::hourglass(self.screen_resolution, graphics, self.spinner.angle())
+                        }
+                    }
+                }
+                RenderState::Compare => {
+                    helper.set_cursor_visible(false);
+                    let left = self.compare_marks[0].clone();
+                    let right = self.compare_marks[1].clone();
+                    let left = self.screen_texture(&left, graphics)?;
+                    let right = self.screen_texture(&right, graphics)?;
+
+                    // an image that is not resized yet is drawn when the cache thread's event redraws the screen
+                    draw::compare(
+                        self.screen_resolution,
+                        graphics,
+                        left.as_ref(),
+                        right.as_ref(),
+                    );
+                }
+                RenderState::Grid => {
+                    helper.set_cursor_visible(true);
+
+                    // the folder may have been scanned again since the selection was made
+                    let len = self.images.all().len();
+                    self.grid_index = self.grid_index.min(len - 1);
+                    let (columns, rows) = draw::grid_page_size(self.screen_resolution);
+                    let first = self.grid_index / (columns * rows) * columns * rows;
+                    let last = (first + columns * rows).min(len);
+                    let thumbnails = self.load_thumbnails(first..last, graphics)?;
+
+                    let page = &self.images.all()[first..last];
+                    let cells: Vec<_> = page
+                        .iter()
+                        .zip(&thumbnails)
+                        .map(|(x, thumbnail)| (x, thumbnail.as_ref()))
+                        .collect();
+                    draw::grid(
+                        self.screen_resolution,
+                        graphics,
+                        &self.font,
+                        &cells,
+                        self.grid_index - first,
+                    );
+                }
+                RenderState::Metadata => {
+                    // the file name is still shown for images without exif, such as screenshots
+                    let metadata = metadata::get_metadata(&self.path, name).unwrap_or_else(|e| {
+                        info!("No metadata for {name}: {e:?}");
+                        ImageMetadata::default()
+                    });
+                    self.metadata_max_scroll = draw::metadata(
+                        name,
+                        self.screen_resolution,
+                        graphics,
+                        &self.font,
+                        &metadata,
+                        self.metadata_scroll,
+                    );
+                    self.metadata_scroll = self.metadata_scroll.min(self.metadata_max_scroll);
+                }
+                RenderState::ExifDump => {
+                    let fields = metadata::get_all_fields(&self.path, name).unwrap_or_default();
+                    let page_size = draw::exif_dump_page_size(self.screen_resolution);
+                    self.exif_scroll = self.exif_scroll.min(fields.len().saturating_sub(page_size));
+                    draw::exif_dump(
+                        name,
+                        self.screen_resolution,
+                        graphics,
+                        &self.font,
+                        &fields,
+                        self.exif_scroll,
+                    )
+                }
+            }
+        } else {
+            let image = self.image.as_ref().expect("no image set");
+            draw::photo(self.screen_resolution, graphics, image, self.upscale());
+        }
+
+        // the grid shows the stars of every cell instead
+        let single_photo = !matches!(self.state, RenderState::Grid | RenderState::Compare);
+        if single_photo && (image_file.is_starred || image_file.rating > 0) {
+            draw::star(self.screen_resolution, graphics, image_file.rating);
+        }
+
+        if single_photo && image_file.is_protected {
+            draw::protected(self.screen_resolution, graphics);
+        }
+
+        if single_photo && image_file.is_flagged {
+            draw::flagged(self.screen_resolution, graphics);
+        }
+
+        if single_photo && image_file.color_label > 0 {
+            draw::color_label(graphics, image_file.color_label);
+        }
+
+        if self.show_filmstrip && self.state == RenderState::Full {
+            let index = self.images.current_index();
+            let first = index.saturating_sub(FILMSTRIP_NEIGHBOURS);
+            let last = (index + FILMSTRIP_NEIGHBOURS + 1).min(self.images.all().len());
+            let thumbnails = self.load_thumbnails(first..last, graphics)?;
+
+            let cells: Vec<_> = self.images.all()[first..last]
+                .iter()
+                .zip(&thumbnails)
+                .map(|(x, thumbnail)| (x, thumbnail.as_ref()))
+                .collect();
+            draw::filmstrip(self.screen_resolution, graphics, &cells, index - first);
+        }
+
+        if self.show_calibration {
+            draw::calibration(self.screen_resolution, graphics, &self.config.calibration);
+        }
+
+        if self.show_ruler && self.state == RenderState::Full {
+            if let (Some(image), Some((_, Some(source)))) = (&self.image, &self.ruler) {
+                // the ruler runs along the displayed width, the stored height when the photo is on its side
+                let orientation = self.displayed_orientation(name, source.orientation);
+                let width = if is_quarter_turned(orientation, image_file.rotation) {
+                    source.height
+                } else {
+                    source.width
+                };
+                let rect = draw::photo_rect(self.screen_resolution, image, self.upscale());
+                draw::ruler(graphics, &self.font, &rect, width, source.pixels_per_mm);
+            }
+        }
+
+        if self.show_focus_points && self.state == RenderState::Full {
+            if let (Some(image), Some((_, points, orientation))) = (&self.image, &self.focus_points)
+            {
+                // turned the same way as the photo
+                let orientation = self.displayed_orientation(name, *orientation);
+                let points: Vec<FocusPoint> = points
+                    .iter()
+                    .map(|x| orient_focus_point(*x, orientation, image_file.rotation))
+                    .collect();
+                let rect = draw::photo_rect(self.screen_resolution, image, self.upscale());
+                draw::focus_points(graphics, &rect, &points);
             }
+        }
+
+        if self.show_file_name {
+            let has_color_label = single_photo && image_file.color_label > 0;
+            draw::file_name(
+                graphics,
+                &image_file.jpg_file_name,
+                &self.font,
+                has_color_label,
+            );
+        }
+
+        let mut labels = vec![];
+        if self.read_only {
+            labels.push("READ ONLY");
+        }
+        if self.listing.is_some() {
+            labels.push("LISTING FOLDER");
+        }
+        let no_focus_points =
+            matches!(&self.focus_points, Some((_, points, _)) if points.is_empty());
+        if self.show_focus_points && self.state == RenderState::Full && no_focus_points {
+            labels.push("NO AF DATA");
+        }
+        // the ruler is in pixels when the photo does not record the physical size of a pixel
+        let pixel_ruler =
+            matches!(&self.ruler, Some((_, Some(source))) if source.pixels_per_mm.is_none());
+        if self.show_ruler && self.state == RenderState::Full && pixel_ruler {
+            labels.push("NO DPI, RULER IN PIXELS");
+        }
+        if self.fresh_decode && self.state == RenderState::Full {
+            labels.push("FRESH DECODE");
+        }
+        if self.config.sort_order == SortOrder::DateTaken {
+            labels.push("BY DATE TAKEN");
+        }
+        if single_photo && self.compare_marks.contains(&image_file.jpg_file_name) {
+            labels.push("MARKED TO COMPARE");
+        }
+        if !labels.is_empty() {
+            draw::label(
+                self.screen_resolution,
+                graphics,
+                &self.font,
+                &labels.join("  "),
+            );
+        }
+
+        // one job is shown at a time, the ones the user started before the cache
+        let progress = [&self.similar_progress, &self.resize_progress]
+            .into_iter()
+            .find(|x| x.percentage() < 100)
+            .unwrap_or(&self.progress);
+        draw::progress_text(self.screen_resolution, graphics, &self.font, progress);
 
-            if image_file.is_starred {
-                draw::star(self.screen_resolution, graphics);
+        if self.is_filtered() {
+            let mut filters = vec![];
+            if self.filter != ImageFilter::All {
+                filters.push(self.filter.to_string());
             }
+            if let Some(date_filter) = self.date_filter {
+                filters.push(date_filter.to_string());
+            }
+
+            let filter = self.navigation_filter(true);
+            let count = self.images.all().iter().filter(|x| filter(x)).count();
+            let status = format!("{}: {count} photos", filters.join(", "));
+            draw::status(self.screen_resolution, graphics, &self.font, &status);
+        }
 
-            if self.show_file_name {
-                draw::file_name(graphics, &image_file.jpg_file_name, &self.font);
+        if let Some((message, shown_at)) = &self.notice {
+            if shown_at.elapsed() < NOTICE_DURATION {
+                draw::notice(self.screen_resolution, graphics, &self.font, message);
+                helper.request_redraw();
+            } else {
+                self.notice = None;
             }
+        }
+
+        if let Some(input) = &self.date_input {
+            draw::prompt(
+                self.screen_resolution,
+                graphics,
+                &self.font,
+                "Show photos taken on YYYY-MM-DD or between YYYY-MM-DD..YYYY-MM-DD\n(leave empty to show all photos)",
+                input,
+            );
+        }
 
-            draw::progress_text(
+        if let Some(confirm) = &self.confirm {
+            draw::confirm(
                 self.screen_resolution,
                 graphics,
                 &self.font,
-                self.progress_percentage.clone(),
+                &confirm.message,
             );
         }
+
+        match self.preload {
+            Preload::Waiting if self.image.is_some() => {
+                self.preload = Preload::Due;
+                helper.request_redraw();
+            }
+            Preload::Due => {
+                self.preload = Preload::Done;
+                self.preload_neighbours(graphics)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
     }
 
-    fn on_key_down(
+    /// Upload the images either side of the current image to the texture cache so that the next UP or DOWN
+    /// does not wait for the database. Images the cache thread has not resized yet are skipped.
+    /// The texture cache drops the least recently used images, which bounds the GPU memory used
+    fn preload_neighbours(&mut self, graphics: &mut Graphics2D) -> Result<(), Error> {
+        let len = self.images.all().len();
+        let index = self.images.current_index();
+        let resolution = self.cache_resolution();
+        for offset in 1..=PRELOAD_NEIGHBOURS.min(len / 2) {
+            for neighbour in [(index + offset) % len, (index + len - offset) % len] {
+                let name = &self.images.all()[neighbour].jpg_file_name;
+                if self.textures.contains(name, resolution) {
+                    continue;
+                }
+
+                let resized = db::try_get_image_from_db(name, resolution, self.connection.clone())?;
+                if let Some(resized) = resized {
+                    let texture = draw::screen_texture(&resized, self.upscale(), graphics);
+                    self.textures.insert(name, resolution, texture);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_key(
         &mut self,
         helper: &mut WindowHelper,
         virtual_key_code: Option<VirtualKeyCode>,
-        _scancode: KeyScancode,
-    ) {
+    ) -> Result<(), Error> {
         match virtual_key_code {
             Some(VirtualKeyCode::Escape) => match self.state {
                 // exit screen or application
-                RenderState::Help | RenderState::Metadata => {
+                RenderState::Help
+                | RenderState::Stats
+                | RenderState::ShootStats
+                | RenderState::Metadata
+                | RenderState::ExifDump => {
                     self.state = RenderState::Full;
                     helper.request_redraw()
                 }
+                RenderState::Zooming => self.stop_zooming(helper),
                 _ => std::process::exit(0),
             },
+            Some(VirtualKeyCode::Up) if self.modifiers.shift() => {
+                // jump back several images
+                self.images
+                    .step(-(self.config.navigation.big_step as isize));
+                self.image_changed(helper);
+            }
             Some(VirtualKeyCode::Up) => {
                 // prev image
-                self.images.prev();
-                self.image = None;
-                helper.request_redraw();
+                self.images.prev_matching(self.navigation_filter(false));
+                self.image_changed(helper);
             }
             Some(VirtualKeyCode::Left) => {
                 // prev image group
-                if self.show_only_starred {
-                    self.images.prev_starred();
+                if self.is_filtered() {
+                    self.images.prev_matching(self.navigation_filter(true));
+                } else {
+                    self.images.prev_group();
+                }
+                self.image_changed(helper);
+            }
+            Some(VirtualKeyCode::Home) => {
+                self.images.first(self.navigation_filter(true));
+                self.image_changed(helper);
+            }
+            Some(VirtualKeyCode::End) => {
+                self.images.last(self.navigation_filter(true));
+                self.image_changed(helper);
+            }
+            Some(VirtualKeyCode::Down) if self.modifiers.shift() => {
+                // jump forward several images
+                self.images.step(self.config.navigation.big_step as isize);
+                self.image_changed(helper);
+            }
+            Some(VirtualKeyCode::Down) => {
+                // next image
+                self.images.next_matching(self.navigation_filter(false));
+                self.image_changed(helper);
+            }
+
+            Some(VirtualKeyCode::Right) => {
+                // next image
+                if self.is_filtered() {
+                    self.images.next_matching(self.navigation_filter(true));
+                } else {
+                    self.images.next_group();
+                }
+                self.image_changed(helper);
+            }
+            Some(VirtualKeyCode::T) => {
+                // toggle photos taken today
+                let today = Local::now().date_naive();
+                let filter = DateFilter {
+                    from: today,
+                    to: today,
+                };
+                if self.date_filter == Some(filter) {
+                    self.set_date_filter(helper, None);
+                } else {
+                    self.set_date_filter(helper, Some(filter));
+                }
+            }
+            Some(VirtualKeyCode::F8) => self.start_finding_similar_photos(helper),
+            Some(VirtualKeyCode::O) if self.modifiers.shift() => {
+                // show the photo, or its full resolution original, in the file manager
+                let image = self.images.current();
+                match &image.original {
+                    Some(original) => {
+                        disk::reveal_in_file_manager(&original.path, &original.jpg_file_name)?
+                    }
+                    None => disk::reveal_in_file_manager(&self.path, &image.jpg_file_name)?,
+                }
+            }
+            Some(VirtualKeyCode::C) => self.copy_path(helper)?,
+            Some(VirtualKeyCode::D) => {
+                self.date_input = Some(String::new());
+                helper.request_redraw()
+            }
+            Some(VirtualKeyCode::LControl) if self.state != RenderState::Zooming => {
+                // hold down to zoom
+                self.state = RenderState::Zooming;
+                self.zoom_latched = false;
+                self.image = None;
+                helper.request_redraw();
+            }
+            Some(VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd) => {
+                // zoom in and stay zoomed, starting pixel for pixel
+                if self.state == RenderState::Zooming {
+                    self.zoom = (self.zoom * ZOOM_STEP).min(MAX_ZOOM);
                 } else {
-                    self.images.prev_group();
+                    self.state = RenderState::Zooming;
+                    self.image = None;
                 }
-                self.image = None;
+                self.zoom_latched = true;
                 helper.request_redraw();
             }
-            Some(VirtualKeyCode::Down) => {
-                // next image
-                self.images.next();
-                self.image = None;
+            Some(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract)
+                if self.state == RenderState::Zooming =>
+            {
+                self.zoom = (self.zoom / ZOOM_STEP).max(MIN_ZOOM);
+                self.zoom_latched = true;
                 helper.request_redraw();
             }
-
-            Some(VirtualKeyCode::Right) => {
-                // next image
-                if self.show_only_starred {
-                    self.images.next_starred();
-                } else {
-                    self.images.next_group();
-                }
-                self.image = None;
+            Some(VirtualKeyCode::Space) if self.modifiers.shift() => {
+                let filter = self.navigation_filter(true);
+                let count = self.images.all().iter().filter(|x| filter(x)).count();
+                self.ask(
+                    &format!("Star all {count} photos shown?"),
+                    ConfirmAction::StarAllShown,
+                );
                 helper.request_redraw();
             }
-            Some(VirtualKeyCode::LControl) => {
-                // hold down to zoom
-                self.state = RenderState::Zooming;
-                self.image = None;
+            Some(VirtualKeyCode::Space) if self.modifiers.ctrl() => {
+                let count = self.images.all().iter().filter(|x| x.is_starred).count();
+                self.ask(
+                    &format!("Remove the star from all {count} starred photos?"),
+                    ConfirmAction::ClearAllStars,
+                );
                 helper.request_redraw();
             }
             Some(VirtualKeyCode::Space) => {
-                // toggle is starred
+                // toggle is starred, or accept the image and move on
+                let space_key = self.config.space_key;
                 let image = self.images.current_mut();
-                image.is_starred = !image.is_starred;
+                image.is_starred = match space_key {
+                    SpaceKey::Toggle => !image.is_starred,
+                    SpaceKey::StarAndAdvance => true,
+                };
                 db::update_image_is_starred(
                     &image.jpg_file_name,
                     image.is_starred,
                     self.connection.clone(),
-                )
-                .unwrap();
+                )?;
+
+                if space_key == SpaceKey::StarAndAdvance {
+                    self.images.next();
+                    self.image_changed(helper);
+                } else {
+                    helper.request_redraw();
+                }
+            }
+            Some(VirtualKeyCode::Delete) => self.delete(helper)?,
+            Some(VirtualKeyCode::Z) if self.modifiers.ctrl() => self.undo_delete(helper)?,
+            Some(VirtualKeyCode::L) => {
+                // toggle protect from deletion
+                let image = self.images.current_mut();
+                image.is_protected = !image.is_protected;
+                db::update_image_is_protected(
+                    &image.jpg_file_name,
+                    image.is_protected,
+                    self.connection.clone(),
+                )?;
                 helper.request_redraw();
             }
-            Some(VirtualKeyCode::E) if self.state != RenderState::ExportRequested => {
-                // export starred images
-                self.state = RenderState::ExportRequested;
-                self.image = None;
+            Some(VirtualKeyCode::K) => {
+                // toggle the maybe flag
+                let image = self.images.current_mut();
+                image.is_flagged = !image.is_flagged;
+                db::update_image_flagged(
+                    &image.jpg_file_name,
+                    image.is_flagged,
+                    self.connection.clone(),
+                )?;
                 helper.request_redraw();
             }
-            Some(VirtualKeyCode::S) => {
-                self.show_only_starred = !self.show_only_starred;
-
-                if self.show_only_starred && !self.images.current().is_starred {
-                    self.images.next_starred();
-                    self.image = None;
+            Some(VirtualKeyCode::N) => self.next_unrated(helper, self.modifiers.shift()),
+            Some(VirtualKeyCode::J) if self.modifiers.shift() => {
+                self.images.prev_flagged();
+                self.image_changed(helper);
+            }
+            Some(VirtualKeyCode::J) => {
+                self.images.next_flagged();
+                self.image_changed(helper);
+            }
+            Some(VirtualKeyCode::E) if self.state != RenderState::ExportRequested => {
+                // export starred images
+                if self.images.all().iter().any(|x| x.is_starred) {
+                    self.run_confirmed(helper, ConfirmAction::Export)?;
+                } else {
+                    self.ask(
+                        "There are no starred photos, create an empty export folder anyway?",
+                        ConfirmAction::Export,
+                    );
                     helper.request_redraw();
                 }
             }
+            Some(VirtualKeyCode::R) if self.modifiers.ctrl() => {
+                self.toggle_exif_orientation(helper)?
+            }
+            Some(VirtualKeyCode::R) if self.modifiers.shift() => self.rotate(helper, 3)?,
+            Some(VirtualKeyCode::R) => self.rotate(helper, 1)?,
+            Some(VirtualKeyCode::W) if self.modifiers.shift() => {
+                // list the starred files in the folder rather than copying them
+                let starred = starred(self.images.all());
+                let file_name = disk::write_manifest(&self.path, &starred)?;
+                info!("Wrote {} photos to {file_name}", starred.len());
+                if self.config.manifest_csv {
+                    let file_name = disk::write_manifest_csv(&self.path, &starred)?;
+                    info!("Wrote {} photos to {file_name}", starred.len());
+                }
+            }
+            Some(VirtualKeyCode::W) => {
+                // write the starred photos to a playlist file
+                let starred = starred(self.images.all());
+                match playlist::write(&self.path, &starred, self.config.playlist_format) {
+                    Ok(file_name) => info!("Wrote {} photos to {file_name}", starred.len()),
+                    Err(e) => error!("Unable to write playlist: {e:?}"),
+                }
+            }
+            Some(VirtualKeyCode::S) => {
+                let filter = self.filter.cycle(self.modifiers.shift());
+                self.set_filter(helper, filter);
+            }
             Some(VirtualKeyCode::H) | Some(VirtualKeyCode::F1) => {
                 // toggle help
                 if self.state == RenderState::Help {
@@ -625,62 +3167,453 @@ impl WindowHandler for PhotoWindowHandler {
                 self.image = None;
                 helper.request_redraw()
             }
+            Some(VirtualKeyCode::F2) if self.modifiers.shift() => {
+                // toggle charts of the camera settings used across the folder
+                if self.state == RenderState::ShootStats {
+                    self.state = RenderState::Full;
+                } else {
+                    self.start_counting_shoot_stats();
+                    self.state = RenderState::ShootStats;
+                }
+                self.image = None;
+                helper.request_redraw()
+            }
+            Some(VirtualKeyCode::F2) => {
+                // toggle the counts of starred and rated photos
+                if self.state == RenderState::Stats {
+                    self.state = RenderState::Full;
+                } else {
+                    self.state = RenderState::Stats;
+                }
+                self.image = None;
+                helper.request_redraw()
+            }
+            Some(VirtualKeyCode::F3) if self.modifiers.shift() => {
+                // toggle every exif field in the file
+                if self.state == RenderState::ExifDump {
+                    self.state = RenderState::Full;
+                } else {
+                    self.state = RenderState::ExifDump;
+                    self.exif_scroll = 0;
+                }
+                self.image = None;
+                helper.request_redraw()
+            }
             Some(VirtualKeyCode::F3) => {
                 // toggle image metadata
                 if self.state == RenderState::Metadata {
                     self.state = RenderState::Full;
                 } else {
                     self.state = RenderState::Metadata;
+                    self.metadata_scroll = 0.0;
                 }
                 self.image = None;
                 helper.request_redraw()
             }
+            Some(VirtualKeyCode::P) => {
+                // play a slideshow from the current image
+                let interval = self.config.slideshow_interval;
+                self.slideshow = Some(Slideshow::start(interval, self.user_event_sender.clone()));
+                self.state = RenderState::Slideshow;
+                self.image = None;
+                helper.request_redraw()
+            }
+            Some(VirtualKeyCode::X) => self.mark_for_compare(helper),
+            Some(VirtualKeyCode::G) => {
+                // overview of the folder starting at the current image
+                self.grid_index = self.images.current_index();
+                self.state = RenderState::Grid;
+                self.image = None;
+                helper.request_redraw()
+            }
             Some(VirtualKeyCode::I) => {
                 self.show_file_name = !self.show_file_name;
                 helper.request_redraw()
             }
-            Some(VirtualKeyCode::Key1) => {
-                self.resize_request_tx.send(0.1).unwrap();
+            Some(VirtualKeyCode::V) => {
+                self.show_filmstrip = !self.show_filmstrip;
+                helper.request_redraw()
+            }
+            Some(VirtualKeyCode::LBracket) => {
+                // most under-exposed image
+                self.jump_to_exposure_extreme(helper, false)?;
+            }
+            Some(VirtualKeyCode::RBracket) => {
+                // most over-exposed image
+                self.jump_to_exposure_extreme(helper, true)?;
+            }
+            Some(VirtualKeyCode::F4) => {
+                self.fresh_decode = !self.fresh_decode;
+                self.image = None;
+                helper.request_redraw()
+            }
+            Some(VirtualKeyCode::F5) => self.refresh(helper)?,
+            Some(VirtualKeyCode::F6) => self.toggle_sort_order(helper),
+            Some(VirtualKeyCode::A) => {
+                self.show_focus_points = !self.show_focus_points;
+                helper.request_redraw()
+            }
+            Some(VirtualKeyCode::M) => {
+                self.show_ruler = !self.show_ruler;
+                helper.request_redraw()
+            }
+            Some(VirtualKeyCode::B) => {
+                self.show_calibration = !self.show_calibration;
+                helper.request_redraw()
+            }
+            Some(VirtualKeyCode::Key1) if self.modifiers.shift() => {
+                self.export_tx.send(ExportMessage::Resize(0.1)).unwrap();
                 helper.request_redraw()
             }
-            Some(VirtualKeyCode::Key2) => {
-                self.resize_request_tx.send(0.2).unwrap();
+            Some(VirtualKeyCode::Key2) if self.modifiers.shift() => {
+                self.export_tx.send(ExportMessage::Resize(0.2)).unwrap();
                 helper.request_redraw()
             }
-            Some(VirtualKeyCode::Key3) => {
-                self.resize_request_tx.send(0.3).unwrap();
+            Some(VirtualKeyCode::Key3) if self.modifiers.shift() => {
+                self.export_tx.send(ExportMessage::Resize(0.3)).unwrap();
                 helper.request_redraw()
             }
-            Some(VirtualKeyCode::Key4) => {
-                self.resize_request_tx.send(0.4).unwrap();
+            Some(VirtualKeyCode::Key4) if self.modifiers.shift() => {
+                self.export_tx.send(ExportMessage::Resize(0.4)).unwrap();
                 helper.request_redraw()
             }
-            Some(VirtualKeyCode::Key5) => {
-                self.resize_request_tx.send(0.5).unwrap();
+            Some(VirtualKeyCode::Key5) if self.modifiers.shift() => {
+                self.export_tx.send(ExportMessage::Resize(0.5)).unwrap();
                 helper.request_redraw()
             }
-            Some(VirtualKeyCode::Key6) => {
-                self.resize_request_tx.send(0.6).unwrap();
+            Some(VirtualKeyCode::Key6) if self.modifiers.shift() => {
+                self.export_tx.send(ExportMessage::Resize(0.6)).unwrap();
                 helper.request_redraw()
             }
-            Some(VirtualKeyCode::Key7) => {
-                self.resize_request_tx.send(0.7).unwrap();
+            Some(VirtualKeyCode::Key7) if self.modifiers.shift() => {
+                self.export_tx.send(ExportMessage::Resize(0.7)).unwrap();
                 helper.request_redraw()
             }
-            Some(VirtualKeyCode::Key8) => {
-                self.resize_request_tx.send(0.8).unwrap();
+            Some(VirtualKeyCode::Key8) if self.modifiers.shift() => {
+                self.export_tx.send(ExportMessage::Resize(0.8)).unwrap();
                 helper.request_redraw()
             }
-            Some(VirtualKeyCode::Key9) => {
-                self.resize_request_tx.send(0.9).unwrap();
+            Some(VirtualKeyCode::Key9) if self.modifiers.shift() => {
+                self.export_tx.send(ExportMessage::Resize(0.9)).unwrap();
                 helper.request_redraw()
             }
-            Some(VirtualKeyCode::Key0) => {
-                self.resize_request_tx.send(1.0).unwrap();
+            Some(VirtualKeyCode::Key0) if self.modifiers.shift() => {
+                self.export_tx.send(ExportMessage::Resize(1.0)).unwrap();
                 helper.request_redraw()
             }
+            Some(VirtualKeyCode::Key1) => self.set_rating(helper, 1)?,
+            Some(VirtualKeyCode::Key2) => self.set_rating(helper, 2)?,
+            Some(VirtualKeyCode::Key3) => self.set_rating(helper, 3)?,
+            Some(VirtualKeyCode::Key4) => self.set_rating(helper, 4)?,
+            Some(VirtualKeyCode::Key5) => self.set_rating(helper, 5)?,
+            Some(VirtualKeyCode::Key0) => self.set_rating(helper, 0)?,
+            Some(VirtualKeyCode::Key6) => self.toggle_color_label(helper, 1)?,
+            Some(VirtualKeyCode::Key7) => self.toggle_color_label(helper, 2)?,
+            Some(VirtualKeyCode::Key8) => self.toggle_color_label(helper, 3)?,
             _ => {}
         }
+
+        Ok(())
+    }
+}
+
+impl WindowHandler for PhotoWindowHandler {
+    fn on_user_event(&mut self, helper: &mut WindowHelper<()>, _user_event: ()) {
+        if let Some(listing) = &self.listing {
+            if let Ok(result) = listing.try_recv() {
+                self.listing = None;
+                let result = result.and_then(|x| self.listing_finished(helper, x));
+                if let Err(e) = result {
+                    self.show_error(e);
+                }
+            }
+        }
+        // the folder is only scanned again once however many batches of new photos have arrived, photos
+        // added while it is first listed are picked up afterwards
+        if self.listing.is_none() && self.new_photos_rx.try_iter().count() > 0 {
+            if let Err(e) = self.add_new_photos(helper) {
+                error!("Unable to add new photos: {e:?}");
+            }
+        }
+        if let Some(exporting) = &self.exporting {
+            if let Ok(result) = exporting.try_recv() {
+                self.exporting = None;
+                self.state = RenderState::Full;
+                if let Err(e) = result {
+                    self.show_error(e);
+                }
+            }
+        }
+        if let Some(counting_shoot_stats) = &self.counting_shoot_stats {
+            if let Ok(result) = counting_shoot_stats.try_recv() {
+                self.counting_shoot_stats = None;
+                match result {
+                    Ok(shoot_stats) => {
+                        self.shoot_stats = shoot_stats;
+                        if self.state == RenderState::ShootStats {
+                            self.image = None;
+                            helper.request_redraw();
+                        }
+                    }
+                    Err(e) => self.show_error(e),
+                }
+            }
+        }
+        if let Some(finding_similar) = &self.finding_similar {
+            if let Ok(result) = finding_similar.try_recv() {
+                self.finding_similar = None;
+                match result {
+                    Ok(group_ids) => {
+                        // photos may have been added or removed while they were compared
+                        let group_ids: HashMap<String, Option<i64>> =
+                            group_ids.into_iter().collect();
+                        for image in self.images.all_mut() {
+                            if let Some(group_id) = group_ids.get(&image.jpg_file_name) {
+                                image.group_id = *group_id;
+                            }
+                        }
+                    }
+                    Err(e) => self.show_error(e),
+                }
+            }
+        }
+        // other keys can leave the comparison without going through `compare_key`
+        if self.state != RenderState::Compare {
+            self.flicker = None;
+        }
+        if let Some(flicker) = &mut self.flicker {
+            if flicker.is_due() {
+                flicker.swap();
+            }
+        }
+        if let Some(slideshow) = &mut self.slideshow {
+            if self.state == RenderState::Slideshow && slideshow.is_due() && !self.images.is_empty()
+            {
+                slideshow.advanced();
+                self.images.next_matching(self.navigation_filter(false));
+                self.image_changed(helper);
+            }
+        }
+        helper.request_redraw()
+    }
+
+    fn on_keyboard_char(&mut self, helper: &mut WindowHelper, unicode_codepoint: char) {
+        if let Some(input) = &mut self.date_input {
+            match unicode_codepoint {
+                '\u{8}' => {
+                    input.pop();
+                }
+                '0'..='9' | '-' | '.' => input.push(unicode_codepoint),
+                _ => return,
+            }
+            helper.request_redraw();
+        }
+    }
+
+    fn on_mouse_move(&mut self, helper: &mut WindowHelper, position: Vec2) {
+        self.mouse_position = position;
+        if self.state == RenderState::Zooming {
+            // pan to the part of the image under the mouse, taking over from the arrow keys
+            self.zoom_pan = None;
+            helper.request_redraw();
+        }
+    }
+
+    fn on_mouse_wheel_scroll(&mut self, helper: &mut WindowHelper, distance: MouseScrollDistance) {
+        if self.state != RenderState::Zooming {
+            return;
+        }
+
+        let lines = match distance {
+            MouseScrollDistance::Lines { y, .. } => y,
+            MouseScrollDistance::Pixels { y, .. } => y / 100.0,
+            MouseScrollDistance::Pages { y, .. } => y * 3.0,
+        };
+        self.zoom = (self.zoom * ZOOM_STEP.powf(lines as f32)).clamp(MIN_ZOOM, MAX_ZOOM);
+        helper.request_redraw();
+    }
+
+    fn on_keyboard_modifiers_changed(&mut self, _helper: &mut WindowHelper, state: ModifiersState) {
+        self.modifiers = state;
+    }
+
+    fn on_resize(&mut self, helper: &mut WindowHelper<()>, size_pixels: UVec2) {
+        if size_pixels == self.screen_resolution {
+            return;
+        }
+
+        log::info!("Screen resolution changed to: {size_pixels:?}");
+        self.screen_resolution = size_pixels;
+        self.resolution_changed(helper);
+    }
+
+    fn on_start(&mut self, helper: &mut WindowHelper<()>, info: WindowStartupInfo) {
+        self.on_scale_factor_changed(helper, info.scale_factor());
+    }
+
+    fn on_scale_factor_changed(&mut self, helper: &mut WindowHelper<()>, scale_factor: f64) {
+        if scale_factor == self.scale_factor {
+            return;
+        }
+
+        log::info!("Scale factor changed to: {scale_factor}");
+        let resolution = self.cache_resolution();
+        self.scale_factor = scale_factor;
+        // the cache is only affected when it is at the logical resolution
+        if self.cache_resolution() != resolution {
+            self.resolution_changed(helper);
+        }
+    }
+
+    fn on_draw(&mut self, helper: &mut WindowHelper, graphics: &mut Graphics2D) {
+        graphics.clear_screen(Color::BLACK);
+
+        if resolution_ok(self.screen_resolution, &self.config) && self.images.is_empty() {
+            helper.set_cursor_visible(true);
+            draw::empty(self.screen_resolution, graphics, &self.font);
+        } else if resolution_ok(self.screen_resolution, &self.config) {
+            if let Err(e) = self.draw_current(helper, graphics) {
+                self.show_error(e);
+                helper.request_redraw();
+            }
+        }
+        self.spinner.end_frame();
+    }
+
+    fn on_key_down(
+        &mut self,
+        helper: &mut WindowHelper,
+        virtual_key_code: Option<VirtualKeyCode>,
+        _scancode: KeyScancode,
+    ) {
+        // answers and typed dates use the keys as they are, everything else honors the key bindings
+        if let Some(confirm) = self.confirm.take() {
+            match virtual_key_code {
+                Some(VirtualKeyCode::Y) | Some(VirtualKeyCode::Return) => {
+                    if let Err(e) = self.run_confirmed(helper, confirm.action) {
+                        self.show_error(e);
+                    }
+                }
+                Some(VirtualKeyCode::N) | Some(VirtualKeyCode::Escape) => {}
+                // keep asking until the user answers
+                _ => self.confirm = Some(confirm),
+            }
+            helper.request_redraw();
+            return;
+        }
+
+        if let Some(input) = self.date_input.take() {
+            // the typed characters arrive in on_keyboard_char
+            match virtual_key_code {
+                Some(VirtualKeyCode::Return) if input.trim().is_empty() => {
+                    self.set_date_filter(helper, None)
+                }
+                Some(VirtualKeyCode::Return) => match DateFilter::parse(&input) {
+                    Some(filter) => self.set_date_filter(helper, Some(filter)),
+                    // keep asking until the date is valid
+                    None => self.date_input = Some(input),
+                },
+                Some(VirtualKeyCode::Escape) => {}
+                _ => self.date_input = Some(input),
+            }
+            helper.request_redraw();
+            return;
+        }
+
+        let virtual_key_code = self.remap(virtual_key_code);
+        if self.images.is_empty() {
+            // nothing to navigate, the only options are to open another folder or exit
+            match virtual_key_code {
+                Some(VirtualKeyCode::O) => open_another_folder(&self.path),
+                Some(VirtualKeyCode::Escape) => std::process::exit(0),
+                Some(VirtualKeyCode::F5) => {
+                    if let Err(e) = self.refresh(helper) {
+                        error!("Unable to scan the folder again: {e:?}");
+                    }
+                }
+                Some(VirtualKeyCode::Z) if self.modifiers.ctrl() => {
+                    if let Err(e) = self.undo_delete(helper) {
+                        error!("Unable to undo: {e:?}");
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        if let RenderState::ErrorMessage { .. } = self.state {
+            // the error stays on screen until the user moves on from the photo that caused it
+            if let Some(VirtualKeyCode::Escape) = virtual_key_code {
+                self.state = RenderState::Full;
+                self.images.next();
+                self.image_changed(helper);
+            }
+            return;
+        }
+
+        if self.state == RenderState::Exporting {
+            // the photo is shown again once the export has finished
+            return;
+        }
+
+        let exports_in_place =
+            export_folder(&self.path, &self.config.export).starts_with(&self.path);
+        if self.read_only
+            && writes_to_folder(virtual_key_code, self.modifiers.shift(), exports_in_place)
+        {
+            info!("{virtual_key_code:?} is disabled because the folder is read only");
+            return;
+        }
+
+        if self.listing.is_some() && needs_whole_folder(virtual_key_code, self.modifiers.shift()) {
+            self.notice = Some(("Still listing the folder".to_owned(), Instant::now()));
+            helper.request_redraw();
+            return;
+        }
+
+        if self.state == RenderState::ExifDump && self.scroll_exif_dump(virtual_key_code) {
+            helper.request_redraw();
+            return;
+        }
+
+        if self.state == RenderState::Metadata && self.scroll_metadata(virtual_key_code) {
+            helper.request_redraw();
+            return;
+        }
+
+        if self.state == RenderState::Grid && self.grid_key(helper, virtual_key_code) {
+            return;
+        }
+
+        if self.state == RenderState::Slideshow && self.slideshow_key(helper, virtual_key_code) {
+            return;
+        }
+
+        if self.state == RenderState::Compare && self.compare_key(helper, virtual_key_code) {
+            return;
+        }
+
+        if self.state == RenderState::Zooming && self.zoom_key(helper, virtual_key_code) {
+            return;
+        }
+
+        let index = self.images.current_index();
+        if let Err(e) = self.handle_key(helper, virtual_key_code) {
+            self.show_error(e);
+            helper.request_redraw();
+        }
+
+        // moving to another photo, opening another screen or asking for input stops the slideshow
+        let interrupted = self.state != RenderState::Slideshow
+            || self.images.current_index() != index
+            || self.date_input.is_some()
+            || self.confirm.is_some();
+        if self.slideshow.is_some() && interrupted {
+            self.slideshow = None;
+            if self.state == RenderState::Slideshow {
+                self.state = RenderState::Full;
+            }
+        }
     }
 
     fn on_key_up(
@@ -689,11 +3622,190 @@ impl WindowHandler for PhotoWindowHandler {
         virtual_key_code: Option<VirtualKeyCode>,
         _scancode: KeyScancode,
     ) {
+        let virtual_key_code = self.remap(virtual_key_code);
         if let Some(VirtualKeyCode::LControl) = virtual_key_code {
-            // resume normal viewing
-            self.state = RenderState::Full;
-            self.image = None;
-            helper.request_redraw();
+            if self.state == RenderState::Zooming && !self.zoom_latched {
+                self.stop_zooming(helper);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    const WIDTH: u32 = 3;
+    const HEIGHT: u32 = 2;
+
+    /// every pixel is a different color so that it can be found after the image is turned
+    fn test_image() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+            Rgb([x as u8 * 80, y as u8 * 80, 0])
+        }))
+    }
+
+    /// where the pixel at (x, y) of the stored image is displayed for each exif orientation, from the
+    /// definition of the tag as the sides of the display that the first row and column are on
+    fn displayed_at(orientation: u32, (x, y): (u32, u32)) -> (u32, u32) {
+        match orientation {
+            1 => (x, y),
+            2 => (WIDTH - 1 - x, y),
+            3 => (WIDTH - 1 - x, HEIGHT - 1 - y),
+            4 => (x, HEIGHT - 1 - y),
+            5 => (y, x),
+            6 => (HEIGHT - 1 - y, x),
+            7 => (HEIGHT - 1 - y, WIDTH - 1 - x),
+            8 => (y, WIDTH - 1 - x),
+            _ => unreachable!(),
+        }
+    }
+
+    /// where a pixel at (x, y) of a `width` by `height` image ends up after `rotation` quarter turns clockwise
+    fn turned_to(
+        rotation: u8,
+        (mut x, mut y): (u32, u32),
+        (mut width, mut height): (u32, u32),
+    ) -> (u32, u32) {
+        for _ in 0..rotation {
+            (x, y) = (height - 1 - y, x);
+            (width, height) = (height, width);
+        }
+        (x, y)
+    }
+
+    #[test]
+    fn apply_orientation_handles_all_exif_orientations() {
+        let img = test_image();
+        for orientation in 1..=8 {
+            for rotation in 0..4 {
+                let oriented = apply_orientation(img.clone(), Some(orientation), rotation);
+                // orientations 5 to 8 are a quarter turn, as is an odd number of turns by the user
+                let turned = (orientation >= 5) != (rotation % 2 == 1);
+                assert_eq!(is_quarter_turned(Some(orientation), rotation), turned);
+                let expected_size = if turned {
+                    (HEIGHT, WIDTH)
+                } else {
+                    (WIDTH, HEIGHT)
+                };
+                let case = format!("orientation {orientation} rotation {rotation}");
+                assert_eq!(oriented.dimensions(), expected_size, "{case}");
+
+                let displayed_size = if orientation >= 5 {
+                    (HEIGHT, WIDTH)
+                } else {
+                    (WIDTH, HEIGHT)
+                };
+                let oriented = oriented.to_rgb8();
+                for (x, y, pixel) in img.to_rgb8().enumerate_pixels() {
+                    let displayed = displayed_at(orientation, (x, y));
+                    let (to_x, to_y) = turned_to(rotation, displayed, displayed_size);
+                    assert_eq!(
+                        oriented.get_pixel(to_x, to_y),
+                        pixel,
+                        "{case} pixel ({x}, {y})"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn focus_points_turn_with_the_image() {
+        for orientation in 1..=8 {
+            for rotation in 0..4 {
+                let displayed_size = if orientation >= 5 {
+                    (HEIGHT, WIDTH)
+                } else {
+                    (WIDTH, HEIGHT)
+                };
+                let turned = (orientation >= 5) != (rotation % 2 == 1);
+                let (width, height) = if turned {
+                    (HEIGHT, WIDTH)
+                } else {
+                    (WIDTH, HEIGHT)
+                };
+                for (x, y) in [(0, 0), (2, 0), (1, 1)] {
+                    // the center of a pixel, which ends up at the center of the pixel it is drawn at
+                    let point = FocusPoint {
+                        x: (x as f32 + 0.5) / WIDTH as f32,
+                        y: (y as f32 + 0.5) / HEIGHT as f32,
+                        width: 1.0 / WIDTH as f32,
+                        height: 1.0 / HEIGHT as f32,
+                    };
+                    let oriented = orient_focus_point(point, Some(orientation), rotation);
+                    let displayed = displayed_at(orientation, (x, y));
+                    let (to_x, to_y) = turned_to(rotation, displayed, displayed_size);
+                    let case = format!("orientation {orientation} rotation {rotation} ({x}, {y})");
+                    assert!(
+                        (oriented.x - (to_x as f32 + 0.5) / width as f32).abs() < 1e-6,
+                        "{case}"
+                    );
+                    assert!(
+                        (oriented.y - (to_y as f32 + 0.5) / height as f32).abs() < 1e-6,
+                        "{case}"
+                    );
+                    assert!((oriented.width - 1.0 / width as f32).abs() < 1e-6, "{case}");
+                }
+            }
         }
     }
+
+    #[test]
+    fn small_images_are_not_enlarged() {
+        let img = test_image();
+        let size = UVec2::new(800, 600);
+        assert!(fits_within(&img, size));
+
+        let cached = resize_jpg(&img, size, FilterType::Triangle).unwrap();
+        let cached = image::load_from_memory(&cached).unwrap();
+        assert_eq!(cached.dimensions(), (WIDTH, HEIGHT));
+        assert_eq!(resize_image(&img, size).dimensions(), (WIDTH, HEIGHT));
+    }
+
+    #[test]
+    fn large_images_are_shrunk_to_fit() {
+        let img = DynamicImage::new_rgb8(1600, 900);
+        let size = UVec2::new(800, 600);
+        assert!(!fits_within(&img, size));
+
+        let cached = resize_jpg(&img, size, FilterType::Triangle).unwrap();
+        let cached = image::load_from_memory(&cached).unwrap();
+        assert_eq!(cached.dimensions(), (800, 450));
+        assert!(fits_within(&cached, size));
+    }
+
+    #[test]
+    fn exports_are_only_blocked_when_they_write_to_the_folder() {
+        let e = Some(VirtualKeyCode::E);
+        let one = Some(VirtualKeyCode::Key1);
+        assert!(!writes_to_folder(e, false, false));
+        assert!(writes_to_folder(e, false, true));
+        assert!(!writes_to_folder(one, true, false));
+        assert!(writes_to_folder(one, true, true));
+
+        // rating
+        assert!(writes_to_folder(one, false, false));
+        assert!(writes_to_folder(Some(VirtualKeyCode::Space), false, false));
+        assert!(!writes_to_folder(Some(VirtualKeyCode::Right), false, true));
+    }
+
+    #[test]
+    fn keys_for_the_whole_folder_wait_for_the_listing() {
+        let w = Some(VirtualKeyCode::W);
+        let space = Some(VirtualKeyCode::Space);
+        let one = Some(VirtualKeyCode::Key1);
+        assert!(needs_whole_folder(w, false));
+        assert!(needs_whole_folder(w, true));
+        assert!(needs_whole_folder(space, true));
+        assert!(needs_whole_folder(one, true));
+        assert!(needs_whole_folder(Some(VirtualKeyCode::Delete), false));
+
+        // starring and rating the photo on the screen
+        assert!(!needs_whole_folder(space, false));
+        assert!(!needs_whole_folder(one, false));
+        assert!(!needs_whole_folder(Some(VirtualKeyCode::Right), false));
+    }
 }
@@ -0,0 +1,307 @@
+use std::ops::Range;
+
+/// An autofocus point in the image as it is stored in the file, with coordinates between 0.0 and 1.0
+#[derive(Debug, Clone, Copy)]
+pub struct FocusPoint {
+    /// center of the focus point, 0.0 is the left edge
+    pub x: f32,
+    /// center of the focus point, 0.0 is the top edge
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+// canon maker note tags
+const CANON_AF_INFO2: u16 = 0x0026;
+const CANON_PROCESSING_INFO: u16 = 0x00a0;
+
+// sony maker note tags
+const SONY_FOCUS_LOCATION: u16 = 0x2027;
+
+// fujifilm maker note tags
+const FUJIFILM_HEADER: &[u8] = b"FUJIFILM";
+const FUJIFILM_SATURATION: u16 = 0x1003;
+const FUJIFILM_FILM_MODE: u16 = 0x1401;
+
+/// A single entry in a TIFF style image file directory
+struct Entry {
+    tag: u16,
+    field_type: u16,
+    count: usize,
+    // position of the value within the data
+    value: usize,
+}
+
+/// A TIFF style image file directory found in a maker note.
+/// Offsets within the directory are relative to the start of `data`
+struct Ifd<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+    entries: Vec<Entry>,
+}
+
+impl<'a> Ifd<'a> {
+    fn parse(data: &'a [u8], offset: usize, little_endian: bool) -> Option<Self> {
+        let mut ifd = Self {
+            data,
+            little_endian,
+            entries: vec![],
+        };
+
+        let count = ifd.u16_at(offset)? as usize;
+        for i in 0..count {
+            let entry = offset + 2 + i * 12;
+            let tag = ifd.u16_at(entry)?;
+            let field_type = ifd.u16_at(entry + 2)?;
+            let count = ifd.u32_at(entry + 4)? as usize;
+            let size = type_size(field_type)? * count;
+
+            // small values are stored in place of the offset
+            let value = if size <= 4 {
+                entry + 8
+            } else {
+                ifd.u32_at(entry + 8)? as usize
+            };
+
+            ifd.entries.push(Entry {
+                tag,
+                field_type,
+                count,
+                value,
+            });
+        }
+
+        Some(ifd)
+    }
+
+    fn bytes(&self, range: Range<usize>) -> Option<&[u8]> {
+        self.data.get(range)
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.bytes(offset..offset + 2)?.try_into().ok()?;
+        if self.little_endian {
+            Some(u16::from_le_bytes(bytes))
+        } else {
+            Some(u16::from_be_bytes(bytes))
+        }
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.bytes(offset..offset + 4)?.try_into().ok()?;
+        if self.little_endian {
+            Some(u32::from_le_bytes(bytes))
+        } else {
+            Some(u32::from_be_bytes(bytes))
+        }
+    }
+
+    /// values of a SHORT or SSHORT entry
+    fn u16s(&self, tag: u16) -> Option<Vec<u16>> {
+        let entry = self.entries.iter().find(|x| x.tag == tag)?;
+        if entry.field_type != 3 && entry.field_type != 8 {
+            return None;
+        }
+
+        (0..entry.count)
+            .map(|i| self.u16_at(entry.value + i * 2))
+            .collect()
+    }
+}
+
+/// Picture style (Canon) or film simulation (Fujifilm) chosen in the camera, if recorded.
+/// `exif_data` is the raw exif (TIFF) data and `offset` is where the maker note starts within it
+pub fn picture_style(
+    make: &str,
+    exif_data: &[u8],
+    offset: usize,
+    little_endian: bool,
+) -> Option<String> {
+    let make = make.to_lowercase();
+    let style = if make.starts_with("canon") {
+        canon_picture_style(exif_data, offset, little_endian)?
+    } else if make.starts_with("fujifilm") {
+        fujifilm_film_simulation(exif_data.get(offset..)?)?
+    } else {
+        return None;
+    };
+
+    Some(style.to_owned())
+}
+
+fn canon_picture_style(data: &[u8], offset: usize, little_endian: bool) -> Option<&'static str> {
+    let ifd = Ifd::parse(data, offset, little_endian)?;
+    let info = ifd.u16s(CANON_PROCESSING_INFO)?;
+
+    // the 11th value of the processing info is the picture style
+    let style = match *info.get(10)? {
+        0x01 | 0x81 => "Standard",
+        0x02 | 0x82 => "Portrait",
+        0x03 => "High Saturation",
+        0x04 => "Adobe RGB",
+        0x05 => "Low Saturation",
+        0x06 => "CM Set 1",
+        0x07 => "CM Set 2",
+        0x21 => "User Defined 1",
+        0x22 => "User Defined 2",
+        0x23 => "User Defined 3",
+        0x41 => "PC 1",
+        0x42 => "PC 2",
+        0x43 => "PC 3",
+        0x83 => "Landscape",
+        0x84 => "Neutral",
+        0x85 => "Faithful",
+        0x86 => "Monochrome",
+        0x87 => "Auto",
+        0x88 => "Fine Detail",
+        _ => return None,
+    };
+
+    Some(style)
+}
+
+fn fujifilm_film_simulation(note: &[u8]) -> Option<&'static str> {
+    // fujifilm maker notes start with a header and the offset of the directory, both
+    // are little endian and relative to the start of the maker note regardless of the exif data
+    if !note.starts_with(FUJIFILM_HEADER) {
+        return None;
+    }
+    let offset = u32::from_le_bytes(note.get(8..12)?.try_into().ok()?) as usize;
+    let ifd = Ifd::parse(note, offset, true)?;
+
+    // monochrome simulations are recorded as a saturation setting rather than a film mode
+    let saturation = ifd
+        .u16s(FUJIFILM_SATURATION)
+        .and_then(|x| x.first().copied());
+    let monochrome = match saturation {
+        Some(0x300) => Some("Monochrome"),
+        Some(0x301) => Some("Monochrome + R Filter"),
+        Some(0x302) => Some("Monochrome + Ye Filter"),
+        Some(0x303) => Some("Monochrome + G Filter"),
+        Some(0x310) => Some("Sepia"),
+        Some(0x500) => Some("Acros"),
+        Some(0x501) => Some("Acros + R Filter"),
+        Some(0x502) => Some("Acros + Ye Filter"),
+        Some(0x503) => Some("Acros + G Filter"),
+        _ => None,
+    };
+    if monochrome.is_some() {
+        return monochrome;
+    }
+
+    let simulation = match *ifd.u16s(FUJIFILM_FILM_MODE)?.first()? {
+        0x000 => "Provia / Standard",
+        0x100 => "Studio Portrait",
+        0x110 => "Studio Portrait Enhanced Saturation",
+        0x120 => "Astia / Soft",
+        0x130 => "Studio Portrait Increased Sharpness",
+        0x200 | 0x400 => "Velvia / Vivid",
+        0x300 => "Studio Portrait Ex",
+        0x500 => "Pro Neg. Std",
+        0x501 => "Pro Neg. Hi",
+        0x600 => "Classic Chrome",
+        0x700 => "Eterna",
+        0x800 => "Classic Negative",
+        0x900 => "Eterna Bleach Bypass",
+        0xa00 => "Nostalgic Negative",
+        0xb00 => "Reala Ace",
+        _ => return None,
+    };
+
+    Some(simulation)
+}
+
+fn type_size(field_type: u16) -> Option<usize> {
+    match field_type {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+/// Focus points used to take the photo, if the camera recorded them in a format we understand.
+/// `exif_data` is the raw exif (TIFF) data and `offset` is where the maker note starts within it.
+/// The focus points are not turned by the exif orientation, that is done along with the image
+pub fn focus_points(
+    make: &str,
+    exif_data: &[u8],
+    offset: usize,
+    little_endian: bool,
+) -> Vec<FocusPoint> {
+    let make = make.to_lowercase();
+    let points = if make.starts_with("canon") {
+        canon_focus_points(exif_data, offset, little_endian)
+    } else if make.starts_with("sony") {
+        sony_focus_points(exif_data, offset, little_endian)
+    } else {
+        // other brands store focus information in undocumented or encrypted formats
+        None
+    };
+
+    points.unwrap_or_default()
+}
+
+fn canon_focus_points(data: &[u8], offset: usize, little_endian: bool) -> Option<Vec<FocusPoint>> {
+    // canon maker note offsets are relative to the start of the exif data
+    let ifd = Ifd::parse(data, offset, little_endian)?;
+    let info = ifd.u16s(CANON_AF_INFO2)?;
+
+    // AFInfoSize, AFAreaMode, NumAFPoints, ValidAFPoints, CanonImageWidth, CanonImageHeight,
+    // AFImageWidth, AFImageHeight then per point widths, heights, x and y positions
+    // then a bit mask of the points in focus
+    let num_points = *info.get(2)? as usize;
+    let image_width = *info.get(6)? as f32;
+    let image_height = *info.get(7)? as f32;
+    if image_width == 0.0 || image_height == 0.0 {
+        return None;
+    }
+
+    let widths = info.get(8..8 + num_points)?;
+    let heights = info.get(8 + num_points..8 + num_points * 2)?;
+    let xs = info.get(8 + num_points * 2..8 + num_points * 3)?;
+    let ys = info.get(8 + num_points * 3..8 + num_points * 4)?;
+    let in_focus_start = 8 + num_points * 4;
+    let in_focus = info.get(in_focus_start..in_focus_start + (num_points + 15) / 16)?;
+
+    let points = (0..num_points)
+        .filter(|i| in_focus[*i / 16] & (1 << (*i % 16)) != 0)
+        .map(|i| FocusPoint {
+            // positions are signed and relative to the center of the image with y pointing up
+            x: 0.5 + xs[i] as i16 as f32 / image_width,
+            y: 0.5 - ys[i] as i16 as f32 / image_height,
+            width: widths[i] as f32 / image_width,
+            height: heights[i] as f32 / image_height,
+        })
+        .collect();
+
+    Some(points)
+}
+
+fn sony_focus_points(data: &[u8], offset: usize, little_endian: bool) -> Option<Vec<FocusPoint>> {
+    // most sony maker notes start with a 12 byte header, offsets are relative to the start of the exif data
+    let offset = if data.get(offset..)?.starts_with(b"SONY") {
+        offset + 12
+    } else {
+        offset
+    };
+    let ifd = Ifd::parse(data, offset, little_endian)?;
+
+    // image width, image height and the position of the focus point from the top left
+    let location = ifd.u16s(SONY_FOCUS_LOCATION)?;
+    let (image_width, image_height) = (*location.first()? as f32, *location.get(1)? as f32);
+    let (x, y) = (*location.get(2)? as f32, *location.get(3)? as f32);
+    if image_width == 0.0 || image_height == 0.0 || (x == 0.0 && y == 0.0) {
+        return None;
+    }
+
+    // only the center is recorded so the point is drawn as a small square
+    let size = image_width.min(image_height) / 20.0;
+    Some(vec![FocusPoint {
+        x: x / image_width,
+        y: y / image_height,
+        width: size / image_width,
+        height: size / image_height,
+    }])
+}
@@ -1,58 +1,128 @@
 use std::{fs::File, io::BufReader};
 
-use chrono::NaiveDateTime;
-use exif::{Exif, In, Tag};
+use chrono::{DateTime, FixedOffset};
+use exif::{Exif, In, Tag, Value};
+use image::DynamicImage;
 
+use crate::makernote::{self, FocusPoint};
 use crate::{disk, Error};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct ImageMetadata {
     pub orientation: Option<u32>,
     pub iso: Option<String>,
     pub model: Option<String>,
+    /// not recorded for many older or manual lenses
+    pub lens_make: Option<String>,
+    pub lens_model: Option<String>,
     pub exposure_time: Option<String>,
     pub f_number: Option<String>,
+    /// as the camera recorded it, for display
     pub date_time: Option<String>,
+    /// when the photo was taken including subseconds, in the time zone the camera recorded
+    pub capture_time: Option<DateTime<FixedOffset>>,
     pub focal_length: Option<String>,
+    pub white_balance: Option<String>,
+    pub metering_mode: Option<String>,
+    /// whether the flash fired and how it was set
+    pub flash: Option<String>,
+    /// picture style (Canon) or film simulation (Fujifilm) from the maker note
+    pub picture_style: Option<String>,
+    /// pixels per resolution unit
+    pub x_resolution: Option<f64>,
+    /// 2 for inches, 3 for centimeters
+    pub resolution_unit: Option<u32>,
+    /// decimal degrees, negative south of the equator
+    pub gps_latitude: Option<f64>,
+    /// decimal degrees, negative west of Greenwich
+    pub gps_longitude: Option<f64>,
 }
 
-impl ImageMetadata {
-    pub fn try_get_timestamp_from_date_time(&self) -> i64 {
-        match self.date_time.as_deref() {
-            Some(date_time) => string_to_unix_timestamp(date_time).unwrap_or(0),
-            _ => 0,
-        }
-    }
+/// The camera settings a photo was taken with as numbers, so that they can be counted across a shoot
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ShotSettings {
+    pub iso: Option<u32>,
+    pub f_number: Option<f64>,
+    /// millimeters
+    pub focal_length: Option<f64>,
 }
 
-fn string_to_unix_timestamp(s: &str) -> Result<i64, Error> {
-    match NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
-        Ok(date_time) => Ok(date_time.timestamp()),
-        Err(e) => Err(Error::ExifDateTime((s.to_owned(), e))),
+/// Rating and color label written to an xmp sidecar by another tool such as Lightroom or darktable
+#[derive(Debug, Default)]
+pub struct XmpSidecar {
+    /// 1 to 5 stars
+    pub rating: Option<u8>,
+    /// 1 red, 2 yellow or 3 green
+    pub color_label: Option<u8>,
+}
+
+impl ImageMetadata {
+    /// physical size of a pixel if the camera or scanner recorded it. Cameras that do not know it write
+    /// the exif default of 72 dpi, which is treated as unknown
+    pub fn pixels_per_mm(&self) -> Option<f64> {
+        let x_resolution = self.x_resolution.filter(|x| *x > 0.0)?;
+        // the resolution unit defaults to inches
+        match self.resolution_unit.unwrap_or(2) {
+            2 if x_resolution == 72.0 => None,
+            2 => Some(x_resolution / 25.4),
+            3 => Some(x_resolution / 10.0),
+            _ => None,
+        }
     }
 }
 
-pub fn _get_date_time(path: &str, name: &str) -> Result<NaiveDateTime, Error> {
-    let file_name = disk::get_full_path(path, name);
+/// `get_capture_time` of a photo that has not been read yet
+pub fn read_capture_time(path: &str, name: &str) -> Result<DateTime<FixedOffset>, Error> {
+    let file_name = disk::get_full_path(path, name)?;
     let file = File::open(file_name)?;
     let mut reader = BufReader::new(&file);
     let exif = exif::Reader::new().read_from_container(&mut reader)?;
+    get_capture_time(&exif)
+}
+
+/// When the shutter was pressed. DateTimeOriginal is used before DateTime because editing software changes
+/// the latter, each with the subseconds and offset from UTC recorded alongside it. Subseconds order photos
+/// taken within the same second of a burst. Cameras that do not record an offset are taken to be on UTC
+fn get_capture_time(exif: &Exif) -> Result<DateTime<FixedOffset>, Error> {
+    let (date_time, sub_sec, offset) = [
+        (
+            Tag::DateTimeOriginal,
+            Tag::SubSecTimeOriginal,
+            Tag::OffsetTimeOriginal,
+        ),
+        (Tag::DateTime, Tag::SubSecTime, Tag::OffsetTime),
+    ]
+    .into_iter()
+    .find_map(|(date_time, sub_sec, offset)| {
+        Some((get_exif_string(exif, date_time)?, sub_sec, offset))
+    })
+    .ok_or(Error::NoExifDateTime)?;
 
-    match exif.get_field(Tag::DateTime, In::PRIMARY) {
-        Some(field) => {
-            let s = field.display_value().with_unit(&exif).to_string();
+    // subseconds are the digits after the decimal point, unknown values are left blank
+    let sub_sec = get_exif_string(exif, sub_sec)
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty() && x.chars().all(|c| c.is_ascii_digit()))
+        .unwrap_or_else(|| "0".to_owned());
+    let offset = get_exif_string(exif, offset)
+        .filter(|x| is_utc_offset(x))
+        .unwrap_or_else(|| "+00:00".to_owned());
 
-            match NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S") {
-                Ok(date_time) => Ok(date_time),
-                Err(e) => Err(Error::ExifDateTime((s, e))),
-            }
-        }
-        None => Err(Error::NoExifDateTime),
-    }
+    let s = format!("{date_time}.{sub_sec} {offset}");
+    DateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S%.f %:z")
+        .map_err(|e| Error::ExifDateTime((s, e)))
+}
+
+/// e.g. "+09:00", an unknown offset is written as "   :  "
+fn is_utc_offset(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    bytes.len() == 6
+        && matches!(bytes[0], b'+' | b'-')
+        && bytes[3] == b':'
+        && [1, 2, 4, 5].iter().all(|i| bytes[*i].is_ascii_digit())
 }
 
 pub fn get_metadata(path: &str, name: &str) -> Result<ImageMetadata, Error> {
-    let file_name = disk::get_full_path(path, name);
+    let file_name = disk::get_full_path(path, name)?;
     let file = File::open(file_name)?;
     let mut reader = BufReader::new(&file);
     let exif = exif::Reader::new().read_from_container(&mut reader)?;
@@ -65,22 +135,162 @@ pub fn get_metadata(path: &str, name: &str) -> Result<ImageMetadata, Error> {
 
     let iso = get_exif_string(&exif, Tag::PhotographicSensitivity);
     let model = get_exif_string(&exif, Tag::Model);
+    let lens_make = get_exif_string(&exif, Tag::LensMake);
+    let lens_model = get_exif_string(&exif, Tag::LensModel);
     let exposure_time = get_exif_string(&exif, Tag::ExposureTime);
     let f_number = get_exif_string(&exif, Tag::FNumber);
     let date_time = get_exif_string(&exif, Tag::DateTime);
+    let capture_time = get_capture_time(&exif).ok();
     let focal_length = get_exif_string(&exif, Tag::FocalLength);
+    let white_balance = get_exif_enum(&exif, Tag::WhiteBalance, white_balance_name);
+    let metering_mode = get_exif_enum(&exif, Tag::MeteringMode, metering_mode_name);
+    let flash = get_exif_uint(&exif, Tag::Flash).map(describe_flash);
+    let make = get_exif_string(&exif, Tag::Make).unwrap_or_default();
+    let picture_style = match exif
+        .get_field(Tag::MakerNote, In::PRIMARY)
+        .map(|x| &x.value)
+    {
+        Some(Value::Undefined(_, offset)) => {
+            makernote::picture_style(&make, exif.buf(), *offset as usize, exif.little_endian())
+        }
+        _ => None,
+    };
+    let x_resolution = get_exif_f64(&exif, Tag::XResolution);
+    let resolution_unit = exif
+        .get_field(Tag::ResolutionUnit, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0));
+    let gps_latitude = get_exif_gps(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S');
+    let gps_longitude = get_exif_gps(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W');
 
     Ok(ImageMetadata {
         orientation,
         iso,
         model,
+        lens_make,
+        lens_model,
         exposure_time,
         f_number,
         date_time,
+        capture_time,
         focal_length,
+        white_balance,
+        metering_mode,
+        flash,
+        picture_style,
+        x_resolution,
+        resolution_unit,
+        gps_latitude,
+        gps_longitude,
+    })
+}
+
+/// the ISO, aperture and focal length, without reading the rest of the metadata
+pub fn get_shot_settings(path: &str, name: &str) -> Result<ShotSettings, Error> {
+    let file_name = disk::get_full_path(path, name)?;
+    let file = File::open(file_name)?;
+    let mut reader = BufReader::new(&file);
+    let exif = exif::Reader::new().read_from_container(&mut reader)?;
+
+    Ok(ShotSettings {
+        iso: get_exif_uint(&exif, Tag::PhotographicSensitivity),
+        f_number: get_exif_f64(&exif, Tag::FNumber),
+        focal_length: get_exif_f64(&exif, Tag::FocalLength),
     })
 }
 
+/// every exif field in the file as (tag, value) pairs, in the order they are stored
+pub fn get_all_fields(path: &str, name: &str) -> Result<Vec<(String, String)>, Error> {
+    let file_name = disk::get_full_path(path, name)?;
+    let file = File::open(file_name)?;
+    let mut reader = BufReader::new(&file);
+    let exif = exif::Reader::new().read_from_container(&mut reader)?;
+
+    let fields = exif
+        .fields()
+        .map(|field| {
+            let tag = match field.ifd_num {
+                In::PRIMARY => field.tag.to_string(),
+                ifd => format!("{} ({})", field.tag, ifd),
+            };
+            let value = field.display_value().with_unit(&exif).to_string();
+            (tag, value)
+        })
+        .collect();
+
+    Ok(fields)
+}
+
+/// autofocus points recorded by the camera, empty if there are none or the format is not supported,
+/// along with the exif orientation that turns them the way the photo is displayed
+pub fn get_focus_points(path: &str, name: &str) -> Result<(Vec<FocusPoint>, Option<u32>), Error> {
+    let file_name = disk::get_full_path(path, name)?;
+    let file = File::open(file_name)?;
+    let mut reader = BufReader::new(&file);
+    let exif = exif::Reader::new().read_from_container(&mut reader)?;
+
+    let make = get_exif_string(&exif, Tag::Make).unwrap_or_default();
+    let orientation = exif
+        .get_field(Tag::Orientation, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0));
+
+    let points = match exif
+        .get_field(Tag::MakerNote, In::PRIMARY)
+        .map(|x| &x.value)
+    {
+        Some(Value::Undefined(_, offset)) => {
+            makernote::focus_points(&make, exif.buf(), *offset as usize, exif.little_endian())
+        }
+        _ => vec![],
+    };
+    Ok((points, orientation))
+}
+
+/// width and height of the image as displayed, taking the exif orientation into account
+pub fn get_dimensions(
+    path: &str,
+    name: &str,
+    orientation: Option<u32>,
+) -> Result<(u32, u32), Error> {
+    let file_name = disk::get_full_path(path, name)?;
+    let (width, height) = image::image_dimensions(file_name)?;
+    match orientation {
+        Some(5..=8) => Ok((height, width)),
+        _ => Ok((width, height)),
+    }
+}
+
+fn get_exif_f64(exif: &Exif, tag: Tag) -> Option<f64> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    match &field.value {
+        Value::Rational(x) => x.first().map(|x| x.to_f64()),
+        Value::SRational(x) => x.first().map(|x| x.to_f64()),
+        _ => field.value.get_uint(0).map(|x| x as f64),
+    }
+}
+
+/// degrees, minutes and seconds as decimal degrees, negative when the reference is `negative_ref` (S or W)
+fn get_exif_gps(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: u8) -> Option<f64> {
+    let degrees = match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(x) => x
+            .iter()
+            .zip([1.0, 60.0, 3600.0])
+            .map(|(x, divisor)| x.to_f64() / divisor)
+            .sum::<f64>(),
+        _ => return None,
+    };
+
+    let is_negative = match exif.get_field(ref_tag, In::PRIMARY).map(|x| &x.value) {
+        Some(Value::Ascii(x)) => x.first().and_then(|x| x.first()) == Some(&negative_ref),
+        _ => false,
+    };
+
+    if is_negative {
+        Some(-degrees)
+    } else {
+        Some(degrees)
+    }
+}
+
 fn get_exif_string(exif: &Exif, tag: Tag) -> Option<String> {
     exif.get_field(tag, In::PRIMARY).map(|field| {
         field
@@ -90,3 +300,149 @@ fn get_exif_string(exif: &Exif, tag: Tag) -> Option<String> {
             .replace('\"', "")
     })
 }
+
+/// The rating and label in an xmp sidecar. These are simple properties, written either as attributes of
+/// the description or as elements inside it, so they are found by name rather than parsing the xml
+pub fn read_xmp_sidecar(path: &str, name: &str) -> Result<XmpSidecar, Error> {
+    let file_name = disk::get_full_path(path, name)?;
+    let text = std::fs::read_to_string(file_name)?;
+
+    let rating = get_xmp_property(&text, "xmp:Rating")
+        .and_then(|x| x.parse::<i32>().ok())
+        // rejected photos are rated -1 by Lightroom
+        .filter(|x| (1..=5).contains(x))
+        .map(|x| x as u8);
+    let color_label = get_xmp_property(&text, "xmp:Label").and_then(color_label_from_name);
+
+    Ok(XmpSidecar {
+        rating,
+        color_label,
+    })
+}
+
+fn get_xmp_property<'a>(text: &'a str, property: &str) -> Option<&'a str> {
+    let attribute = format!("{property}=");
+    if let Some(start) = text.find(&attribute) {
+        let value = &text[start + attribute.len()..];
+        let quote = value.chars().next()?;
+        return value[quote.len_utf8()..].split(quote).next();
+    }
+
+    let element = format!("<{property}>");
+    let start = text.find(&element)? + element.len();
+    text[start..].split('<').next().map(str::trim)
+}
+
+/// other tools have more colors but only red, yellow and green can be shown here
+fn color_label_from_name(name: &str) -> Option<u8> {
+    match name.to_lowercase().as_str() {
+        "red" => Some(1),
+        "yellow" => Some(2),
+        "green" => Some(3),
+        _ => None,
+    }
+}
+
+fn get_exif_uint(exif: &Exif, tag: Tag) -> Option<u32> {
+    exif.get_field(tag, In::PRIMARY)?.value.get_uint(0)
+}
+
+/// a tag whose value is one of a list of numbers, named by `name` so that it reads naturally
+fn get_exif_enum(exif: &Exif, tag: Tag, name: fn(u32) -> Option<&'static str>) -> Option<String> {
+    let value = get_exif_uint(exif, tag)?;
+    Some(match name(value) {
+        Some(name) => name.to_owned(),
+        None => format!("Unknown ({value})"),
+    })
+}
+
+fn white_balance_name(value: u32) -> Option<&'static str> {
+    match value {
+        0 => Some("Auto"),
+        1 => Some("Manual"),
+        _ => None,
+    }
+}
+
+fn metering_mode_name(value: u32) -> Option<&'static str> {
+    match value {
+        0 => Some("Unknown"),
+        1 => Some("Average"),
+        2 => Some("Center-weighted average"),
+        3 => Some("Spot"),
+        4 => Some("Multi-spot"),
+        5 => Some("Pattern"),
+        6 => Some("Partial"),
+        255 => Some("Other"),
+        _ => None,
+    }
+}
+
+/// The flash tag is a set of bits: whether it fired, whether the strobe return was detected,
+/// the flash mode, whether there is a flash at all and red-eye reduction
+fn describe_flash(value: u32) -> String {
+    if value & 0x20 != 0 {
+        return "No flash".to_owned();
+    }
+
+    let mut description = if value & 0x01 != 0 {
+        String::from("Fired")
+    } else {
+        String::from("Did not fire")
+    };
+    match (value >> 3) & 0x03 {
+        1 => description.push_str(", compulsory"),
+        2 => description.push_str(", suppressed"),
+        3 => description.push_str(", auto"),
+        _ => {}
+    }
+    if value & 0x40 != 0 {
+        description.push_str(", red-eye reduction");
+    }
+
+    description
+}
+
+/// Average hash of an image: one bit for each cell of an 8x8 grid, set when the cell is brighter than average.
+/// Photos that look alike have hashes that differ in only a few bits, whatever their size or compression
+pub fn perceptual_hash(img: &DynamicImage) -> u64 {
+    let small = img
+        .resize_exact(8, 8, image::imageops::FilterType::Triangle)
+        .to_luma8();
+    let mean = small.pixels().map(|x| x.0[0] as u32).sum::<u32>() / 64;
+
+    small
+        .pixels()
+        .enumerate()
+        .filter(|(_, x)| x.0[0] as u32 > mean)
+        .fold(0, |hash, (bit, _)| hash | 1 << bit)
+}
+
+/// A single number describing how bright an image is, higher is brighter.
+/// It is the mean luminance (0.0 to 1.0) plus the fraction of clipped highlights
+/// minus the fraction of clipped shadows so that clipping pushes an image to the extremes
+pub fn exposure_score(img: &DynamicImage) -> f64 {
+    // a small version of the image is plenty for statistics
+    let luma = img.thumbnail(256, 256).to_luma8();
+    let num_pixels = luma.pixels().len();
+    if num_pixels == 0 {
+        return 0.0;
+    }
+
+    let mut sum = 0u64;
+    let mut highlights = 0usize;
+    let mut shadows = 0usize;
+    for pixel in luma.pixels() {
+        let value = pixel.0[0];
+        sum += value as u64;
+        if value >= 250 {
+            highlights += 1;
+        } else if value <= 5 {
+            shadows += 1;
+        }
+    }
+
+    let num_pixels = num_pixels as f64;
+    let mean = sum as f64 / num_pixels / 255.0;
+    mean + highlights as f64 / num_pixels - shadows as f64 / num_pixels
+}
@@ -0,0 +1,86 @@
+use std::fs;
+
+use crate::config::PlaylistFormat;
+use crate::{disk, Error, ImageNamePair};
+
+const PLAYLIST_FILE_NAME: &str = "picks";
+
+/// Write the full paths of the images to a playlist file in the folder so that other programs
+/// can use the selection without the files being copied. Returns the name of the file written
+pub fn write(
+    path: &str,
+    images: &[&ImageNamePair],
+    format: PlaylistFormat,
+) -> Result<String, Error> {
+    let entries = images
+        .iter()
+        .map(|image| {
+            // proofs are listed as the full resolution original they were made from
+            let full_path = match &image.original {
+                Some(original) => disk::get_full_path(&original.path, &original.jpg_file_name)?,
+                None => disk::get_full_path(path, &image.jpg_file_name)?,
+            };
+            Ok((full_path, *image))
+        })
+        .collect::<Result<Vec<(String, &ImageNamePair)>, Error>>()?;
+
+    let (extension, contents) = match format {
+        PlaylistFormat::Paths => ("txt", paths(&entries)),
+        PlaylistFormat::M3u => ("m3u", format!("#EXTM3U\n{}", paths(&entries))),
+        PlaylistFormat::Csv => ("csv", csv(&entries)),
+        PlaylistFormat::Json => ("json", json(&entries)),
+    };
+
+    let file_name = disk::get_full_path(path, &format!("{PLAYLIST_FILE_NAME}.{extension}"))?;
+    fs::write(&file_name, contents)?;
+    Ok(file_name)
+}
+
+fn paths(entries: &[(String, &ImageNamePair)]) -> String {
+    entries
+        .iter()
+        .map(|(full_path, _)| format!("{full_path}\n"))
+        .collect()
+}
+
+fn csv(entries: &[(String, &ImageNamePair)]) -> String {
+    let mut csv = String::from("path,starred,rating,protected\n");
+    for (full_path, image) in entries {
+        // quotes in a quoted csv field are escaped by doubling them
+        let full_path = full_path.replace('"', "\"\"");
+        csv.push_str(&format!(
+            "\"{full_path}\",{},{},{}\n",
+            image.is_starred, image.rating, image.is_protected
+        ));
+    }
+    csv
+}
+
+fn json(entries: &[(String, &ImageNamePair)]) -> String {
+    let items: Vec<String> = entries
+        .iter()
+        .map(|(full_path, image)| {
+            format!(
+                "  {{ \"path\": \"{}\", \"starred\": {}, \"rating\": {}, \"protected\": {} }}",
+                json_escape(full_path),
+                image.is_starred,
+                image.rating,
+                image.is_protected
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", items.join(",\n"))
+}
+
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
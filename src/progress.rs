@@ -0,0 +1,80 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Progress of a batch of background work, shared between the worker threads and the window.
+/// Finished items are counted rather than derived from an index so that the percentage
+/// never goes backwards, even when items complete out of order
+#[derive(Debug)]
+pub struct Progress {
+    /// weight given to the latest item when averaging the time per item, between 0.0 and 1.0
+    smoothing: f64,
+    batch: Mutex<Batch>,
+}
+
+#[derive(Debug)]
+struct Batch {
+    total: usize,
+    completed: usize,
+    last_completed: Instant,
+    /// smoothed seconds per item, none until the first item has completed
+    seconds_per_item: Option<f64>,
+}
+
+impl Progress {
+    pub fn new(smoothing: f64) -> Self {
+        Self {
+            smoothing: smoothing.clamp(0.0, 1.0),
+            batch: Mutex::new(Batch {
+                total: 0,
+                completed: 0,
+                last_completed: Instant::now(),
+                seconds_per_item: None,
+            }),
+        }
+    }
+
+    /// start counting a new batch of work, this is the only way the percentage can go down. The time per
+    /// item of the previous batch is forgotten as its work may have been quite different
+    pub fn start(&self, total: usize) {
+        let mut batch = self.batch.lock().unwrap();
+        batch.total = total;
+        batch.completed = 0;
+        batch.last_completed = Instant::now();
+        batch.seconds_per_item = None;
+    }
+
+    pub fn complete_one(&self) {
+        let mut batch = self.batch.lock().unwrap();
+        if batch.completed >= batch.total {
+            return;
+        }
+
+        let now = Instant::now();
+        let seconds = now.duration_since(batch.last_completed).as_secs_f64();
+        batch.seconds_per_item = Some(match batch.seconds_per_item {
+            Some(average) => average + self.smoothing * (seconds - average),
+            None => seconds,
+        });
+        batch.last_completed = now;
+        batch.completed += 1;
+    }
+
+    /// percentage of the current batch that is done, 100 when there is nothing to do
+    pub fn percentage(&self) -> i32 {
+        let batch = self.batch.lock().unwrap();
+        if batch.total == 0 {
+            100
+        } else {
+            // round down so that 100 percent is only shown once everything is done
+            (100 * batch.completed / batch.total) as i32
+        }
+    }
+
+    /// estimated time until the current batch is done
+    pub fn remaining(&self) -> Option<Duration> {
+        let batch = self.batch.lock().unwrap();
+        let seconds_per_item = batch.seconds_per_item?;
+        let remaining = (batch.total - batch.completed) as f64;
+        Some(Duration::from_secs_f64(seconds_per_item * remaining))
+    }
+}
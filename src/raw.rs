@@ -0,0 +1,56 @@
+use std::fs;
+
+use image::DynamicImage;
+
+use crate::Error;
+
+/// Decode the largest jpeg preview embedded in a raw file. Most cameras store a full size (or nearly full size)
+/// jpeg alongside the sensor data which is far quicker to show than developing the raw file ourselves
+pub fn load_preview(file_name: &str) -> Result<DynamicImage, Error> {
+    let data = fs::read(file_name)?;
+    let start = largest_preview(&data).ok_or_else(|| Error::NoRawPreview(file_name.to_owned()))?;
+
+    // the decoder stops at the end of the jpeg so the rest of the raw file is ignored
+    let img = image::load_from_memory_with_format(&data[start..], image::ImageFormat::Jpeg)?;
+    Ok(img)
+}
+
+/// offset of the embedded jpeg with the most pixels
+fn largest_preview(data: &[u8]) -> Option<usize> {
+    data.windows(3)
+        .enumerate()
+        .filter(|(_, x)| *x == [0xFF, 0xD8, 0xFF])
+        .filter_map(|(start, _)| {
+            let (width, height) = jpeg_dimensions(&data[start..])?;
+            Some((start, width as u64 * height as u64))
+        })
+        .max_by_key(|(_, pixels)| *pixels)
+        .map(|(start, _)| start)
+}
+
+/// Width and height from the frame header of a baseline or progressive jpeg at the start of `data`.
+/// None for anything else, such as the lossless jpeg some cameras use for the sensor data
+fn jpeg_dimensions(data: &[u8]) -> Option<(u16, u16)> {
+    // skip the start of image marker
+    let mut position = 2;
+    loop {
+        let segment = data.get(position..position + 4)?;
+        if segment[0] != 0xFF {
+            return None;
+        }
+
+        let length = u16::from_be_bytes([segment[2], segment[3]]) as usize;
+        match segment[1] {
+            // baseline, extended and progressive frames
+            0xC0..=0xC2 => {
+                let frame = data.get(position + 5..position + 9)?;
+                let height = u16::from_be_bytes([frame[0], frame[1]]);
+                let width = u16::from_be_bytes([frame[2], frame[3]]);
+                return Some((width, height)).filter(|(width, height)| *width > 0 && *height > 0);
+            }
+            // other kinds of frame, or the start of the image data without a frame we can decode
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA => return None,
+            _ => position += 2 + length,
+        }
+    }
+}
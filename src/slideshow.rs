@@ -0,0 +1,53 @@
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use speedy2d::window::UserEventSender;
+
+use crate::ticker::spawn_ticker;
+
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+// the timer may wake the window slightly before the interval has passed
+const TOLERANCE: Duration = Duration::from_millis(100);
+
+/// Moves to the next photo every `interval`. The window is event driven so a thread wakes it up
+/// with a user event, the thread stops when this is dropped
+pub struct Slideshow {
+    interval: Duration,
+    last_advanced: Instant,
+    interval_tx: Sender<Duration>,
+}
+
+impl Slideshow {
+    pub fn start(interval: Duration, user_event_sender: Arc<Mutex<UserEventSender<()>>>) -> Self {
+        let interval = interval.max(MIN_INTERVAL);
+        let interval_tx = spawn_ticker(interval, user_event_sender);
+
+        Self {
+            interval,
+            last_advanced: Instant::now(),
+            interval_tx,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// longer or shorter by `seconds`, never less than a second
+    pub fn adjust(&mut self, seconds: i64) {
+        let interval = self.interval.as_secs() as i64 + seconds;
+        self.interval = Duration::from_secs(interval.max(0) as u64).max(MIN_INTERVAL);
+        // the thread has gone if the window is closing
+        let _ = self.interval_tx.send(self.interval);
+    }
+
+    /// user events are also sent by other threads so check that the photo has been shown long enough
+    pub fn is_due(&self) -> bool {
+        self.last_advanced.elapsed() + TOLERANCE >= self.interval
+    }
+
+    pub fn advanced(&mut self) {
+        self.last_advanced = Instant::now();
+    }
+}
@@ -0,0 +1,50 @@
+use std::f32::consts::TAU;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use speedy2d::window::UserEventSender;
+
+use crate::ticker::spawn_ticker;
+
+// how often the window is redrawn while the hourglass is on the screen
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+const TURNS_PER_SECOND: f32 = 0.5;
+
+/// Turns the hourglass so that a long load or export does not look frozen. The window is event driven so
+/// a thread wakes it up with a user event for as long as the hourglass is being drawn
+pub struct Spinner {
+    user_event_sender: Arc<Mutex<UserEventSender<()>>>,
+    /// when the hourglass appeared, and the timer thread which stops when this is dropped
+    turning: Option<(Instant, Sender<Duration>)>,
+    /// the hourglass has been drawn since the last `end_frame`
+    drawn: bool,
+}
+
+impl Spinner {
+    pub fn new(user_event_sender: Arc<Mutex<UserEventSender<()>>>) -> Self {
+        Self {
+            user_event_sender,
+            turning: None,
+            drawn: false,
+        }
+    }
+
+    /// radians to turn the hourglass by in the frame being drawn, starting the timer if it is not running
+    pub fn angle(&mut self) -> f32 {
+        self.drawn = true;
+        let (started, _) = self.turning.get_or_insert_with(|| {
+            let ticker = spawn_ticker(FRAME_INTERVAL, self.user_event_sender.clone());
+            (Instant::now(), ticker)
+        });
+        (started.elapsed().as_secs_f32() * TURNS_PER_SECOND * TAU) % TAU
+    }
+
+    /// called after each frame, the timer stops once a frame is drawn without the hourglass
+    pub fn end_frame(&mut self) {
+        if !self.drawn {
+            self.turning = None;
+        }
+        self.drawn = false;
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+
+use speedy2d::dimen::UVec2;
+use speedy2d::image::ImageHandle;
+
+/// Images recently uploaded to the GPU so that going back to one of them does not upload it again.
+/// The least recently used image is dropped when the cache is full
+pub struct TextureCache {
+    capacity: usize,
+    // most recently used at the back
+    entries: VecDeque<(String, UVec2, ImageHandle)>,
+}
+
+impl TextureCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// the texture for an image resized to fit the screen resolution `size`
+    pub fn get(&mut self, name: &str, size: UVec2) -> Option<ImageHandle> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(x, s, _)| x == name && *s == size)?;
+        let entry = self.entries.remove(index)?;
+        let handle = entry.2.clone();
+        self.entries.push_back(entry);
+        Some(handle)
+    }
+
+    /// unlike `get` this does not count as a use
+    pub fn contains(&self, name: &str, size: UVec2) -> bool {
+        self.entries.iter().any(|(x, s, _)| x == name && *s == size)
+    }
+
+    pub fn insert(&mut self, name: &str, size: UVec2, handle: ImageHandle) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.entries.retain(|(x, s, _)| !(x == name && *s == size));
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((name.to_owned(), size, handle));
+    }
+
+    /// forget every resolution of an image that has changed
+    pub fn remove(&mut self, name: &str) {
+        self.entries.retain(|(x, _, _)| x != name);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use speedy2d::window::UserEventSender;
+
+/// The window is event driven so anything animated wakes it up from a thread. This thread sends a user event
+/// every `interval`, a new interval sent to it is used from then on and it stops when the sender is dropped
+pub fn spawn_ticker(
+    interval: Duration,
+    user_event_sender: Arc<Mutex<UserEventSender<()>>>,
+) -> Sender<Duration> {
+    let (interval_tx, interval_rx) = channel();
+    thread::spawn(move || {
+        let mut timeout = interval;
+        loop {
+            match interval_rx.recv_timeout(timeout) {
+                Ok(interval) => timeout = interval,
+                Err(RecvTimeoutError::Timeout) => {
+                    let locked = user_event_sender.lock().unwrap();
+                    locked.send_event(()).unwrap();
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    });
+    interval_tx
+}
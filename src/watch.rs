@@ -0,0 +1,77 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use log::{info, warn};
+use speedy2d::window::UserEventSender;
+
+use crate::disk;
+
+// how often the folder is listed to look for new photos
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// List the folder every few seconds and send the names of photos that have been added since it was opened,
+/// for example by a tethered camera. A photo is only sent once its size and modification time are the same in
+/// two listings in a row so that a file that is still being written is not shown early. Returns when the window
+/// has closed
+pub fn watch_folder(
+    path: String,
+    tx: Sender<Vec<String>>,
+    user_event_sender: Arc<Mutex<UserEventSender<()>>>,
+) {
+    let mut known: HashSet<String> = match disk::list_photo_names(&path) {
+        Ok(names) => names.into_iter().collect(),
+        Err(e) => {
+            warn!("Not watching {path} for new photos: {e:?}");
+            return;
+        }
+    };
+
+    // photos found by the last listing that have not been sent yet, with their size and modification time
+    let mut added: HashMap<String, (u64, SystemTime)> = HashMap::new();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let names = match disk::list_photo_names(&path) {
+            Ok(names) => names,
+            Err(e) => {
+                warn!("Unable to look for new photos in {path}: {e:?}");
+                continue;
+            }
+        };
+
+        // a file that cannot be read yet is looked at again next time
+        let new: HashMap<String, (u64, SystemTime)> = names
+            .into_iter()
+            .filter(|x| !known.contains(x))
+            .filter_map(|name| {
+                let metadata = disk::get_full_path(&path, &name)
+                    .ok()
+                    .and_then(|x| fs::metadata(x).ok())?;
+                let modified = metadata.modified().ok()?;
+                Some((name, (metadata.len(), modified)))
+            })
+            .collect();
+        let ready: Vec<String> = new
+            .iter()
+            .filter(|(name, state)| added.get(*name) == Some(*state))
+            .map(|(name, _)| name.clone())
+            .collect();
+        added = new;
+
+        if ready.is_empty() {
+            continue;
+        }
+
+        info!("New photos in the folder: {ready:?}");
+        known.extend(ready.iter().cloned());
+        if tx.send(ready).is_err() {
+            return;
+        }
+
+        let locked = user_event_sender.lock().unwrap();
+        locked.send_event(()).unwrap();
+    }
+}